@@ -0,0 +1,38 @@
+//! Compares building a chunk-heavy `MultiArena` on `HeapStorage` and `BumpHeapStorage`.
+//!
+//! Run with `cargo bench --bench bump_heap_storage`
+
+use chunky::*;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 20;
+const ITEMS: usize = 20_000;
+
+/// Pushes items of many different sizes into small chunks, so most pushes create a chunk
+fn build_multi_arena(storage: Rc<dyn ChunkStorage>) {
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, storage);
+    for index in 0..ITEMS {
+        let (item, _) = arena.push(8 + (index * 37) % 200);
+        unsafe { *item = index as u8 };
+    }
+}
+
+fn time(name: &str, make_storage: impl Fn() -> Rc<dyn ChunkStorage>) -> Duration {
+    let mut total = Duration::default();
+    for _ in 0..ROUNDS {
+        let storage = make_storage();
+        let start = Instant::now();
+        build_multi_arena(storage);
+        total += start.elapsed();
+    }
+    let average = total / ROUNDS;
+    println!("{:<16} {:>10.3?} per build of {} items", name, average, ITEMS);
+    average
+}
+
+fn main() {
+    let heap = time("HeapStorage", || Rc::new(HeapStorage::new()));
+    let bump = time("BumpHeapStorage", || Rc::new(BumpHeapStorage::new(1 << 20)));
+    println!("speedup: {:.2}x", heap.as_secs_f64() / bump.as_secs_f64());
+}
@@ -0,0 +1,46 @@
+//! Compares churning through the chunks of a `Queue` on `HeapStorage` with and without pooling.
+//!
+//! Run with `cargo bench --bench heap_storage_pool`
+
+use chunky::*;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 20;
+const ITEMS: usize = 100_000;
+
+/// Keeps a few items in flight while pushing many through small chunks,
+/// so chunks are constantly created and forgotten
+fn churn_queue(storage: Rc<dyn ChunkStorage>) {
+    let mut queue = Queue::new(&Ident::from("q"), 4096, storage);
+    for index in 0..ITEMS {
+        unsafe {
+            *(queue.enqueue(64) as *mut u64) = index as u64;
+            if queue.len() > 16 {
+                queue.dequeue();
+            }
+            queue.drop_old_chunks();
+        }
+    }
+}
+
+fn time(name: &str, make_storage: impl Fn() -> Rc<dyn ChunkStorage>) -> Duration {
+    let mut total = Duration::default();
+    for _ in 0..ROUNDS {
+        let storage = make_storage();
+        let start = Instant::now();
+        churn_queue(storage);
+        total += start.elapsed();
+    }
+    let average = total / ROUNDS;
+    println!("{:<20} {:>10.3?} per churn of {} items", name, average, ITEMS);
+    average
+}
+
+fn main() {
+    let unpooled = time("zeroed, unpooled", || Rc::new(HeapStorage::builder().zero_on_create(true).build()));
+    let pooled = time("zeroed, pooled", || {
+        Rc::new(HeapStorage::builder().zero_on_create(true).pool_limit(8).build())
+    });
+    println!("speedup: {:.2}x", unpooled.as_secs_f64() / pooled.as_secs_f64());
+}
@@ -0,0 +1,137 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::live_chunks::LiveChunks;
+use crate::shared::SendableStorage;
+use std::sync::{Arc, Mutex};
+use memmap::MmapMut;
+
+/// A `ChunkStorage` that allocates transient chunks as anonymous (not file-backed) memory maps.
+///
+/// Like `HeapStorage`, chunks can't be loaded again once dropped, but like `MmapStorage`,
+/// they are page-aligned memory managed by the OS, only backed by physical memory when touched,
+/// whose pages can be handed back to the OS with `discard`.
+#[derive(Default)]
+pub struct AnonMmapStorage {
+    live_chunks: Arc<Mutex<LiveChunks>>,
+}
+
+struct AnonMmapStorageHandle {
+    _mmap: MmapMut,
+    address: usize,
+    ident: Ident,
+    live_chunks: Arc<Mutex<LiveChunks>>,
+}
+
+impl Drop for AnonMmapStorageHandle {
+    fn drop(&mut self) {
+        self.live_chunks.lock().unwrap().remove(&self.ident, self.address);
+    }
+}
+
+impl AnonMmapStorage {
+    /// Create a new AnonMmapStorage
+    pub fn new() -> AnonMmapStorage {
+        AnonMmapStorage::default()
+    }
+
+    /// Hand the physical memory of `chunk` back to the OS using `madvise`, while keeping it mapped.
+    /// Afterwards its contents are unspecified (on Linux, it reads as zeros).
+    pub fn discard(&self, chunk: &mut Chunk) {
+        assert!(chunk._handle_to_drop.is::<AnonMmapStorageHandle>(), "AnonMmapStorage got handed a foreign chunk.");
+        discard(chunk);
+    }
+}
+
+impl ChunkStorage for AnonMmapStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        self.try_create_chunk(ident.clone(), size)
+            .unwrap_or_else(|err| panic!("Can't map anonymous memory for chunk {}: {}", ident.0, err))
+    }
+
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        // zero-sized mappings aren't allowed, so map at least one byte
+        let capacity = ::std::cmp::max(size, 1).next_multiple_of(self.page_size());
+        let mut mmap = MmapMut::map_anon(capacity)?;
+        let ptr = mmap.as_mut_ptr();
+        self.live_chunks.lock().unwrap().insert(&ident, ptr as usize, size);
+        let handle = AnonMmapStorageHandle {
+            _mmap: mmap,
+            address: ptr as usize,
+            ident,
+            live_chunks: Arc::clone(&self.live_chunks),
+        };
+        Ok(unsafe { Chunk::from_raw_parts_with_capacity(ptr, size, capacity, ChunkKind::Transient, Box::new(handle)) })
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        (self.create_chunk(ident, size), true)
+    }
+
+    fn load_chunk(&self, _ident: Ident) -> Chunk {
+        panic!("can't load memory based chunks");
+    }
+
+    /// Unmaps the chunk's memory
+    fn forget_chunk(&self, chunk: Chunk) {
+        if !chunk._handle_to_drop.is::<AnonMmapStorageHandle>() {
+            crate::warn_foreign_chunk("AnonMmapStorage");
+        }
+        ::std::mem::drop(chunk);
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        if !chunk._handle_to_drop.is::<AnonMmapStorageHandle>() {
+            return Err(crate::foreign_chunk_error("AnonMmapStorage"));
+        }
+        self.forget_chunk(chunk);
+        Ok(())
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.live_chunks.lock().unwrap().latest(ident).is_some()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.live_chunks.lock().unwrap().latest(ident).map(|(_, len)| len)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.live_chunks.lock().unwrap().list(group)
+    }
+
+    fn page_size(&self) -> usize {
+        crate::mmap_storage::page_size()
+    }
+
+    fn chunk_kind(&self) -> ChunkKind {
+        ChunkKind::Transient
+    }
+
+    /// Anonymous chunks only exist while they are alive, so this hashes the live mapping
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let (ptr, len) = self.live_chunks.lock().unwrap().latest(ident)
+            .unwrap_or_else(|| panic!("No live anonymous chunk {}", ident.0));
+        crate::checksum(unsafe { ::std::slice::from_raw_parts(ptr as *const u8, len) })
+    }
+}
+
+/// Anonymous maps are shared mappings, whose pages `MADV_DONTNEED` would only unmap,
+/// so on Linux, `MADV_REMOVE` is needed to actually free them
+#[cfg(target_os = "linux")]
+const DISCARD_ADVICE: libc::c_int = libc::MADV_REMOVE;
+#[cfg(all(unix, not(target_os = "linux")))]
+const DISCARD_ADVICE: libc::c_int = libc::MADV_DONTNEED;
+
+#[cfg(unix)]
+fn discard(chunk: &mut Chunk) {
+    if !chunk.is_empty() {
+        unsafe {
+            libc::madvise(chunk.as_mut_ptr() as *mut libc::c_void, chunk.len(), DISCARD_ADVICE);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn discard(_chunk: &mut Chunk) {}
+
+/// Anonymous chunk handles own their mappings, which can be used from any thread
+unsafe impl SendableStorage for AnonMmapStorage {}
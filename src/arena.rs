@@ -1,53 +1,298 @@
-use crate::{Chunk, ChunkStorage, Ident};
-use crate::value::Value;
-use std::rc::Rc;
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::value::{Portable, PortableValue};
+use crate::shared::{SendableStorage, Shared};
+use core::cell::RefCell;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 /// Refers to an item within an `Arena`
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ArenaIndex(pub usize);
 
+/// Returned when constructing a collection whose persisted chunks are (partially) missing
+#[derive(Debug)]
+pub struct MissingChunksError {
+    /// Identifiers of all expected chunks that don't exist
+    pub missing: Vec<Ident>,
+}
+
+impl ::core::fmt::Display for MissingChunksError {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        let missing = self.missing.iter().map(|ident| ident.0.as_str()).collect::<Vec<_>>();
+        write!(f, "Missing chunks: {}", missing.join(", "))
+    }
+}
+
+impl ::core::error::Error for MissingChunksError {}
+
+/// Returned by `try_reserve` when the storage couldn't create the needed chunks
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct TryReserveError {
+    /// The number of additional items that space was requested for
+    pub additional: usize,
+    /// Why the storage couldn't create a chunk
+    pub source: ::std::io::Error,
+}
+
+#[cfg(feature = "std")]
+impl ::core::fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "Couldn't reserve space for {} more items: {}", self.additional, self.source)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::core::error::Error for TryReserveError {
+    fn source(&self) -> Option<&(dyn ::core::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// The result of checking the persisted state of an `Arena` with `Arena::verify`
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The persisted length (0 if it wasn't persisted)
+    pub len: usize,
+    /// Identifiers of chunks implied by the length that don't exist
+    pub missing: Vec<Ident>,
+    /// Identifiers and actual sizes of chunks that exist but have an unexpected size
+    pub mis_sized: Vec<(Ident, usize)>,
+    /// Identifiers of existing chunks beyond the ones implied by the length,
+    /// such as ones created by `reserve`
+    pub extra: Vec<Ident>,
+}
+
+impl VerifyReport {
+    /// Whether the arena could be loaded as persisted, without missing or mis-sized chunks
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.mis_sized.is_empty()
+    }
+}
+
 /// Stores items of a fixed (max) size consecutively in a collection of chunks
 pub struct Arena {
     ident: Ident,
-    chunks: Vec<Chunk>,
+    /// Persisted chunks that weren't accessed yet in a lazily loaded arena are `None`
+    chunks: RefCell<Vec<Option<Chunk>>>,
     chunk_size: usize,
     item_size: usize,
-    len: Value<usize>,
+    len: PortableValue<usize>,
+    /// Called on each item removed by `pop_away`, `swap_remove`, `truncate` or `clear`, if set
+    dropper: Option<unsafe fn(*mut u8)>,
     storage: Rc<dyn ChunkStorage>
 }
 
 impl Arena {
     /// Create a new arena given a chunk group identifier, chunk size and (max) item size
     pub fn new(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>) -> Arena {
+        Self::try_new(ident, chunk_size, item_size, storage).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Create a new arena like `new`, but with `chunk_size` rounded up to a multiple of the storage's page size
+    pub fn new_page_aligned(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>) -> Arena {
+        let chunk_size = ::core::cmp::max(chunk_size, item_size).next_multiple_of(storage.page_size());
+        Self::new(ident, chunk_size, item_size, storage)
+    }
+
+    /// Create a new arena like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, item_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Arena> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, item_size, storage))
+    }
+
+    /// Create a new arena like `new`, which calls `dropper` (if given) on each item it removes,
+    /// so untyped callers storing typed data can run destructors.
+    /// Dropping or forgetting the arena as a whole doesn't drop its items.
+    pub fn new_with_dropper(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>, dropper: Option<unsafe fn(*mut u8)>) -> Arena {
+        let mut arena = Self::new(ident, chunk_size, item_size, storage);
+        arena.dropper = dropper;
+        arena
+    }
+
+    /// Create a new arena like `new`, but only load persisted chunks when one of their items
+    /// is first accessed, which makes opening large, sparsely accessed arenas much faster
+    pub fn new_lazy(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>) -> Arena {
+        Self::load(ident, chunk_size, item_size, storage, true).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `new`, but first verifies that all chunks implied by the persisted length exist,
+    /// returning an error listing the missing ones instead of panicking while loading
+    pub fn try_new(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>) -> Result<Arena, MissingChunksError> {
+        Self::load(ident, chunk_size, item_size, storage, false)
+    }
+
+    fn load(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>, lazy: bool) -> Result<Arena, MissingChunksError> {
         assert!(chunk_size >= item_size);
 
-        let len = Value::<usize>::load_or_default(ident.sub("len"), 0, Rc::clone(&storage));
-        let mut chunks = Vec::new();
+        let len = PortableValue::<usize>::load_or_default(ident.sub("len"), 0, Rc::clone(&storage));
+
+        let chunk_idents = (0..len.get())
+            .step_by(chunk_size / item_size)
+            .map(|item_offset| ident.sub(item_offset))
+            .collect::<Vec<_>>();
+
+        let missing = chunk_idents
+            .iter()
+            .filter(|chunk_ident| !storage.chunk_exists(chunk_ident))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(MissingChunksError { missing });
+        }
+
+        let load = |chunk_ident: Ident| if lazy { None } else { Some(storage.load_chunk(chunk_ident)) };
 
-        let mut item_offset = 0;
+        let mut chunks = chunk_idents
+            .into_iter()
+            .map(load)
+            .collect::<Vec<_>>();
 
-        while item_offset < *len {
-            chunks.push(storage.load_chunk(ident.sub(item_offset)));
-            item_offset += chunk_size / item_size;
+        // also load chunks beyond the length that were created by `reserve`, which only outlive
+        // the arena on persistent storages (live transient chunks belong to other arenas of the same ident)
+        let items_per_chunk = chunk_size / item_size;
+        while storage.chunk_kind() == ChunkKind::Persistent && storage.chunk_exists(&ident.sub(chunks.len() * items_per_chunk)) {
+            chunks.push(load(ident.sub(chunks.len() * items_per_chunk)));
         }
 
-        Arena {
+        Ok(Arena {
             ident,
-            chunks,
+            chunks: RefCell::new(chunks),
             chunk_size,
             item_size,
             len,
+            dropper: None,
             storage
+        })
+    }
+
+    /// Check the persisted state of the arena with the given parameters without loading or modifying it,
+    /// reporting which chunks implied by its length are missing or mis-sized,
+    /// as well as chunks beyond its length.
+    ///
+    /// On storages with `Transient` chunks, the persisted length is always 0,
+    /// so any live chunks of the arena are reported as extra.
+    pub fn verify(ident: &Ident, chunk_size: usize, item_size: usize, storage: &dyn ChunkStorage) -> VerifyReport {
+        assert!(chunk_size >= item_size);
+        let items_per_chunk = chunk_size / item_size;
+        let mut missing = Vec::new();
+        let mut mis_sized = Vec::new();
+        let mut extra = Vec::new();
+
+        let len_ident = ident.sub("len");
+        // chunks of transient storages can't be loaded, so nothing of the arena is persisted
+        let persisted_len_size = match storage.chunk_kind() {
+            ChunkKind::Transient => None,
+            ChunkKind::Persistent => storage.chunk_len(&len_ident),
+        };
+        let len = match persisted_len_size {
+            None => 0,
+            Some(len_size) if len_size < <usize as Portable>::SIZE => {
+                mis_sized.push((len_ident, len_size));
+                0
+            }
+            Some(_) => usize::decode(&storage.load_chunk(len_ident)[..<usize as Portable>::SIZE]),
+        };
+
+        let n_chunks = len.div_ceil(items_per_chunk);
+
+        for chunk_index in 0..n_chunks {
+            let chunk_ident = ident.sub(chunk_index * items_per_chunk);
+            match storage.chunk_len(&chunk_ident) {
+                None => missing.push(chunk_ident),
+                Some(size) if size != chunk_size => mis_sized.push((chunk_ident, size)),
+                Some(_) => {}
+            }
+        }
+
+        let mut chunk_index = n_chunks;
+        while storage.chunk_exists(&ident.sub(chunk_index * items_per_chunk)) {
+            extra.push(ident.sub(chunk_index * items_per_chunk));
+            chunk_index += 1;
         }
+
+        VerifyReport { len, missing, mis_sized, extra }
     }
 
-    fn items_per_chunk(&self) -> usize {
+    pub(crate) fn items_per_chunk(&self) -> usize {
         self.chunk_size / self.item_size
     }
 
+    pub(crate) fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub(crate) fn item_size(&self) -> usize {
+        self.item_size
+    }
+
+    pub(crate) fn storage(&self) -> &Rc<dyn ChunkStorage> {
+        &self.storage
+    }
+
+    /// Total bytes of all chunks currently allocated for this arena, including ones not loaded yet
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        self.n_chunks() * self.chunk_size
+    }
+
+    fn n_chunks(&self) -> usize {
+        self.chunks.borrow().len()
+    }
+
+    /// Pointer to the start of the chunk at `chunk_index`, loading it first if needed.
+    /// Chunks never move in memory, so the pointer stays valid until the chunk is forgotten.
+    fn chunk_ptr(&self, chunk_index: usize) -> *mut u8 {
+        let mut chunks = self.chunks.borrow_mut();
+        let items_per_chunk = self.items_per_chunk();
+        chunks[chunk_index]
+            .get_or_insert_with(|| self.storage.load_chunk(self.ident.sub(chunk_index * items_per_chunk)))
+            .as_mut_ptr()
+    }
+
+    /// Remove the last chunk, loading it first if needed, so it can be forgotten
+    fn pop_chunk(&mut self) -> Option<Chunk> {
+        let chunk_index = self.n_chunks().checked_sub(1)?;
+        let loaded = self.chunks.get_mut().pop().expect("should have chunk left");
+        Some(loaded.unwrap_or_else(|| self.storage.load_chunk(self.ident.sub(chunk_index * self.items_per_chunk()))))
+    }
+
+    /// Pointers to the start of each chunk, together with the number of items stored in it
+    pub(crate) fn chunk_runs(&self) -> impl Iterator<Item = (*const u8, usize)> + '_ {
+        let items_per_chunk = self.items_per_chunk();
+        let len = self.len();
+        (0..self.n_chunks())
+            .map(move |chunk_index| (chunk_index, ::core::cmp::min(items_per_chunk, len.saturating_sub(chunk_index * items_per_chunk))))
+            .filter(|&(_, n_items)| n_items > 0)
+            .map(move |(chunk_index, n_items)| (self.chunk_ptr(chunk_index) as *const u8, n_items))
+    }
+
+    /// Iterate over pointers to all items in order
+    pub fn iter_ptrs(&self) -> impl Iterator<Item = *const u8> + '_ {
+        let item_size = self.item_size;
+        self.chunk_runs().flat_map(move |(chunk_ptr, n_items)| {
+            (0..n_items).map(move |offset| chunk_ptr.wrapping_add(offset * item_size))
+        })
+    }
+
+    /// Iterate over mutable pointers to all items in order,
+    /// borrowing the arena mutably for as long as the iterator lives
+    pub fn iter_mut_ptrs(&mut self) -> impl Iterator<Item = *mut u8> + '_ {
+        let item_size = self.item_size;
+        let items_per_chunk = self.items_per_chunk();
+        let len = self.len();
+        let arena = &*self;
+        (0..self.n_chunks())
+            .flat_map(move |chunk_index| {
+                let n_items = ::core::cmp::min(items_per_chunk, len.saturating_sub(chunk_index * items_per_chunk));
+                let chunk_ptr = if n_items > 0 { arena.chunk_ptr(chunk_index) } else { ::core::ptr::null_mut() };
+                (0..n_items).map(move |offset| chunk_ptr.wrapping_add(offset * item_size))
+            })
+    }
+
     /// Number of elements in the collection
     pub fn len(&self) -> usize {
-        *self.len
+        self.len.get()
     }
 
     /// Is the collection empty?
@@ -61,64 +306,295 @@ impl Arena {
     /// This is handled like this so items of heterogeneous types or sizes less
     /// than the fixed item size can be added to the collection.
     pub fn push(&mut self) -> (*mut u8, ArenaIndex) {
+        let len = self.len();
         // Make sure the item can fit in the current chunk
-        if (*self.len + 1) > self.chunks.len() * self.items_per_chunk() {
+        if (len + 1) > self.n_chunks() * self.items_per_chunk() {
             // If not, create a new chunk
-            self.chunks
-                .push(self.storage.create_chunk(self.ident.sub(*self.len), self.chunk_size));
+            let chunk = self.storage.create_chunk(self.ident.sub(len), self.chunk_size);
+            self.chunks.get_mut().push(Some(chunk));
         }
-        let offset = (*self.len % self.items_per_chunk()) * self.item_size;
-        let index = ArenaIndex(*self.len);
-        *self.len += 1;
+        let chunk_index = len / self.items_per_chunk();
+        let offset = (len % self.items_per_chunk()) * self.item_size;
+        let index = ArenaIndex(len);
+        self.len.set(len + 1);
         unsafe {
             (
-                self.chunks.last_mut().unwrap().as_mut_ptr().offset(offset as isize),
+                self.chunk_ptr(chunk_index).add(offset),
                 index,
             )
         }
     }
 
-    /// Remove the last item from the end
+    /// Set the length of the arena directly, for reconstructing it from chunks that were put into
+    /// its storage by other means, such as copying them in. Chunks that appeared in a persistent
+    /// storage since the arena was loaded are loaded first.
+    ///
+    /// # Safety
+    ///
+    /// The first `new_len` items have to be initialized. Panics if there aren't enough chunks
+    /// to hold `new_len` items.
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        let items_per_chunk = self.items_per_chunk();
+        while self.storage.chunk_kind() == ChunkKind::Persistent
+            && self.storage.chunk_exists(&self.ident.sub(self.n_chunks() * items_per_chunk))
+        {
+            let chunk = self.storage.load_chunk(self.ident.sub(self.n_chunks() * items_per_chunk));
+            self.chunks.get_mut().push(Some(chunk));
+        }
+
+        assert!(
+            new_len <= self.n_chunks() * items_per_chunk,
+            "Not enough chunks for {} items (only {} chunks of {} items)",
+            new_len, self.n_chunks(), items_per_chunk
+        );
+        self.len.set(new_len);
+    }
+
+    /// Create chunks ahead of time so that at least `additional` more items
+    /// can be pushed without allocating
+    pub fn reserve(&mut self, additional: usize) {
+        let items_per_chunk = self.items_per_chunk();
+        let needed_chunks = (self.len() + additional).div_ceil(items_per_chunk);
+
+        while self.n_chunks() < needed_chunks {
+            let item_offset = self.n_chunks() * items_per_chunk;
+            let chunk = self.storage.create_chunk(self.ident.sub(item_offset), self.chunk_size);
+            self.chunks.get_mut().push(Some(chunk));
+        }
+    }
+
+    /// Append `count` items by copying them from `src`, where they are stored consecutively
+    /// with a stride of the item size, copying each run that fits into a chunk at once
+    ///
+    /// # Safety
+    ///
+    /// `src` has to be valid for reads of `count * item_size` bytes, and not point into the arena.
+    pub unsafe fn extend_from_ptr(&mut self, src: *const u8, count: usize) {
+        self.reserve(count);
+        let items_per_chunk = self.items_per_chunk();
+        let mut copied = 0;
+
+        while copied < count {
+            let len = self.len();
+            let chunk_index = len / items_per_chunk;
+            let offset_in_chunk = len % items_per_chunk;
+            let n_items = ::core::cmp::min(items_per_chunk - offset_in_chunk, count - copied);
+            ::core::ptr::copy_nonoverlapping(
+                src.add(copied * self.item_size),
+                self.chunk_ptr(chunk_index).add(offset_in_chunk * self.item_size),
+                n_items * self.item_size,
+            );
+            copied += n_items;
+            self.len.set(len + n_items);
+        }
+    }
+
+    /// Like `reserve`, but returns an error instead of panicking if the storage can't create a chunk,
+    /// in which case all chunks created by this call are forgotten again
+    #[cfg(feature = "std")]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let items_per_chunk = self.items_per_chunk();
+        let needed_chunks = self.len().checked_add(additional)
+            .ok_or_else(|| TryReserveError {
+                additional,
+                source: ::std::io::Error::new(::std::io::ErrorKind::OutOfMemory, "Capacity overflow"),
+            })?
+            .div_ceil(items_per_chunk);
+        let n_chunks_before = self.n_chunks();
+
+        while self.n_chunks() < needed_chunks {
+            let item_offset = self.n_chunks() * items_per_chunk;
+            match self.storage.try_create_chunk(self.ident.sub(item_offset), self.chunk_size) {
+                Ok(chunk) => self.chunks.get_mut().push(Some(chunk)),
+                Err(source) => {
+                    while self.n_chunks() > n_chunks_before {
+                        let chunk = self.pop_chunk().expect("should have chunk left");
+                        self.storage.forget_chunk(chunk);
+                    }
+                    return Err(TryReserveError { additional, source });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget trailing chunks that don't hold any items, such as ones created by `reserve`
+    pub fn shrink_to_fit(&mut self) {
+        let needed_chunks = self.len().div_ceil(self.items_per_chunk());
+
+        while self.n_chunks() > needed_chunks {
+            let chunk = self.pop_chunk().expect("should have chunk left");
+            self.storage.forget_chunk(chunk);
+        }
+    }
+
+    /// Call the dropper (if any) on the items `[start, end)`
+    fn drop_items(&mut self, start: usize, end: usize) {
+        if let Some(dropper) = self.dropper {
+            for index in start..end {
+                unsafe { dropper(self.at_mut(ArenaIndex(index))) };
+            }
+        }
+    }
+
+    /// Remove the last item from the end, calling the dropper on it (if any)
     pub fn pop_away(&mut self) {
-        *self.len -= 1;
+        let len = self.len();
+        self.drop_items(len - 1, len);
+        self.pop_away_without_drop();
+    }
+
+    fn pop_away_without_drop(&mut self) {
+        let len = self.len() - 1;
+        self.len.set(len);
         // If possible, remove the last chunk as well
-        if *self.len % self.items_per_chunk() == 0 {
-            self.storage.forget_chunk(self.chunks.pop().expect("should have chunk left"));
+        if len % self.items_per_chunk() == 0 {
+            let chunk = self.pop_chunk().expect("should have chunk left");
+            self.storage.forget_chunk(chunk);
         }
     }
 
+    /// Shorten the arena to `new_len` items (if it is longer), calling the dropper on the removed ones (if any)
+    /// and forgetting all chunks that don't hold any of the remaining items, including ones created by `reserve`
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.len() {
+            return;
+        }
+        self.drop_items(new_len, self.len());
+        self.len.set(new_len);
+        self.shrink_to_fit();
+    }
+
+    /// Remove all items, calling the dropper on them (if any), and forget all chunks
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
     /// Remove the item at index, by swapping it with the last item
     /// and then popping, returning the swapped in item (unless empty).
     ///
     /// This is a O(1) way of removing an item if the order of items doesn't matter.
+    /// The dropper (if any) is called on the removed item.
     pub unsafe fn swap_remove(&mut self, index: ArenaIndex) -> Option<*const u8> {
-        assert!(*self.len > 0);
-        let last_index = *self.len - 1;
+        assert!(!self.is_empty());
+        self.drop_items(index.0, index.0 + 1);
+        self.swap_remove_without_drop(index)
+    }
+
+    unsafe fn swap_remove_without_drop(&mut self, index: ArenaIndex) -> Option<*const u8> {
+        let last_index = self.len() - 1;
         if last_index == index.0 {
             // if swapping last item
-            self.pop_away();
+            self.pop_away_without_drop();
             None
         } else {
-            let last = self.at(ArenaIndex(*self.len - 1));
+            let last = self.at(ArenaIndex(last_index));
             let at_index = self.at_mut(index);
-            ::std::ptr::copy_nonoverlapping(last, at_index, self.item_size);
-            self.pop_away();
+            ::core::ptr::copy_nonoverlapping(last, at_index, self.item_size);
+            self.pop_away_without_drop();
             Some(self.at(index))
         }
     }
 
+    /// Remove all items for which `keep` returns false, using `swap_remove`,
+    /// so the order of the remaining items is not preserved.
+    ///
+    /// Items are visited from the end, so every item swapped into a removed slot
+    /// has already been visited and kept.
+    ///
+    /// # Safety
+    ///
+    /// `keep` is handed raw pointers to the items, which are only valid during the call.
+    pub unsafe fn retain_swap<F: FnMut(*const u8) -> bool>(&mut self, mut keep: F) {
+        for index in (0..self.len()).rev() {
+            if !keep(self.at(ArenaIndex(index))) {
+                self.swap_remove(ArenaIndex(index));
+            }
+        }
+    }
+
+    /// Remove all items for which `remove` returns true, like `retain_swap`,
+    /// returning copies of their bytes in the order they were stored in.
+    /// The removed items are moved out, so the dropper (if any) isn't called on them.
+    ///
+    /// # Safety
+    ///
+    /// `remove` is handed raw pointers to the items, which are only valid during the call.
+    pub unsafe fn drain_filter<F: FnMut(*const u8) -> bool>(&mut self, mut remove: F) -> Vec<Vec<u8>> {
+        let mut removed = Vec::new();
+        for index in (0..self.len()).rev() {
+            let item_ptr = self.at(ArenaIndex(index));
+            if remove(item_ptr) {
+                removed.push(::core::slice::from_raw_parts(item_ptr, self.item_size).to_vec());
+                self.swap_remove_without_drop(ArenaIndex(index));
+            }
+        }
+        removed.reverse();
+        removed
+    }
+
+    /// Forget all chunks of this arena, including the one storing its length,
+    /// deleting any persisted representation of it
+    pub fn forget_all(self) {
+        let (chunks, storage) = self.into_chunks();
+        storage.forget_chunks(chunks);
+    }
+
+    /// Give up the arena, returning all its chunks in order (loading them if needed),
+    /// followed by the chunk of its length, so the caller can take over forgetting them
+    pub(crate) fn into_chunks(mut self) -> (Vec<Chunk>, Rc<dyn ChunkStorage>) {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = self.pop_chunk() {
+            chunks.push(chunk);
+        }
+        chunks.reverse();
+        chunks.push(self.len.into_chunk());
+        (chunks, self.storage)
+    }
+
+    /// Whether there is an item at `index`, which is the case exactly if it's below the length
+    pub fn contains(&self, index: ArenaIndex) -> bool {
+        index.0 < self.len()
+    }
+
+    /// Get a pointer to the item at `index`, unless there is none (see `contains`)
+    pub fn get_ptr(&self, index: ArenaIndex) -> Option<*const u8> {
+        if self.contains(index) {
+            Some(unsafe { self.at(index) })
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable pointer to the item at `index`, unless there is none (see `contains`)
+    pub fn get_mut_ptr(&mut self, index: ArenaIndex) -> Option<*mut u8> {
+        if self.contains(index) {
+            Some(unsafe { self.at_mut(index) })
+        } else {
+            None
+        }
+    }
+
     /// Get a pointer to the item at `index`
     pub unsafe fn at(&self, index: ArenaIndex) -> *const u8 {
-        self.chunks[index.0 / self.items_per_chunk()]
-            .as_ptr()
+        self.chunk_ptr(index.0 / self.items_per_chunk())
             .offset(((index.0 % self.items_per_chunk()) * self.item_size) as isize)
     }
 
     /// Get a mutable pointer to the item at `index`
     pub unsafe fn at_mut(&mut self, index: ArenaIndex) -> *mut u8 {
         let items_per_chunk = self.items_per_chunk();
-        self.chunks[index.0 / items_per_chunk]
-            .as_mut_ptr()
+        self.chunk_ptr(index.0 / items_per_chunk)
             .offset(((index.0 % items_per_chunk) * self.item_size) as isize)
     }
-}
\ No newline at end of file
+}
+
+/// Chunks that weren't loaded yet in a lazily loaded arena are unchanged, so they aren't loaded for this
+impl crate::Flushable for Arena {
+    fn flush(&self) {
+        for chunk in self.chunks.borrow().iter().flatten() {
+            self.storage.flush_chunk(chunk);
+        }
+        self.len.flush(&*self.storage);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{Chunk, ChunkStorage, Ident};
+use alloc::rc::Rc;
+use core::sync::atomic::Ordering;
+
+/// An integer type with a corresponding atomic type of the same size and representation,
+/// which can be stored in an `AtomicValue`
+pub trait AtomicInt: Copy {
+    /// The corresponding atomic type
+    type Atomic;
+    /// See e.g. `AtomicU64::new`
+    fn new_atomic(value: Self) -> Self::Atomic;
+    /// See e.g. `AtomicU64::load`
+    fn load(atomic: &Self::Atomic, order: Ordering) -> Self;
+    /// See e.g. `AtomicU64::store`
+    fn store(atomic: &Self::Atomic, value: Self, order: Ordering);
+    /// See e.g. `AtomicU64::compare_exchange`
+    fn compare_exchange(atomic: &Self::Atomic, current: Self, new: Self, success: Ordering, failure: Ordering) -> Result<Self, Self>;
+    /// See e.g. `AtomicU64::fetch_add`
+    fn fetch_add(atomic: &Self::Atomic, value: Self, order: Ordering) -> Self;
+}
+
+macro_rules! impl_atomic_int {
+    ($int:ty, $atomic:ty) => {
+        impl AtomicInt for $int {
+            type Atomic = $atomic;
+
+            fn new_atomic(value: Self) -> Self::Atomic {
+                <$atomic>::new(value)
+            }
+
+            fn load(atomic: &Self::Atomic, order: Ordering) -> Self {
+                atomic.load(order)
+            }
+
+            fn store(atomic: &Self::Atomic, value: Self, order: Ordering) {
+                atomic.store(value, order)
+            }
+
+            fn compare_exchange(atomic: &Self::Atomic, current: Self, new: Self, success: Ordering, failure: Ordering) -> Result<Self, Self> {
+                atomic.compare_exchange(current, new, success, failure)
+            }
+
+            fn fetch_add(atomic: &Self::Atomic, value: Self, order: Ordering) -> Self {
+                atomic.fetch_add(value, order)
+            }
+        }
+    };
+}
+
+impl_atomic_int!(u32, core::sync::atomic::AtomicU32);
+impl_atomic_int!(i32, core::sync::atomic::AtomicI32);
+impl_atomic_int!(u64, core::sync::atomic::AtomicU64);
+impl_atomic_int!(i64, core::sync::atomic::AtomicI64);
+impl_atomic_int!(usize, core::sync::atomic::AtomicUsize);
+
+/// A single integer stored in a chunk, which is only ever accessed atomically.
+///
+/// When backed by a chunk that is shared memory, such as the same file mmap'ed by
+/// several `MmapStorage`s (possibly in different processes), this can be used to coordinate
+/// between them. The chunk has to be aligned to the size of the integer, which chunks of all
+/// storages in this crate are. The integer is stored in native byte order, so unlike
+/// `PortableValue` it can't be read back on an architecture of different endianness.
+pub struct AtomicValue<T: AtomicInt> {
+    chunk: Chunk,
+    _storage: Rc<dyn ChunkStorage>,
+    _marker: core::marker::PhantomData<*mut T>,
+}
+
+impl<T: AtomicInt> AtomicValue<T> {
+    /// Load the value in the chunk with the given identifier, or create it using a default value
+    pub fn load_or_default(ident: Ident, default: T, storage: Rc<dyn ChunkStorage>) -> AtomicValue<T> {
+        let size = ::core::mem::size_of::<T::Atomic>();
+        let (chunk, created_new) = storage.load_or_create_chunk(ident, size);
+
+        assert!(
+            chunk.len() >= size && (chunk.as_ptr() as *const T::Atomic).is_aligned(),
+            "Chunk is too small or not aligned for atomic value"
+        );
+
+        let value = AtomicValue {
+            chunk,
+            _storage: storage,
+            _marker: core::marker::PhantomData,
+        };
+
+        if created_new {
+            unsafe { ::core::ptr::write(value.chunk.as_ptr() as *mut T::Atomic, T::new_atomic(default)) };
+        }
+
+        value
+    }
+
+    fn atomic(&self) -> &T::Atomic {
+        unsafe { &*(self.chunk.as_ptr() as *const T::Atomic) }
+    }
+
+    /// Atomically load the value
+    pub fn load(&self, order: Ordering) -> T {
+        T::load(self.atomic(), order)
+    }
+
+    /// Atomically store a new value
+    pub fn store(&self, value: T, order: Ordering) {
+        T::store(self.atomic(), value, order)
+    }
+
+    /// Atomically store `new` if the value is `current`, returning the previous value,
+    /// which is wrapped in `Ok` if it was `current`
+    pub fn compare_exchange(&self, current: T, new: T, success: Ordering, failure: Ordering) -> Result<T, T> {
+        T::compare_exchange(self.atomic(), current, new, success, failure)
+    }
+
+    /// Atomically add to the value (wrapping around on overflow), returning the previous value
+    pub fn fetch_add(&self, value: T, order: Ordering) -> T {
+        T::fetch_add(self.atomic(), value, order)
+    }
+}
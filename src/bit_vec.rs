@@ -0,0 +1,89 @@
+use crate::{ChunkStorage, Ident};
+use crate::arena::{Arena, ArenaIndex};
+use crate::value::PortableValue;
+use crate::shared::{SendableStorage, Shared};
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// A growable vector of flags, such as liveness flags indexed by `ArenaIndex`,
+/// stored as 8 flags per byte in the chunks of an `Arena`
+pub struct BitVec {
+    bytes: Arena,
+    len: PortableValue<usize>,
+}
+
+impl BitVec {
+    /// Create a new bit vector given a chunk group identifier and chunk size
+    pub fn new(ident: Ident, chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        BitVec {
+            len: PortableValue::load_or_default(ident.sub("n_bits"), 0, Rc::clone(&storage)),
+            bytes: Arena::new(ident, ::core::cmp::max(chunk_size, 1), 1, storage),
+        }
+    }
+
+    /// Create a new bit vector like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, storage))
+    }
+
+    /// Number of flags
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Does the bit vector not contain any flags?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn byte_mut(&mut self, index: usize) -> &mut u8 {
+        assert!(index < self.len(), "bit index {} out of bounds (len {})", index, self.len());
+        unsafe { &mut *self.bytes.at_mut(ArenaIndex(index / 8)) }
+    }
+
+    /// Get the flag at `index`
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < self.len(), "bit index {} out of bounds (len {})", index, self.len());
+        let byte = unsafe { *self.bytes.at(ArenaIndex(index / 8)) };
+        byte & (1 << (index % 8)) != 0
+    }
+
+    /// Set the flag at `index`
+    pub fn set(&mut self, index: usize) {
+        *self.byte_mut(index) |= 1 << (index % 8);
+    }
+
+    /// Clear the flag at `index`
+    pub fn clear(&mut self, index: usize) {
+        *self.byte_mut(index) &= !(1 << (index % 8));
+    }
+
+    /// Grow or shrink to `new_len` flags, where added flags are cleared
+    pub fn resize(&mut self, new_len: usize) {
+        let old_len = self.len();
+
+        if new_len < old_len {
+            // clear the flags beyond the new length in the last kept byte,
+            // so they are cleared when growing again
+            for index in new_len..::core::cmp::min(old_len, new_len.div_ceil(8) * 8) {
+                self.clear(index);
+            }
+            while self.bytes.len() > new_len.div_ceil(8) {
+                self.bytes.pop_away();
+            }
+        } else {
+            while self.bytes.len() < new_len.div_ceil(8) {
+                unsafe { *self.bytes.push().0 = 0 };
+            }
+        }
+
+        self.len.set(new_len);
+    }
+}
+
+impl crate::Flushable for BitVec {
+    fn flush(&self) {
+        self.bytes.flush();
+        self.len.flush(&**self.bytes.storage());
+    }
+}
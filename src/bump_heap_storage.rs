@@ -0,0 +1,173 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::live_chunks::LiveChunks;
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Alignment of all chunks handed out, matching what the system allocator guarantees
+const CHUNK_ALIGN: usize = 16;
+
+/// A `ChunkStorage` that allocates chunks on the heap by handing out ranges of large slabs,
+/// instead of doing one allocation per chunk.
+///
+/// The ranges of dropped or forgotten chunks are reused for new chunks of the same
+/// (aligned) size. Slabs are only freed once the storage and all its chunks are dropped.
+pub struct BumpHeapStorage {
+    slab_size: usize,
+    state: Rc<RefCell<BumpState>>,
+}
+
+struct Slab {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Drop for Slab {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+struct BumpState {
+    slabs: Vec<Slab>,
+    bump_ptr: *mut u8,
+    bump_remaining: usize,
+    free_ranges: HashMap<usize, Vec<*mut u8>>,
+    live_chunks: LiveChunks,
+}
+
+impl BumpState {
+    fn allocate_slab(&mut self, size: usize) -> *mut u8 {
+        let layout = Layout::from_size_align(size, CHUNK_ALIGN).expect("Invalid slab size");
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        self.slabs.push(Slab { ptr, layout });
+        ptr
+    }
+}
+
+struct BumpHeapStorageHandle {
+    ptr: *mut u8,
+    aligned_size: usize,
+    ident: Ident,
+    state: Rc<RefCell<BumpState>>,
+}
+
+impl Drop for BumpHeapStorageHandle {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.free_ranges.entry(self.aligned_size).or_default().push(self.ptr);
+        state.live_chunks.remove(&self.ident, self.ptr as usize);
+    }
+}
+
+impl BumpHeapStorage {
+    /// Create a new `BumpHeapStorage` which allocates slabs of `slab_size` bytes.
+    /// Chunks larger than that get a slab of their own.
+    pub fn new(slab_size: usize) -> BumpHeapStorage {
+        BumpHeapStorage {
+            slab_size: aligned(slab_size),
+            state: Rc::new(RefCell::new(BumpState {
+                slabs: Vec::new(),
+                bump_ptr: ::std::ptr::null_mut(),
+                bump_remaining: 0,
+                free_ranges: HashMap::new(),
+                live_chunks: LiveChunks::default(),
+            })),
+        }
+    }
+
+    fn allocate(&self, aligned_size: usize) -> *mut u8 {
+        let mut state = self.state.borrow_mut();
+
+        if let Some(ptr) = state.free_ranges.get_mut(&aligned_size).and_then(Vec::pop) {
+            ptr
+        } else if aligned_size > self.slab_size {
+            state.allocate_slab(aligned_size)
+        } else {
+            if state.bump_remaining < aligned_size {
+                state.bump_ptr = state.allocate_slab(self.slab_size);
+                state.bump_remaining = self.slab_size;
+            }
+            let ptr = state.bump_ptr;
+            state.bump_ptr = unsafe { ptr.add(aligned_size) };
+            state.bump_remaining -= aligned_size;
+            ptr
+        }
+    }
+}
+
+/// Round `size` up to a nonzero multiple of `CHUNK_ALIGN`
+fn aligned(size: usize) -> usize {
+    ::std::cmp::max(size.div_ceil(CHUNK_ALIGN), 1) * CHUNK_ALIGN
+}
+
+impl ChunkStorage for BumpHeapStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        let aligned_size = aligned(size);
+        let ptr = self.allocate(aligned_size);
+        self.state.borrow_mut().live_chunks.insert(&ident, ptr as usize, size);
+        let handle = BumpHeapStorageHandle {
+            ptr,
+            aligned_size,
+            ident,
+            state: Rc::clone(&self.state),
+        };
+        // the handle keeps the slabs alive, even if the storage is dropped first
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, size, aligned_size, ChunkKind::Transient, Box::new(handle)) }
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        (self.create_chunk(ident, size), true)
+    }
+
+    fn load_chunk(&self, _ident: Ident) -> Chunk {
+        panic!("can't load memory based chunks");
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        if !chunk._handle_to_drop.is::<BumpHeapStorageHandle>() {
+            crate::warn_foreign_chunk("BumpHeapStorage");
+        }
+        ::std::mem::drop(chunk);
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        if !chunk._handle_to_drop.is::<BumpHeapStorageHandle>() {
+            return Err(crate::foreign_chunk_error("BumpHeapStorage"));
+        }
+        self.forget_chunk(chunk);
+        Ok(())
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.state.borrow().live_chunks.latest(ident).is_some()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.state.borrow().live_chunks.latest(ident).map(|(_, len)| len)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.state.borrow().live_chunks.list(group)
+    }
+
+    /// Chunks are handed out in multiples of their alignment, which is treated as the page size
+    fn page_size(&self) -> usize {
+        CHUNK_ALIGN
+    }
+
+    fn chunk_kind(&self) -> ChunkKind {
+        ChunkKind::Transient
+    }
+
+    /// Heap chunks only exist while they are alive, so this hashes the live buffer
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let (ptr, len) = self.state.borrow().live_chunks.latest(ident)
+            .unwrap_or_else(|| panic!("No live heap chunk {}", ident.0));
+        crate::checksum(unsafe { ::std::slice::from_raw_parts(ptr as *const u8, len) })
+    }
+}
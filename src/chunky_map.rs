@@ -0,0 +1,263 @@
+use crate::{ChunkStorage, Ident};
+use crate::arena::ArenaIndex;
+use crate::multi_arena::{MultiArena, MultiArenaIndex};
+use crate::value::PortableValue;
+use crate::vector::Vector;
+use crate::shared::{SendableStorage, Shared};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// Slot value of a slot that was never used
+const EMPTY: usize = 0;
+/// Slot value of a slot whose entry was removed, which lookups have to probe past
+const TOMBSTONE: usize = 1;
+/// Other slot values are the index of the entry in its bin, offset by this
+const FIRST_ENTRY: usize = 2;
+
+const INITIAL_CAPACITY: usize = 8;
+
+/// FNV-1a, which (unlike std's `DefaultHasher`) is guaranteed to hash the same way
+/// in every program run, so persisted slots stay valid when the map is loaded again
+struct StableHasher(u64);
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn hash<K: Hash>(key: &K) -> u64 {
+    let mut hasher = StableHasher(0xcbf2_9ce4_8422_2325);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Entry<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// A hash map which stores its entries in a `MultiArena` and finds them using
+/// an open-addressing (linear probing) index of slots, persisted in a `Vector<usize>`.
+///
+/// Like for the other collections, keys and values are persisted as their in-memory
+/// representation, so they shouldn't contain pointers if the map is to be loaded again.
+/// Keys also have to hash the same way in every program run.
+pub struct ChunkyMap<K: Hash + Eq, V> {
+    entries: MultiArena,
+    slots: Vector<usize>,
+    tombstones: PortableValue<usize>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K: Hash + Eq, V> ChunkyMap<K, V> {
+    /// Create a new map given a chunk group identifier and chunk size
+    pub fn new(ident: Ident, chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        let entry_size = ::core::cmp::max(::core::mem::size_of::<Entry<K, V>>(), 1);
+        let mut map = ChunkyMap {
+            entries: MultiArena::new(ident.sub("entries"), chunk_size, entry_size, Rc::clone(&storage)),
+            slots: Vector::new(ident.sub("slots"), chunk_size, Rc::clone(&storage)),
+            tombstones: PortableValue::load_or_default(ident.sub("tombstones"), 0, storage),
+            _marker: PhantomData,
+        };
+
+        if map.slots.is_empty() {
+            map.rehash(INITIAL_CAPACITY);
+        }
+
+        map
+    }
+
+    /// Create a new map like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, storage))
+    }
+
+    /// Number of entries in the map
+    pub fn len(&self) -> usize {
+        self.entries.populated_bin_indices_and_lens().map(|(_, len)| len).sum()
+    }
+
+    /// Is the map empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entry_index(&self, index: usize) -> MultiArenaIndex {
+        // all entries have the same size, so they are all in the first bin
+        MultiArenaIndex(0, ArenaIndex(index))
+    }
+
+    fn entry(&self, index: usize) -> &Entry<K, V> {
+        unsafe { &*(self.entries.at(self.entry_index(index)) as *const Entry<K, V>) }
+    }
+
+    fn entry_mut(&mut self, index: usize) -> &mut Entry<K, V> {
+        unsafe { &mut *(self.entries.at_mut(self.entry_index(index)) as *mut Entry<K, V>) }
+    }
+
+    fn slot(&self, slot_index: usize) -> usize {
+        *self.slots.at(slot_index).expect("should have slot")
+    }
+
+    fn set_slot(&mut self, slot_index: usize, slot: usize) {
+        *self.slots.at_mut(slot_index).expect("should have slot") = slot;
+    }
+
+    /// Slot indices in probing order for `hash`
+    fn probe(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let capacity = self.slots.len();
+        let start = hash as usize & (capacity - 1);
+        (0..capacity).map(move |offset| (start + offset) & (capacity - 1))
+    }
+
+    /// Find the slot index of the entry for `key` (if any)
+    /// and the first slot index where it could be inserted instead
+    fn find(&self, hash: u64, key: &K) -> (Option<usize>, Option<usize>) {
+        let mut free_slot_index = None;
+        for slot_index in self.probe(hash) {
+            match self.slot(slot_index) {
+                EMPTY => return (None, free_slot_index.or(Some(slot_index))),
+                TOMBSTONE => {
+                    free_slot_index.get_or_insert(slot_index);
+                }
+                slot => {
+                    let entry = self.entry(slot - FIRST_ENTRY);
+                    if entry.hash == hash && entry.key == *key {
+                        return (Some(slot_index), free_slot_index);
+                    }
+                }
+            }
+        }
+        (None, free_slot_index)
+    }
+
+    /// Rebuild the index with `capacity` slots, getting rid of all tombstones
+    fn rehash(&mut self, capacity: usize) {
+        self.slots.clear();
+        for _ in 0..capacity {
+            self.slots.push(EMPTY);
+        }
+        self.tombstones.set(0);
+
+        for index in 0..self.len() {
+            let hash = self.entry(index).hash;
+            let slot_index = self.probe(hash)
+                .find(|slot_index| self.slot(*slot_index) == EMPTY)
+                .expect("should have empty slot after growing");
+            self.set_slot(slot_index, index + FIRST_ENTRY);
+        }
+    }
+
+    /// Get a reference to the value for `key`
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match self.find(hash(key), key) {
+            (Some(slot_index), _) => Some(&self.entry(self.slot(slot_index) - FIRST_ENTRY).value),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable reference to the value for `key`
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        match self.find(hash(key), key) {
+            (Some(slot_index), _) => {
+                let index = self.slot(slot_index) - FIRST_ENTRY;
+                Some(&mut self.entry_mut(index).value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Does the map contain an entry for `key`?
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert `value` for `key`, returning the value it replaced, if any
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let hash = hash(&key);
+
+        if let (Some(slot_index), _) = self.find(hash, &key) {
+            let index = self.slot(slot_index) - FIRST_ENTRY;
+            return Some(::core::mem::replace(&mut self.entry_mut(index).value, value));
+        }
+
+        // keep the load factor (counting tombstones) at most 3/4,
+        // only growing if tombstones are not what fills the slots
+        let capacity = self.slots.len();
+        if (self.len() + self.tombstones.get() + 1) * 4 > capacity * 3 {
+            let new_capacity = if (self.len() + 1) * 2 > capacity { capacity * 2 } else { capacity };
+            self.rehash(new_capacity);
+        }
+
+        let slot_index = self.find(hash, &key).1.expect("should have free slot");
+        if self.slot(slot_index) == TOMBSTONE {
+            self.tombstones.set(self.tombstones.get() - 1);
+        }
+
+        let (entry_ptr, entry_index) = self.entries.push(::core::mem::size_of::<Entry<K, V>>());
+        unsafe { ::core::ptr::write(entry_ptr as *mut Entry<K, V>, Entry { hash, key, value }) };
+        self.set_slot(slot_index, (entry_index.1).0 + FIRST_ENTRY);
+        None
+    }
+
+    /// Remove the entry for `key`, returning its value, if any
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot_index = self.find(hash(key), key).0?;
+        let index = self.slot(slot_index) - FIRST_ENTRY;
+        self.set_slot(slot_index, TOMBSTONE);
+        self.tombstones.set(self.tombstones.get() + 1);
+
+        let last_index = self.len() - 1;
+        let entry = unsafe { ::core::ptr::read(self.entry(index)) };
+
+        if index != last_index {
+            // the last entry is about to be moved into the removed one's place
+            let last_hash = self.entry(last_index).hash;
+            let last_slot_index = self.probe(last_hash)
+                .find(|slot_index| self.slot(*slot_index) == last_index + FIRST_ENTRY)
+                .expect("should have slot for last entry");
+            self.set_slot(last_slot_index, index + FIRST_ENTRY);
+        }
+        self.entries.swap_remove_within_bin(self.entry_index(index));
+
+        Some(entry.value)
+    }
+
+    /// Iterate over all keys and values, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        (0..self.len()).map(move |index| {
+            let entry = self.entry(index);
+            (&entry.key, &entry.value)
+        })
+    }
+}
+
+/// Dropping a map drops all its keys and values, but keeps its chunks' persisted representation (if any)
+impl<K: Hash + Eq, V> Drop for ChunkyMap<K, V> {
+    fn drop(&mut self) {
+        if ::core::mem::needs_drop::<Entry<K, V>>() {
+            for index in 0..self.len() {
+                unsafe { ::core::ptr::drop_in_place(self.entries.at_mut(self.entry_index(index)) as *mut Entry<K, V>) };
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> crate::Flushable for ChunkyMap<K, V> {
+    fn flush(&self) {
+        self.entries.flush();
+        self.slots.flush();
+        self.tombstones.flush(self.entries.storage());
+    }
+}
@@ -0,0 +1,192 @@
+//! A test harness for `ChunkStorage` implementations, checking that they satisfy
+//! the contract that the collections in this crate rely on.
+//!
+//! Backends are only checked by their observable behaviour, so this can be run against
+//! any storage, such as in the tests of a crate implementing a custom backend:
+//!
+//! ```ignore
+//! let results = chunky::conformance::run_storage_tests(|| MyStorage::open("test_dir"));
+//! assert!(results.iter().all(|result| result.passed), "{:?}", results);
+//! ```
+
+use crate::{ChunkStorage, Ident};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// The outcome of a single check of `run_storage_tests`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+    /// Name of the check
+    pub name: &'static str,
+    /// Whether the storage behaved as expected
+    pub passed: bool,
+    /// What went wrong, if the check failed
+    pub message: Option<String>,
+}
+
+/// Sizes that chunks are created with, including edge cases
+const SIZES: [usize; 5] = [0, 1, 13, 4096, 4099];
+
+fn pattern(size: usize, seed: u8) -> Vec<u8> {
+    (0..size).map(|index| (index as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+fn ensure(condition: bool, message: &str) -> Result<(), String> {
+    if condition { Ok(()) } else { Err(message.to_owned()) }
+}
+
+fn ident(check: &str, suffix: usize) -> Ident {
+    Ident(format!("conformance{}", check)).sub(suffix)
+}
+
+type Factory<'a> = &'a mut dyn FnMut() -> Box<dyn ChunkStorage>;
+type Check = fn(Factory) -> Result<(), String>;
+
+/// Run all checks against storages made by `storage_factory`, which is called
+/// at least once per check. For persistent storages, each storage it returns has to
+/// see the chunks persisted by the previous ones (e.g. by using the same directory).
+///
+/// All chunks created by the checks are forgotten again, unless a check fails midway.
+/// A check that panics counts as failed.
+pub fn run_storage_tests<S: ChunkStorage + 'static, F: FnMut() -> S>(mut storage_factory: F) -> Vec<CheckResult> {
+    let checks: [(&'static str, Check); 8] = [
+        ("created chunks have the requested length", check_create_len),
+        ("created chunks exist", check_create_exists),
+        ("chunk contents can be written and read", check_write_read),
+        ("load_or_create reports creation", check_load_or_create),
+        ("dropped chunks are persisted or gone depending on kind", check_drop_and_load),
+        ("forgotten chunks don't exist", check_forget),
+        ("list_chunks lists exactly the chunks of a group", check_list_chunks),
+        ("chunks outlive their storage", check_outlive_storage),
+    ];
+
+    let mut boxed_factory = || Box::new(storage_factory()) as Box<dyn ChunkStorage>;
+
+    checks
+        .iter()
+        .map(|&(name, check)| {
+            let outcome = catch_unwind(AssertUnwindSafe(|| check(&mut boxed_factory)))
+                .unwrap_or_else(|panic| {
+                    let message = panic.downcast_ref::<String>().cloned()
+                        .or_else(|| panic.downcast_ref::<&str>().map(|message| (*message).to_owned()))
+                        .unwrap_or_default();
+                    Err(format!("panicked: {}", message))
+                });
+            CheckResult { name, passed: outcome.is_ok(), message: outcome.err() }
+        })
+        .collect()
+}
+
+fn check_create_len(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    for (index, &size) in SIZES.iter().enumerate() {
+        let chunk = storage.create_chunk(ident("len", index), size);
+        let len = chunk.len();
+        storage.forget_chunk(chunk);
+        ensure(len == size, &format!("chunk of size {} has length {}", size, len))?;
+    }
+    Ok(())
+}
+
+fn check_create_exists(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    for (index, &size) in SIZES.iter().enumerate() {
+        let chunk_ident = ident("exists", index);
+        ensure(!storage.chunk_exists(&chunk_ident), "chunk exists before being created")?;
+        let chunk = storage.create_chunk(chunk_ident.clone(), size);
+        let exists = storage.chunk_exists(&chunk_ident);
+        let chunk_len = storage.chunk_len(&chunk_ident);
+        storage.forget_chunk(chunk);
+        ensure(exists, "created chunk doesn't exist")?;
+        ensure(chunk_len == Some(size), &format!("chunk_len of chunk of size {} is {:?}", size, chunk_len))?;
+    }
+    Ok(())
+}
+
+fn check_write_read(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    for (index, &size) in SIZES.iter().enumerate() {
+        let mut chunk = storage.create_chunk(ident("write", index), size);
+        chunk.copy_from_slice(&pattern(size, 1));
+        let matches = chunk[..] == pattern(size, 1)[..];
+        storage.forget_chunk(chunk);
+        ensure(matches, "chunk contents differ from what was written")?;
+    }
+    Ok(())
+}
+
+fn check_load_or_create(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    let (chunk, created_new) = storage.load_or_create_chunk(ident("load_or_create", 0), 64);
+    let len = chunk.len();
+    storage.forget_chunk(chunk);
+    ensure(created_new, "load_or_create_chunk didn't report creating a new chunk")?;
+    ensure(len == 64, "load_or_create_chunk created a chunk of the wrong length")
+}
+
+fn check_drop_and_load(storage_factory: Factory) -> Result<(), String> {
+    let chunk_ident = ident("persist", 0);
+    let persistent = {
+        let storage = storage_factory();
+        let mut chunk = storage.create_chunk(chunk_ident.clone(), 100);
+        chunk.copy_from_slice(&pattern(100, 2));
+        chunk.is_persistent()
+    };
+
+    let storage = storage_factory();
+    if persistent {
+        ensure(storage.chunk_exists(&chunk_ident), "dropped persistent chunk doesn't exist")?;
+        let checksum = storage.chunk_checksum(&chunk_ident);
+        let (chunk, created_new) = storage.load_or_create_chunk(chunk_ident.clone(), 100);
+        let matches = chunk[..] == pattern(100, 2)[..];
+        storage.forget_chunk(chunk);
+        ensure(!created_new, "load_or_create_chunk created a persisted chunk anew")?;
+        ensure(matches, "loaded chunk differs from what was persisted")?;
+        ensure(checksum == crate::checksum(&pattern(100, 2)), "chunk_checksum doesn't match persisted contents")
+    } else {
+        ensure(!storage.chunk_exists(&chunk_ident), "dropped transient chunk still exists")
+    }
+}
+
+fn check_forget(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    let chunk_ident = ident("forget", 0);
+    let chunk = storage.create_chunk(chunk_ident.clone(), 100);
+    storage.forget_chunk(chunk);
+    ensure(!storage.chunk_exists(&chunk_ident), "forgotten chunk still exists")?;
+    ensure(storage.chunk_len(&chunk_ident).is_none(), "forgotten chunk still has a length")?;
+
+    let chunks = (0..3).map(|index| storage.create_chunk(ident("forget", index + 1), 10)).collect();
+    storage.forget_chunks(chunks);
+    ensure((0..3).all(|index| !storage.chunk_exists(&ident("forget", index + 1))), "chunks forgotten together still exist")
+}
+
+fn check_list_chunks(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    let group = Ident::from("conformancelist");
+    let chunks = vec![
+        storage.create_chunk(group.sub(0), 10),
+        storage.create_chunk(group.sub("a"), 10),
+        // same prefix, but a different group
+        storage.create_chunk(Ident::from("conformancelistother_0"), 10),
+    ];
+    let mut listed = storage.list_chunks(&group);
+    listed.sort_by(|a, b| a.0.cmp(&b.0));
+    storage.forget_chunks(chunks);
+    ensure(listed == vec![group.sub(0), group.sub("a")], &format!("listed {:?}", listed))
+}
+
+fn check_outlive_storage(storage_factory: Factory) -> Result<(), String> {
+    let storage = storage_factory();
+    let chunk_ident = ident("outlive", 0);
+    let mut chunk = storage.create_chunk(chunk_ident.clone(), 100);
+    ::std::mem::drop(storage);
+    chunk.copy_from_slice(&pattern(100, 3));
+    let matches = chunk[..] == pattern(100, 3)[..];
+    ::std::mem::drop(chunk);
+
+    let storage = storage_factory();
+    if storage.chunk_exists(&chunk_ident) {
+        storage.forget_chunk(storage.load_chunk(chunk_ident));
+    }
+    ensure(matches, "chunk contents changed after dropping its storage")
+}
@@ -0,0 +1,281 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// Group of the inner chunks holding blobs, which can't clash with identifiers created by `sub`
+const BLOB_GROUP: &str = "~blob";
+
+/// Size of the reference count in front of the contents of each blob
+const REFS_SIZE: usize = 8;
+
+/// Size of the manifest entry of each chunk: its blob's key and its length
+const ENTRY_SIZE: usize = 16;
+
+fn blob_ident(key: u64) -> Ident {
+    Ident(format!("{}_{:016x}", BLOB_GROUP, key))
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buffer = [0u8; 8];
+    buffer.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buffer)
+}
+
+/// The inner storage, together with the inner chunks that would be lost if they were dropped
+struct State<S: ChunkStorage> {
+    inner: S,
+    transient: RefCell<HashMap<String, Chunk>>,
+}
+
+impl<S: ChunkStorage> State<S> {
+    /// Call `f` with the contents and kind of the inner chunk `ident`, if it exists
+    fn read<T, F: FnOnce(&[u8], ChunkKind) -> T>(&self, ident: Ident, f: F) -> Option<T> {
+        if let Some(chunk) = self.transient.borrow().get(&ident.0) {
+            return Some(f(chunk, chunk.kind()));
+        }
+        if !self.inner.chunk_exists(&ident) {
+            return None;
+        }
+        let chunk = self.inner.load_chunk(ident);
+        Some(f(&chunk, chunk.kind()))
+    }
+
+    /// Call `f` with the contents of the inner chunk `ident`, which is created with `size` if needed,
+    /// and flush it afterwards, returning the result of `f` and the chunk's kind
+    fn write<T, F: FnOnce(&mut [u8]) -> T>(&self, ident: Ident, size: usize, f: F) -> (T, ChunkKind) {
+        if let Some(chunk) = self.transient.borrow_mut().get_mut(&ident.0) {
+            return (f(chunk), chunk.kind());
+        }
+        let key = ident.0.clone();
+        let (mut chunk, _) = self.inner.load_or_create_chunk(ident, size);
+        let result = f(&mut chunk);
+        let kind = chunk.kind();
+        if chunk.is_persistent() {
+            self.inner.flush_chunk(&chunk);
+        } else {
+            self.transient.borrow_mut().insert(key, chunk);
+        }
+        (result, kind)
+    }
+
+    fn remove(&self, ident: Ident) {
+        let cached = self.transient.borrow_mut().remove(&ident.0);
+        if let Some(chunk) = cached {
+            self.inner.forget_chunk(chunk);
+        } else if self.inner.chunk_exists(&ident) {
+            self.inner.forget_chunk(self.inner.load_chunk(ident));
+        }
+    }
+
+    /// The blob key, length and kind of the chunk `ident`, if it exists
+    fn entry(&self, ident: &Ident) -> Option<(u64, usize, ChunkKind)> {
+        self.read(ident.clone(), |entry, kind| {
+            let len = usize::try_from(read_u64(&entry[8..])).expect("Chunk too large for this platform");
+            (read_u64(entry), len, kind)
+        })
+    }
+
+    /// Add a reference to the blob with `contents`, storing it if it doesn't exist yet,
+    /// and return its key
+    fn store(&self, contents: &[u8]) -> u64 {
+        // the key is the checksum of the contents, or the next free one after it if that's taken
+        // by different contents. Removing blobs can leave duplicates behind a freed key,
+        // which only costs space, since entries refer to the exact key of their blob
+        let mut key = crate::checksum(contents);
+        loop {
+            let matches = self.read(blob_ident(key), |blob, _| &blob[REFS_SIZE..] == contents);
+            match matches {
+                Some(false) => key = key.wrapping_add(1),
+                Some(true) => {
+                    self.write(blob_ident(key), REFS_SIZE + contents.len(), |blob| {
+                        let refs = read_u64(blob) + 1;
+                        blob[..REFS_SIZE].copy_from_slice(&refs.to_le_bytes());
+                    });
+                    return key;
+                }
+                None => {
+                    self.write(blob_ident(key), REFS_SIZE + contents.len(), |blob| {
+                        blob[..REFS_SIZE].copy_from_slice(&1u64.to_le_bytes());
+                        blob[REFS_SIZE..].copy_from_slice(contents);
+                    });
+                    return key;
+                }
+            }
+        }
+    }
+
+    /// Remove a reference to the blob `key`, removing the blob if it was the last one
+    fn release(&self, key: u64) {
+        let len = self.read(blob_ident(key), |blob, _| blob.len()).expect("Missing blob");
+        let (refs, _) = self.write(blob_ident(key), len, |blob| {
+            let refs = read_u64(blob) - 1;
+            blob[..REFS_SIZE].copy_from_slice(&refs.to_le_bytes());
+            refs
+        });
+        if refs == 0 {
+            self.remove(blob_ident(key));
+        }
+    }
+
+    /// Point the chunk `ident` at the blob with `contents`, returning the kind of its entry
+    fn persist(&self, ident: &Ident, contents: &[u8]) -> ChunkKind {
+        let old_entry = self.entry(ident);
+        let key = self.store(contents);
+        let (_, kind) = self.write(ident.clone(), ENTRY_SIZE, |entry| {
+            entry[..8].copy_from_slice(&key.to_le_bytes());
+            entry[8..].copy_from_slice(&(contents.len() as u64).to_le_bytes());
+        });
+        if let Some((old_key, _, _)) = old_entry {
+            self.release(old_key);
+        }
+        kind
+    }
+
+    fn forget(&self, ident: &Ident) {
+        if let Some((key, _, _)) = self.entry(ident) {
+            self.release(key);
+            self.remove(ident.clone());
+        }
+    }
+}
+
+/// A `ChunkStorage` decorator that stores the contents of chunks in an inner storage
+/// deduplicated by content, so identical chunks (such as zeroed ones) take up space only once.
+///
+/// Each chunk is stored as a small manifest entry pointing to a reference-counted blob,
+/// named after the checksum of its contents. Chunks live in memory while loaded, as a private
+/// copy of their blob, so mutating one never affects others sharing the blob. Only when
+/// a chunk is flushed or dropped are its contents stored again, as a new or existing blob.
+///
+/// Chunks have the kind of the inner storage: if it's transient, dropped chunks are gone.
+pub struct Dedup<S: ChunkStorage + 'static> {
+    state: Rc<State<S>>,
+}
+
+struct DedupHandle<S: ChunkStorage + 'static> {
+    ident: Ident,
+    /// Never resized, so the chunk can point into it
+    contents: Vec<u8>,
+    kind: ChunkKind,
+    state: Rc<State<S>>,
+    /// Set by `forget_chunk`, so the chunk isn't stored again after its entry is removed
+    forgotten: bool,
+}
+
+impl<S: ChunkStorage + 'static> Drop for DedupHandle<S> {
+    fn drop(&mut self) {
+        if self.forgotten {
+            return;
+        }
+        match self.kind {
+            ChunkKind::Persistent => {
+                self.state.persist(&self.ident, &self.contents);
+            }
+            ChunkKind::Transient => self.state.forget(&self.ident),
+        }
+    }
+}
+
+impl<S: ChunkStorage + 'static> Dedup<S> {
+    /// Wrap `inner`, using it to store the manifest entries and blobs of all chunks
+    pub fn new(inner: S) -> Dedup<S> {
+        Dedup {
+            state: Rc::new(State { inner, transient: RefCell::new(HashMap::new()) }),
+        }
+    }
+
+    /// The number of distinct blobs stored in the inner storage
+    pub fn blob_count(&self) -> usize {
+        self.state.inner.list_chunks(&Ident(BLOB_GROUP.to_owned())).len()
+    }
+
+    fn chunk(&self, ident: Ident, contents: Vec<u8>, kind: ChunkKind) -> Chunk {
+        let mut handle = Box::new(DedupHandle {
+            ident,
+            contents,
+            kind,
+            state: Rc::clone(&self.state),
+            forgotten: false,
+        });
+        let (ptr, len) = (handle.contents.as_mut_ptr(), handle.contents.len());
+        unsafe { Chunk::from_raw_parts(ptr, len, kind, handle) }
+    }
+
+    fn forget_handle(chunk: Chunk) -> ::std::io::Result<Ident> {
+        let mut handle = chunk._handle_to_drop.downcast::<DedupHandle<S>>()
+            .map_err(|_| crate::foreign_chunk_error("Dedup storage"))?;
+        handle.forgotten = true;
+        Ok(handle.ident.clone())
+    }
+}
+
+impl<S: ChunkStorage + 'static> ChunkStorage for Dedup<S> {
+    /// Stores the zeroed contents right away, sharing one blob between all zeroed chunks of a size
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        let contents = vec![0; size];
+        let kind = self.state.persist(&ident, &contents);
+        self.chunk(ident, contents, kind)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        if self.chunk_exists(&ident) {
+            (self.load_chunk(ident), false)
+        } else {
+            (self.create_chunk(ident, size), true)
+        }
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let (key, _, kind) = self.state.entry(&ident)
+            .unwrap_or_else(|| panic!("Can't load chunk {}", ident.0));
+        let contents = self.state.read(blob_ident(key), |blob, _| blob[REFS_SIZE..].to_vec())
+            .unwrap_or_else(|| panic!("Missing blob of chunk {}", ident.0));
+        self.chunk(ident, contents, kind)
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        match Self::forget_handle(chunk) {
+            Ok(ident) => self.state.forget(&ident),
+            Err(_) => crate::warn_foreign_chunk("Dedup storage"),
+        }
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let ident = Self::forget_handle(chunk)?;
+        self.state.forget(&ident);
+        Ok(())
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.state.entry(ident).is_some()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.state.entry(ident).map(|(_, len, _)| len)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.state.inner.list_chunks(group)
+            .into_iter()
+            .filter(|ident| !ident.belongs_to(&Ident(BLOB_GROUP.to_owned())))
+            .collect()
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let (key, _, _) = self.state.entry(ident)
+            .unwrap_or_else(|| panic!("Can't read chunk {}", ident.0));
+        self.state.read(blob_ident(key), |blob, _| crate::checksum(&blob[REFS_SIZE..]))
+            .unwrap_or_else(|| panic!("Missing blob of chunk {}", ident.0))
+    }
+
+    /// Stores the chunk's current contents as a new or existing blob
+    fn flush_chunk(&self, chunk: &Chunk) {
+        let handle = match chunk._handle_to_drop.downcast_ref::<DedupHandle<S>>() {
+            Some(handle) => handle,
+            None => return crate::warn_foreign_flush("Dedup storage"),
+        };
+        self.state.persist(&handle.ident, &handle.contents);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{Chunk, ChunkStorage, Ident};
+use crate::value::{Portable, PortableValue};
+use crate::shared::{SendableStorage, Shared};
+use core::convert::TryFrom;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Positions of the first item and one past the last item. Pushing to the front
+/// moves to negative positions, so positions are signed.
+#[derive(Clone, Copy)]
+struct DequeState {
+    front: isize,
+    back: isize,
+}
+
+/// Persisted as two consecutive `u64`s (in two's complement), in field order
+impl Portable for DequeState {
+    const SIZE: usize = 2 * <u64 as Portable>::SIZE;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        let (front_bytes, back_bytes) = bytes.split_at_mut(<u64 as Portable>::SIZE);
+        (self.front as i64 as u64).encode(front_bytes);
+        (self.back as i64 as u64).encode(back_bytes);
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let (front_bytes, back_bytes) = bytes.split_at(<u64 as Portable>::SIZE);
+        let decode_position = |bytes| {
+            isize::try_from(u64::decode(bytes) as i64).expect("Persisted position doesn't fit in isize")
+        };
+        DequeState {
+            front: decode_position(front_bytes),
+            back: decode_position(back_bytes),
+        }
+    }
+}
+
+/// A double-ended queue of fixed-size items, which can be pushed and popped at both ends.
+///
+/// The chunk at number `n` holds the items at positions `[n * items_per_chunk, (n + 1) * items_per_chunk)`,
+/// chunks are created when an end moves into them and dropped when both ends moved past them.
+/// Chunks exist for all positions between the front and back, including the positions at the ends.
+pub struct Deque {
+    ident: Ident,
+    chunk_size: usize,
+    item_size: usize,
+    /// Number of the chunk at the front of `chunks`
+    first_chunk: isize,
+    chunks: VecDeque<Chunk>,
+    state: PortableValue<DequeState>,
+    chunks_to_drop: Vec<(isize, Chunk)>,
+    storage: Rc<dyn ChunkStorage>,
+}
+
+impl Deque {
+    /// Create a new deque given a chunk group identifier, chunk size and item size
+    pub fn new(ident: Ident, chunk_size: usize, item_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        assert!(item_size > 0, "Deque items need to have a nonzero size");
+        let mut deque = Deque {
+            state: PortableValue::load_or_default(ident.sub("d_state"), DequeState { front: 0, back: 0 }, Rc::clone(&storage)),
+            ident,
+            chunk_size: ::core::cmp::max(chunk_size, item_size),
+            item_size,
+            first_chunk: 0,
+            chunks: VecDeque::new(),
+            chunks_to_drop: Vec::new(),
+            storage,
+        };
+
+        let state = deque.state.get();
+        deque.first_chunk = deque.chunk_number(state.front);
+        for chunk_number in deque.first_chunk..=deque.chunk_number(state.back) {
+            let (chunk, _) = deque.storage.load_or_create_chunk(deque.ident.sub(chunk_number), deque.chunk_size);
+            deque.chunks.push_back(chunk);
+        }
+
+        deque
+    }
+
+    /// Create a new deque like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, item_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, item_size, storage))
+    }
+
+    fn items_per_chunk(&self) -> isize {
+        (self.chunk_size / self.item_size) as isize
+    }
+
+    fn chunk_number(&self, position: isize) -> isize {
+        position.div_euclid(self.items_per_chunk())
+    }
+
+    fn last_chunk(&self) -> isize {
+        self.first_chunk + self.chunks.len() as isize - 1
+    }
+
+    /// Number of items in the deque
+    pub fn len(&self) -> usize {
+        let state = self.state.get();
+        (state.back - state.front) as usize
+    }
+
+    /// Is the deque empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn ptr_at(&self, position: isize) -> *mut u8 {
+        let chunk = &self.chunks[(self.chunk_number(position) - self.first_chunk) as usize];
+        let offset = position.rem_euclid(self.items_per_chunk()) as usize * self.item_size;
+        unsafe { chunk.ptr.add(offset) }
+    }
+
+    /// Get a chunk that is about to become part of the deque, reusing it
+    /// if it was only just dropped, so no two chunks with the same identifier exist.
+    /// Otherwise it is loaded if it still exists, because it was left over from an earlier
+    /// instance that didn't drop it before being dropped itself.
+    fn take_or_create_chunk(&mut self, chunk_number: isize) -> Chunk {
+        match self.chunks_to_drop.iter().position(|(number, _)| *number == chunk_number) {
+            Some(index) => self.chunks_to_drop.swap_remove(index).1,
+            None => self.storage.load_or_create_chunk(self.ident.sub(chunk_number), self.chunk_size).0,
+        }
+    }
+
+    /// Add an item at the back, returning a pointer that the item can be written to
+    pub fn push_back(&mut self) -> *mut u8 {
+        let mut state = self.state.get();
+        let item_ptr = self.ptr_at(state.back);
+        state.back += 1;
+        if self.chunk_number(state.back) > self.last_chunk() {
+            let chunk = self.take_or_create_chunk(self.last_chunk() + 1);
+            self.chunks.push_back(chunk);
+        }
+        self.state.set(state);
+        item_ptr
+    }
+
+    /// Add an item at the front, returning a pointer that the item can be written to
+    pub fn push_front(&mut self) -> *mut u8 {
+        let mut state = self.state.get();
+        state.front -= 1;
+        if self.chunk_number(state.front) < self.first_chunk {
+            let chunk = self.take_or_create_chunk(self.first_chunk - 1);
+            self.chunks.push_front(chunk);
+            self.first_chunk -= 1;
+        }
+        self.state.set(state);
+        self.ptr_at(state.front)
+    }
+
+    /// Remove the item at the front, returning a pointer to it, unless the deque is empty
+    ///
+    /// # Safety
+    ///
+    /// The pointer stays valid until the next call to `drop_old_chunks`.
+    pub unsafe fn pop_front(&mut self) -> Option<*const u8> {
+        let mut state = self.state.get();
+        if state.front == state.back {
+            return None;
+        }
+        let item_ptr = self.ptr_at(state.front);
+        state.front += 1;
+        if self.chunk_number(state.front) > self.first_chunk {
+            let chunk = self.chunks.pop_front().expect("should have first chunk");
+            self.chunks_to_drop.push((self.first_chunk, chunk));
+            self.first_chunk += 1;
+        }
+        self.state.set(state);
+        Some(item_ptr)
+    }
+
+    /// Remove the item at the back, returning a pointer to it, unless the deque is empty
+    ///
+    /// # Safety
+    ///
+    /// The pointer stays valid until the next call to `drop_old_chunks`.
+    pub unsafe fn pop_back(&mut self) -> Option<*const u8> {
+        let mut state = self.state.get();
+        if state.front == state.back {
+            return None;
+        }
+        state.back -= 1;
+        if self.chunk_number(state.back) < self.last_chunk() {
+            let chunk = self.chunks.pop_back().expect("should have last chunk");
+            self.chunks_to_drop.push((self.last_chunk() + 1, chunk));
+        }
+        self.state.set(state);
+        Some(self.ptr_at(state.back))
+    }
+
+    /// Iterate over pointers to all items, from front to back, or back to front with `rev()`
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = *const u8> + '_ {
+        let state = self.state.get();
+        (state.front..state.back).map(move |position| self.ptr_at(position) as *const u8)
+    }
+
+    /// Delete chunks which both ends have moved past
+    ///
+    /// # Safety
+    ///
+    /// Invalidates all pointers returned by `pop_front` and `pop_back` so far.
+    pub unsafe fn drop_old_chunks(&mut self) {
+        for (_, chunk) in self.chunks_to_drop.drain(..) {
+            self.storage.forget_chunk(chunk);
+        }
+    }
+}
+
+/// Chunks which both ends have moved past are forgotten, as by `drop_old_chunks`
+impl Drop for Deque {
+    fn drop(&mut self) {
+        for (_, chunk) in self.chunks_to_drop.drain(..) {
+            self.storage.forget_chunk(chunk);
+        }
+    }
+}
+
+impl crate::Flushable for Deque {
+    fn flush(&self) {
+        for chunk in &self.chunks {
+            self.storage.flush_chunk(chunk);
+        }
+        self.state.flush(&*self.storage);
+    }
+}
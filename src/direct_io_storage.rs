@@ -0,0 +1,247 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::shared::SendableStorage;
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Block size assumed by `DirectIoStorage::new`, which most file systems and devices accept
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// A `ChunkStorage` that keeps chunks in memory and persists them into files using direct
+/// (unbuffered) I/O, which bypasses the OS page cache, unlike `MmapStorage`.
+///
+/// This is meant for writing out large collections at once (such as when saving),
+/// without evicting other processes' data from the page cache.
+/// A chunk is read in completely when it is loaded, and written out completely
+/// when it is flushed or dropped, padded to a multiple of the block size in memory.
+///
+/// Where direct I/O is not supported (by the platform or the file system), or the block size
+/// doesn't meet its alignment requirements, files are read and written with buffered I/O instead.
+pub struct DirectIoStorage {
+    directory: PathBuf,
+    block_size: usize,
+}
+
+struct DirectIoStorageHandle {
+    ptr: *mut u8,
+    len: usize,
+    layout: Layout,
+    file_path: PathBuf,
+    /// Set by `forget_chunk`, so the chunk isn't written out again right before its file is removed
+    forgotten: bool,
+}
+
+impl DirectIoStorageHandle {
+    fn write_out(&self) -> ::std::io::Result<()> {
+        let padded = unsafe { ::std::slice::from_raw_parts(self.ptr, self.layout.size()) };
+        write_file(&self.file_path, padded, self.len)
+    }
+}
+
+impl Drop for DirectIoStorageHandle {
+    fn drop(&mut self) {
+        if !self.forgotten {
+            self.write_out()
+                .unwrap_or_else(|err| panic!("Couldn't write file {}: {}", self.file_path.to_string_lossy(), err));
+        }
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
+
+impl DirectIoStorage {
+    /// Create a new DirectIoStorage which will put files in `directory`, creating it if needed
+    pub fn new(directory: PathBuf) -> DirectIoStorage {
+        Self::with_block_size(directory, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a new DirectIoStorage like `new`, which aligns and pads chunks to `block_size`,
+    /// which has to be a power of two
+    pub fn with_block_size(directory: PathBuf, block_size: usize) -> DirectIoStorage {
+        assert!(block_size.is_power_of_two(), "Block size has to be a power of two");
+        ::std::fs::create_dir_all(&directory)
+            .unwrap_or_else(|_| panic!("Can't create directory {}", directory.to_string_lossy()));
+        DirectIoStorage { directory, block_size }
+    }
+
+    fn file_path(&self, ident: &Ident) -> PathBuf {
+        self.directory.join(&ident.0)
+    }
+
+    /// Allocate zeroed, block-aligned memory for a chunk of `len` bytes
+    fn allocate_chunk(&self, file_path: PathBuf, len: usize) -> Chunk {
+        let padded_len = ::std::cmp::max(len, 1).next_multiple_of(self.block_size);
+        let layout = Layout::from_size_align(padded_len, self.block_size).expect("Chunk size too large");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+
+        let handle = DirectIoStorageHandle { ptr, len, layout, file_path, forgotten: false };
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, len, layout.size(), ChunkKind::Persistent, Box::new(handle)) }
+    }
+
+    fn handle(chunk: &Chunk) -> &DirectIoStorageHandle {
+        chunk._handle_to_drop.downcast_ref::<DirectIoStorageHandle>().expect("DirectIoStorage got handed a foreign chunk.")
+    }
+}
+
+impl ChunkStorage for DirectIoStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        let file_path = self.file_path(&ident);
+        self.try_create_chunk(ident, size)
+            .unwrap_or_else(|err| panic!("Can't create file {}: {}", file_path.to_string_lossy(), err))
+    }
+
+    /// Writes out the zeroed chunk right away, failing if that's not possible
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        let file_path = self.file_path(&ident);
+        OpenOptions::new().write(true).create_new(true).open(&file_path)?;
+        let chunk = self.allocate_chunk(file_path, size);
+        if let Err(err) = Self::handle(&chunk).write_out() {
+            self.forget_chunk(chunk);
+            return Err(err);
+        }
+        Ok(chunk)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        if self.chunk_exists(&ident) {
+            (self.load_chunk(ident), false)
+        } else {
+            (self.create_chunk(ident, size), true)
+        }
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let file_path = self.file_path(&ident);
+        let len = self.chunk_len(&ident)
+            .unwrap_or_else(|| panic!("Can't load file {}", file_path.to_string_lossy()));
+        let chunk = self.allocate_chunk(file_path.clone(), len);
+        let handle = Self::handle(&chunk);
+        let padded = unsafe { ::std::slice::from_raw_parts_mut(handle.ptr, handle.layout.size()) };
+        read_file(&file_path, padded, len)
+            .unwrap_or_else(|err| panic!("Can't read file {}: {}", file_path.to_string_lossy(), err));
+        chunk
+    }
+
+    /// Deallocate a chunk and delete its file (unlike Drop, which writes it out)
+    fn forget_chunk(&self, chunk: Chunk) {
+        let file_path = match chunk._handle_to_drop.downcast_ref::<DirectIoStorageHandle>() {
+            Some(handle) => handle.file_path.clone(),
+            None => return crate::warn_foreign_chunk("DirectIoStorage"),
+        };
+        self.try_forget_chunk(chunk).unwrap_or_else(|_| panic!("Couldn't remove file {}", file_path.to_string_lossy()));
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let mut handle = chunk._handle_to_drop.downcast::<DirectIoStorageHandle>()
+            .map_err(|_| crate::foreign_chunk_error("DirectIoStorage"))?;
+        handle.forgotten = true;
+        let file_path = handle.file_path.clone();
+        ::std::mem::drop(handle);
+        ::std::fs::remove_file(&file_path)
+    }
+
+    fn flush_chunk(&self, chunk: &Chunk) {
+        let handle = match chunk._handle_to_drop.downcast_ref::<DirectIoStorageHandle>() {
+            Some(handle) => handle,
+            None => return crate::warn_foreign_flush("DirectIoStorage"),
+        };
+        handle.write_out()
+            .unwrap_or_else(|err| panic!("Couldn't write file {}: {}", handle.file_path.to_string_lossy(), err));
+    }
+
+    fn page_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.file_path(ident).is_file()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        ::std::fs::metadata(self.file_path(ident))
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len() as usize)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        match ::std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .map(Ident)
+                .filter(|ident| ident.belongs_to(group))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let file_path = self.file_path(ident);
+        let bytes = ::std::fs::read(&file_path)
+            .unwrap_or_else(|_| panic!("Can't read file {}", file_path.to_string_lossy()));
+        crate::checksum(&bytes)
+    }
+}
+
+/// Open `file_path` for direct I/O, or for buffered I/O where that's not supported
+fn open(file_path: &Path, write: bool) -> ::std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(!write).write(write);
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut direct_options = options.clone();
+        direct_options.custom_flags(libc::O_DIRECT);
+        if let Ok(file) = direct_options.open(file_path) {
+            return Ok(file);
+        }
+    }
+
+    options.open(file_path)
+}
+
+/// Write all of the block-aligned `padded` buffer, which direct I/O requires,
+/// then cut the file back to the chunk's actual length `len`
+fn write_file(file_path: &Path, padded: &[u8], len: usize) -> ::std::io::Result<()> {
+    let result = open(file_path, true).and_then(|mut file| file.write_all(padded));
+    if let Err(err) = result {
+        // direct I/O can still reject a write, e.g. if the block size is too small for the device
+        if err.kind() != ::std::io::ErrorKind::InvalidInput {
+            return Err(err);
+        }
+        OpenOptions::new().write(true).open(file_path)?.write_all(padded)?;
+    }
+    OpenOptions::new().write(true).open(file_path)?.set_len(len as u64)
+}
+
+/// Read a file of length `len` into the block-aligned `padded` buffer. Reads are done in
+/// whole blocks, which direct I/O requires, the last of which just ends early at the end of the file.
+fn read_file(file_path: &Path, padded: &mut [u8], len: usize) -> ::std::io::Result<()> {
+    let result = open(file_path, false).and_then(|mut file| read_up_to(&mut file, padded, len));
+    match result {
+        Err(ref err) if err.kind() == ::std::io::ErrorKind::InvalidInput => {
+            read_up_to(&mut File::open(file_path)?, padded, len)
+        }
+        result => result,
+    }
+}
+
+fn read_up_to(file: &mut File, padded: &mut [u8], len: usize) -> ::std::io::Result<()> {
+    let mut read = 0;
+    while read < len {
+        match file.read(&mut padded[read..])? {
+            0 => return Err(::std::io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+    Ok(())
+}
+
+/// Direct I/O chunk handles own plain allocations, which can be used from any thread
+unsafe impl SendableStorage for DirectIoStorage {}
@@ -0,0 +1,114 @@
+use crate::{ChunkStorage, Ident};
+use crate::arena::ArenaIndex;
+use crate::typed_arena::TypedArena;
+use crate::vector::Vector;
+use crate::shared::{SendableStorage, Shared};
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// Refers to an item within a `GenerationalArena`, remembering the generation of its slot
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GenerationalIndex(pub ArenaIndex, pub u32);
+
+/// A `TypedArena` which detects stale indices, by keeping a generation counter for each slot
+/// which is bumped whenever the item in that slot is removed or moved away by `swap_remove`.
+///
+/// Generations are kept for all slots ever used, so they stay valid when slots are reused.
+pub struct GenerationalArena<T> {
+    items: TypedArena<T>,
+    generations: Vector<u32>,
+}
+
+impl<T> GenerationalArena<T> {
+    /// Create a new generational arena given a chunk group identifier and chunk size
+    pub fn new(ident: Ident, chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        GenerationalArena {
+            generations: Vector::new(ident.sub("generations"), chunk_size, Rc::clone(&storage)),
+            items: TypedArena::new(ident, chunk_size, storage),
+        }
+    }
+
+    /// Create a new generational arena like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, storage))
+    }
+
+    /// Number of items in the arena
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Is the arena empty?
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Get the current index of the item in slot `index`, if there is one
+    pub fn index_at(&self, index: ArenaIndex) -> Option<GenerationalIndex> {
+        if self.items.contains(index) {
+            Some(GenerationalIndex(index, *self.generations.at(index.0).expect("should have generation")))
+        } else {
+            None
+        }
+    }
+
+    fn is_current(&self, index: GenerationalIndex) -> bool {
+        self.index_at(index.0) == Some(index)
+    }
+
+    fn bump_generation(&mut self, index: ArenaIndex) {
+        let generation = self.generations.at_mut(index.0).expect("should have generation");
+        *generation = generation.wrapping_add(1);
+    }
+
+    /// Add an item to the end, returning its index
+    pub fn push(&mut self, item: T) -> GenerationalIndex {
+        let index = self.items.push(item);
+        if self.generations.len() <= index.0 {
+            self.generations.push(0);
+        }
+        self.index_at(index).expect("should have just pushed item")
+    }
+
+    /// Get a reference to the item at `index`, unless it was removed or moved since
+    pub fn get(&self, index: GenerationalIndex) -> Option<&T> {
+        if self.is_current(index) {
+            self.items.get(index.0)
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable reference to the item at `index`, unless it was removed or moved since
+    pub fn get_mut(&mut self, index: GenerationalIndex) -> Option<&mut T> {
+        if self.is_current(index) {
+            self.items.get_mut(index.0)
+        } else {
+            None
+        }
+    }
+
+    /// Remove and return the item at `index`, unless it was removed or moved since.
+    ///
+    /// The last item is moved to the removed slot, so indices to it become stale as well;
+    /// its new index can be looked up with `index_at`.
+    pub fn swap_remove(&mut self, index: GenerationalIndex) -> Option<T> {
+        if !self.is_current(index) {
+            return None;
+        }
+
+        let last_index = ArenaIndex(self.len() - 1);
+        self.bump_generation(index.0);
+        if last_index != index.0 {
+            self.bump_generation(last_index);
+        }
+        self.items.swap_remove(index.0)
+    }
+}
+
+impl<T> crate::Flushable for GenerationalArena<T> {
+    fn flush(&self) {
+        self.items.flush();
+        self.generations.flush();
+    }
+}
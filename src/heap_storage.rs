@@ -1,23 +1,194 @@
-use crate::{Chunk, ChunkStorage, Ident};
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+#[cfg(feature = "std")]
+use crate::shared::SendableStorage;
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use crate::live_chunks::LiveChunks;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+
+/// Addresses of the pooled buffers of forgotten chunks, by allocation size
+type PoolMap = BTreeMap<usize, Vec<usize>>;
+
+/// Without `std`, there is no lock to share the live chunks between threads
+#[cfg(feature = "std")]
+type Locked<T> = alloc::sync::Arc<std::sync::Mutex<T>>;
+#[cfg(not(feature = "std"))]
+type Locked<T> = alloc::rc::Rc<core::cell::RefCell<T>>;
+
+
+#[cfg(feature = "std")]
+fn lock<T>(locked: &Locked<T>) -> std::sync::MutexGuard<'_, T> {
+    locked.lock().unwrap()
+}
+
+#[cfg(not(feature = "std"))]
+fn lock<T>(locked: &Locked<T>) -> core::cell::RefMut<'_, T> {
+    locked.borrow_mut()
+}
 
 /// A `ChunkStorage` that allocates chunks on the heap
-pub struct HeapStorage;
+pub struct HeapStorage {
+    config: HeapStorageConfig,
+    live_chunks: Locked<LiveChunks>,
+    pool: Locked<PoolMap>,
+}
+
+/// How a `HeapStorage` allocates its chunks
+#[derive(Copy, Clone, Debug)]
+pub struct HeapStorageConfig {
+    /// Whether new chunks are filled with zeros, rather than left uninitialized
+    pub zero_on_create: bool,
+    /// Minimum alignment of all chunks, which has to be a power of two
+    pub min_align: usize,
+    /// How many buffers of forgotten chunks to keep per allocation size, to reuse them
+    /// for new chunks instead of allocating. 0 (the default) disables pooling.
+    pub pool_limit: usize,
+}
+
+impl Default for HeapStorageConfig {
+    fn default() -> Self {
+        HeapStorageConfig {
+            zero_on_create: false,
+            min_align: 16,
+            pool_limit: 0,
+        }
+    }
+}
+
+/// Builds a `HeapStorage` with a custom `HeapStorageConfig`
+#[derive(Default)]
+pub struct HeapStorageBuilder {
+    config: HeapStorageConfig,
+}
+
+impl HeapStorageBuilder {
+    /// Set whether new chunks are filled with zeros
+    pub fn zero_on_create(mut self, zero_on_create: bool) -> Self {
+        self.config.zero_on_create = zero_on_create;
+        self
+    }
+
+    /// Set the minimum alignment of all chunks, which has to be a power of two
+    pub fn min_align(mut self, min_align: usize) -> Self {
+        self.config.min_align = min_align;
+        self
+    }
+
+    /// Set how many buffers of forgotten chunks to keep per allocation size for reuse
+    pub fn pool_limit(mut self, pool_limit: usize) -> Self {
+        self.config.pool_limit = pool_limit;
+        self
+    }
+
+    /// Create the configured `HeapStorage`
+    pub fn build(self) -> HeapStorage {
+        HeapStorage::with_config(self.config)
+    }
+}
+
+struct HeapStorageHandle {
+    /// Null once the buffer was taken by the pool
+    ptr: *mut u8,
+    /// The address of the buffer, even after it was taken by the pool
+    address: usize,
+    layout: Layout,
+    ident: Ident,
+    live_chunks: Locked<LiveChunks>,
+}
+
+impl Drop for HeapStorageHandle {
+    fn drop(&mut self) {
+        // the buffer of a pooled chunk is owned by the pool
+        if !self.ptr.is_null() {
+            unsafe { dealloc(self.ptr, self.layout) };
+        }
+        lock(&self.live_chunks).remove(&self.ident, self.address);
+    }
+}
 
 impl HeapStorage {
-    /// Get an instance of `HeapStorage` - it doesn't have any configuration
-    pub fn new() -> HeapStorage{
-        HeapStorage
+    /// Get an instance of `HeapStorage` with the default configuration
+    pub fn new() -> HeapStorage {
+        Self::with_config(HeapStorageConfig::default())
+    }
+
+    /// Get an instance of `HeapStorage` with the given configuration
+    pub fn with_config(config: HeapStorageConfig) -> HeapStorage {
+        assert!(config.min_align.is_power_of_two(), "Minimum alignment has to be a power of two");
+        HeapStorage {
+            config,
+            live_chunks: Locked::default(),
+            pool: Locked::default(),
+        }
+    }
+
+    /// Number of buffers of forgotten chunks currently kept for reuse
+    pub fn pooled_buffers(&self) -> usize {
+        lock(&self.pool).values().map(Vec::len).sum()
+    }
+
+    fn layout(&self, size: usize) -> Layout {
+        // zero-sized allocations aren't allowed, so allocate at least one byte
+        Layout::from_size_align(::core::cmp::max(size, 1), self.config.min_align)
+            .expect("Invalid chunk size")
+    }
+
+
+    /// Start building a `HeapStorage` with a custom configuration
+    pub fn builder() -> HeapStorageBuilder {
+        HeapStorageBuilder::default()
+    }
+}
+
+impl Default for HeapStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HeapStorage {
+    fn drop(&mut self) {
+        for (size, ptrs) in ::core::mem::take(&mut *lock(&self.pool)) {
+            let layout = self.layout(size);
+            for ptr in ptrs {
+                unsafe { dealloc(ptr as *mut u8, layout) };
+            }
+        }
     }
 }
 
 impl ChunkStorage for HeapStorage {
-    fn create_chunk(&self, _ident: Ident, size: usize) -> Chunk {
-        let mut vec = Vec::with_capacity(size);
-        Chunk {
-            ptr: vec.as_mut_ptr(),
-            len: vec.capacity(),
-            _handle_to_drop: Box::new(vec)
+    /// Reuses a pooled buffer of the same allocation size if there is one,
+    /// zeroing it again if configured to zero new chunks
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        let layout = self.layout(size);
+        let pooled = lock(&self.pool).get_mut(&layout.size()).and_then(Vec::pop);
+        let ptr = unsafe {
+            match pooled {
+                Some(ptr) => {
+                    let ptr = ptr as *mut u8;
+                    if self.config.zero_on_create {
+                        ::core::ptr::write_bytes(ptr, 0, layout.size());
+                    }
+                    ptr
+                }
+                None if self.config.zero_on_create => alloc_zeroed(layout),
+                None => alloc(layout),
+            }
+        };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
         }
+        lock(&self.live_chunks).insert(&ident, ptr as usize, size);
+        let handle = HeapStorageHandle {
+            ptr,
+            address: ptr as usize,
+            layout,
+            ident,
+            live_chunks: Locked::clone(&self.live_chunks),
+        };
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, size, layout.size(), ChunkKind::Transient, Box::new(handle)) }
     }
 
     fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
@@ -28,7 +199,65 @@ impl ChunkStorage for HeapStorage {
         panic!("can't load memory based chunks");
     }
 
-    fn forget_chunk(&self, chunk: Chunk) {
-        ::std::mem::drop(chunk);
+    /// Keeps the chunk's buffer for reuse if pooling is enabled and the pool of its size isn't full.
+    /// In debug builds, the chunk is overwritten with `FORGOTTEN_CHUNK_POISON` first.
+    fn forget_chunk(&self, mut chunk: Chunk) {
+        if !chunk._handle_to_drop.is::<HeapStorageHandle>() {
+            return crate::warn_foreign_chunk("HeapStorage");
+        }
+        if cfg!(debug_assertions) {
+            chunk.fill(crate::FORGOTTEN_CHUNK_POISON);
+        }
+        if self.config.pool_limit == 0 {
+            return;
+        }
+        if let Ok(mut handle) = chunk._handle_to_drop.downcast::<HeapStorageHandle>() {
+            let mut pool = lock(&self.pool);
+            let pooled = pool.entry(handle.layout.size()).or_default();
+            if pooled.len() < self.config.pool_limit && handle.layout.align() == self.config.min_align {
+                pooled.push(::core::mem::replace(&mut handle.ptr, ::core::ptr::null_mut()) as usize);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        if !chunk._handle_to_drop.is::<HeapStorageHandle>() {
+            return Err(crate::foreign_chunk_error("HeapStorage"));
+        }
+        self.forget_chunk(chunk);
+        Ok(())
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        lock(&self.live_chunks).latest(ident).is_some()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        lock(&self.live_chunks).latest(ident).map(|(_, len)| len)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        lock(&self.live_chunks).list(group)
     }
-}
\ No newline at end of file
+
+    /// Chunks are aligned to the configured minimum alignment, which is treated as the page size
+    fn page_size(&self) -> usize {
+        self.config.min_align
+    }
+
+    fn chunk_kind(&self) -> ChunkKind {
+        ChunkKind::Transient
+    }
+
+    /// Heap chunks only exist while they are alive, so this hashes the live buffer
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let (ptr, len) = lock(&self.live_chunks).latest(ident)
+            .unwrap_or_else(|| panic!("No live heap chunk {}", ident.0));
+        crate::checksum(unsafe { ::core::slice::from_raw_parts(ptr as *const u8, len) })
+    }
+}
+
+/// Heap chunk handles own plain allocations, which can be used from any thread
+#[cfg(feature = "std")]
+unsafe impl SendableStorage for HeapStorage {}
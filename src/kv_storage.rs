@@ -0,0 +1,260 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::shared::SendableStorage;
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use redb::{Database, TableDefinition};
+
+/// The table of a `KvStorage`'s database which holds the contents of all chunks, by identifier
+const CHUNKS: TableDefinition<&str, &[u8]> = TableDefinition::new("chunks");
+
+/// Alignment of all chunks, like the default of `HeapStorage`
+const CHUNK_ALIGN: usize = 16;
+
+/// Address and length of each live chunk, by identifier
+type LiveChunks = Arc<Mutex<BTreeMap<String, (usize, usize)>>>;
+
+fn io_error<E: Into<redb::Error>>(err: E) -> io::Error {
+    io::Error::other(err.into())
+}
+
+/// A `ChunkStorage` that persists chunks as entries of an embedded key-value store
+/// ([redb](https://docs.rs/redb)), keyed by their identifier, instead of as one file per chunk.
+///
+/// Chunks live on the heap while loaded, and are written into the store when they are
+/// flushed or dropped, each in a transaction of its own. `flush` writes all live chunks
+/// in one transaction, so the store never holds a mix of their old and new contents.
+pub struct KvStorage {
+    db: Arc<Database>,
+    live_chunks: LiveChunks,
+}
+
+struct KvStorageHandle {
+    ptr: *mut u8,
+    len: usize,
+    layout: Layout,
+    ident: Ident,
+    db: Arc<Database>,
+    live_chunks: LiveChunks,
+    /// Set by `forget_chunk`, so the chunk isn't written again right before its entry is removed
+    forgotten: bool,
+}
+
+impl KvStorageHandle {
+    fn write_out(&self) -> io::Result<()> {
+        let contents = unsafe { ::std::slice::from_raw_parts(self.ptr, self.len) };
+        write_entries(&self.db, ::std::iter::once((self.ident.0.as_str(), contents)))
+    }
+}
+
+impl Drop for KvStorageHandle {
+    fn drop(&mut self) {
+        self.live_chunks.lock().unwrap().remove(&self.ident.0);
+        if !self.forgotten {
+            self.write_out()
+                .unwrap_or_else(|err| panic!("Couldn't write chunk {}: {}", self.ident.0, err));
+        }
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// Write all `entries` into the chunk table of `db`, in one transaction
+fn write_entries<'a, I: IntoIterator<Item = (&'a str, &'a [u8])>>(db: &Database, entries: I) -> io::Result<()> {
+    let transaction = db.begin_write().map_err(io_error)?;
+    {
+        let mut table = transaction.open_table(CHUNKS).map_err(io_error)?;
+        for (key, contents) in entries {
+            table.insert(key, contents).map_err(io_error)?;
+        }
+    }
+    transaction.commit().map_err(io_error)
+}
+
+impl KvStorage {
+    /// Create a new KvStorage which keeps chunks in the database file at `path`, creating it if needed
+    pub fn new(path: PathBuf) -> KvStorage {
+        let db = Database::create(&path)
+            .unwrap_or_else(|err| panic!("Can't open database {}: {}", path.to_string_lossy(), err));
+        // make sure the table exists, so reads don't have to handle it missing
+        write_entries(&db, ::std::iter::empty())
+            .unwrap_or_else(|err| panic!("Can't create table in database {}: {}", path.to_string_lossy(), err));
+        KvStorage { db: Arc::new(db), live_chunks: LiveChunks::default() }
+    }
+
+    /// Write all live chunks into the store, in one transaction
+    pub fn flush(&self) -> io::Result<()> {
+        let live_chunks = self.live_chunks.lock().unwrap();
+        write_entries(&self.db, live_chunks.iter().map(|(key, &(ptr, len))| {
+            (key.as_str(), unsafe { ::std::slice::from_raw_parts(ptr as *const u8, len) })
+        }))
+    }
+
+    /// Read the persisted contents of the chunk `ident`, if it exists
+    fn read_entry<T, F: FnOnce(&[u8]) -> T>(&self, ident: &Ident, f: F) -> Option<T> {
+        let transaction = self.db.begin_read()
+            .unwrap_or_else(|err| panic!("Can't read chunk {}: {}", ident.0, err));
+        let table = transaction.open_table(CHUNKS)
+            .unwrap_or_else(|err| panic!("Can't read chunk {}: {}", ident.0, err));
+        table.get(ident.0.as_str())
+            .unwrap_or_else(|err| panic!("Can't read chunk {}: {}", ident.0, err))
+            .map(|contents| f(contents.value()))
+    }
+
+    /// Remove the entries of the chunks `keys`, in one transaction
+    fn remove_entries(&self, keys: &[String]) -> io::Result<()> {
+        let transaction = self.db.begin_write().map_err(io_error)?;
+        {
+            let mut table = transaction.open_table(CHUNKS).map_err(io_error)?;
+            for key in keys {
+                table.remove(key.as_str()).map_err(io_error)?;
+            }
+        }
+        transaction.commit().map_err(io_error)
+    }
+
+    /// Deallocate a chunk, returning its key, unless it wasn't created by this storage
+    fn forget_handle(chunk: Chunk) -> io::Result<String> {
+        let mut handle = chunk._handle_to_drop.downcast::<KvStorageHandle>()
+            .map_err(|_| crate::foreign_chunk_error("KvStorage"))?;
+        handle.forgotten = true;
+        Ok(handle.ident.0.clone())
+    }
+
+    /// Allocate zeroed memory for a chunk of `len` bytes
+    fn allocate_chunk(&self, ident: Ident, len: usize) -> Chunk {
+        let layout = Layout::from_size_align(::std::cmp::max(len, 1), CHUNK_ALIGN).expect("Chunk size too large");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+
+        self.live_chunks.lock().unwrap().insert(ident.0.clone(), (ptr as usize, len));
+        let handle = KvStorageHandle {
+            ptr,
+            len,
+            layout,
+            ident,
+            db: Arc::clone(&self.db),
+            live_chunks: Arc::clone(&self.live_chunks),
+            forgotten: false,
+        };
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, len, layout.size(), ChunkKind::Persistent, Box::new(handle)) }
+    }
+
+    fn handle(chunk: &Chunk) -> &KvStorageHandle {
+        chunk._handle_to_drop.downcast_ref::<KvStorageHandle>().expect("KvStorage got handed a foreign chunk.")
+    }
+}
+
+impl ChunkStorage for KvStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        self.try_create_chunk(ident.clone(), size)
+            .unwrap_or_else(|err| panic!("Can't create chunk {}: {}", ident.0, err))
+    }
+
+    /// Writes the zeroed chunk into the store right away, failing if that's not possible
+    /// or if the chunk already exists
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> io::Result<Chunk> {
+        if self.chunk_exists(&ident) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Chunk {} already exists", ident.0)));
+        }
+        let chunk = self.allocate_chunk(ident, size);
+        if let Err(err) = Self::handle(&chunk).write_out() {
+            self.forget_chunk(chunk);
+            return Err(err);
+        }
+        Ok(chunk)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        if self.chunk_exists(&ident) {
+            (self.load_chunk(ident), false)
+        } else {
+            (self.create_chunk(ident, size), true)
+        }
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let contents = self.read_entry(&ident, <[u8]>::to_vec)
+            .unwrap_or_else(|| panic!("Can't load chunk {}", ident.0));
+        let mut chunk = self.allocate_chunk(ident, contents.len());
+        chunk.copy_from_slice(&contents);
+        chunk
+    }
+
+    /// Deallocate a chunk and remove its entry from the store (unlike Drop, which writes it)
+    fn forget_chunk(&self, chunk: Chunk) {
+        self.forget_chunks(vec![chunk]);
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> io::Result<()> {
+        let key = Self::forget_handle(chunk)?;
+        self.remove_entries(&[key])
+    }
+
+    /// Deallocates all chunks first, then removes all their entries in one transaction
+    fn forget_chunks(&self, chunks: Vec<Chunk>) {
+        let keys = chunks.into_iter()
+            .filter_map(|chunk| match Self::forget_handle(chunk) {
+                Ok(key) => Some(key),
+                Err(_) => {
+                    crate::warn_foreign_chunk("KvStorage");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.remove_entries(&keys)
+            .unwrap_or_else(|err| panic!("Couldn't remove chunks {}: {}", keys.join(", "), err));
+    }
+
+    fn flush_chunk(&self, chunk: &Chunk) {
+        let handle = match chunk._handle_to_drop.downcast_ref::<KvStorageHandle>() {
+            Some(handle) => handle,
+            None => return crate::warn_foreign_flush("KvStorage"),
+        };
+        handle.write_out()
+            .unwrap_or_else(|err| panic!("Couldn't write chunk {}: {}", handle.ident.0, err));
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.read_entry(ident, |_| ()).is_some()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.read_entry(ident, <[u8]>::len)
+    }
+
+    /// Keys are sorted, so this only scans the keys starting with the group's identifier
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        let list = || -> io::Result<Vec<Ident>> {
+            let transaction = self.db.begin_read().map_err(io_error)?;
+            let table = transaction.open_table(CHUNKS).map_err(io_error)?;
+            let mut idents = Vec::new();
+            for entry in table.range(group.0.as_str()..).map_err(io_error)? {
+                let key = entry.map_err(io_error)?.0.value().to_owned();
+                if !key.starts_with(&group.0) {
+                    break;
+                }
+                idents.push(Ident(key));
+            }
+            Ok(idents)
+        };
+        list()
+            .unwrap_or_else(|err| panic!("Can't list chunks of {}: {}", group.0, err))
+            .into_iter()
+            .filter(|ident| ident.belongs_to(group))
+            .collect()
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        self.read_entry(ident, crate::checksum)
+            .unwrap_or_else(|| panic!("Can't read chunk {}", ident.0))
+    }
+}
+
+/// Chunk handles own plain allocations and the database is thread-safe,
+/// so chunks can be used from any thread
+unsafe impl SendableStorage for KvStorage {}
@@ -0,0 +1,628 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Once, OnceLock};
+
+/// Size of each field of the header in front of the compressed blocks of a chunk:
+/// its length, its block size, its number of blocks, and then the compressed size of each block
+const FIELD_SIZE: usize = 8;
+
+fn read_field(bytes: &[u8], index: usize) -> usize {
+    let mut buffer = [0u8; FIELD_SIZE];
+    buffer.copy_from_slice(&bytes[index * FIELD_SIZE..(index + 1) * FIELD_SIZE]);
+    usize::try_from(u64::from_le_bytes(buffer)).expect("Chunk too large for this platform")
+}
+
+/// Compress `bytes` with run-length encoding: each run starts with a control byte `n`,
+/// followed by `n + 1` literal bytes if `n < 128`, or by a single byte repeated `n - 126` times
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut compressed = vec![0; max_compressed_len(bytes.len())];
+    let len = compress_into(bytes, &mut compressed);
+    compressed.truncate(len);
+    compressed.shrink_to_fit();
+    compressed
+}
+
+/// The most bytes `len` bytes can take up compressed: every run takes
+/// at most twice as many bytes as it encodes, with short literal runs being the worst
+fn max_compressed_len(len: usize) -> usize {
+    2 * len
+}
+
+/// Compress `bytes` like `compress`, but into `compressed`, which has to have room
+/// for `max_compressed_len` bytes, returning the compressed length. Doesn't allocate,
+/// so it can be used by the fault handler.
+fn compress_into(bytes: &[u8], compressed: &mut [u8]) -> usize {
+    let (mut start, mut len) = (0, 0);
+
+    while start < bytes.len() {
+        let repeats = bytes[start..].iter().take(129).take_while(|&&byte| byte == bytes[start]).count();
+        if repeats >= 2 {
+            compressed[len] = (repeats + 126) as u8;
+            compressed[len + 1] = bytes[start];
+            len += 2;
+            start += repeats;
+        } else {
+            // literals end where the next repeat starts
+            let mut end = start + 1;
+            while end < bytes.len() && end - start < 128 && (end + 1 == bytes.len() || bytes[end] != bytes[end + 1]) {
+                end += 1;
+            }
+            compressed[len] = (end - start - 1) as u8;
+            compressed[len + 1..len + 1 + end - start].copy_from_slice(&bytes[start..end]);
+            len += 1 + end - start;
+            start = end;
+        }
+    }
+
+    len
+}
+
+/// Decompress the output of `compress` into `bytes`, which has to have the original length
+fn decompress(compressed: &[u8], bytes: &mut [u8]) {
+    let (mut read_at, mut write_at) = (0, 0);
+
+    while read_at < compressed.len() {
+        let control = compressed[read_at] as usize;
+        if control < 128 {
+            let n_literals = control + 1;
+            bytes[write_at..write_at + n_literals].copy_from_slice(&compressed[read_at + 1..read_at + 1 + n_literals]);
+            read_at += 1 + n_literals;
+            write_at += n_literals;
+        } else {
+            let repeats = control - 126;
+            bytes[write_at..write_at + repeats].fill(compressed[read_at + 1]);
+            read_at += 2;
+            write_at += repeats;
+        }
+    }
+}
+
+/// Whether a block is decompressed, and if so, whether it was written to since
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum BlockState {
+    /// Not accessible, its contents only exist compressed
+    Evicted,
+    /// Decompressed and read-only, so the first write to it can be noticed
+    Clean,
+    /// Decompressed and writable, with contents that differ from the compressed ones
+    Dirty,
+}
+
+/// The mapping backing a chunk, which is decompressed block by block on access
+struct Region {
+    base: usize,
+    len: usize,
+    block_size: usize,
+    cache_blocks: usize,
+    states: Vec<BlockState>,
+    compressed: Vec<Vec<u8>>,
+    /// Blocks that are currently decompressed, least recently decompressed first,
+    /// with room for all blocks, so the fault handler never has to allocate
+    resident: VecDeque<usize>,
+    /// Memory that the fault handler compresses evicted blocks into, with a slot of
+    /// `max_compressed_len(block_size)` bytes for each block, which only takes up memory once used
+    spill: usize,
+    /// The compressed length of each block whose current contents are in its spill slot
+    /// rather than in `compressed`
+    spilled: Vec<Option<usize>>,
+}
+
+impl Region {
+    fn spill_len(&self) -> usize {
+        self.states.len() * max_compressed_len(self.block_size)
+    }
+
+    fn block_ptr(&self, block: usize) -> *mut u8 {
+        (self.base + block * self.block_size) as *mut u8
+    }
+
+    fn block_len(&self, block: usize) -> usize {
+        ::std::cmp::min(self.block_size, self.len - ::std::cmp::min(self.len, block * self.block_size))
+    }
+
+    fn spill_slot(&mut self, block: usize) -> &mut [u8] {
+        let slot_len = max_compressed_len(self.block_size);
+        unsafe { ::std::slice::from_raw_parts_mut((self.spill + block * slot_len) as *mut u8, slot_len) }
+    }
+
+    /// The current compressed contents of the block, wherever they are
+    fn compressed_block(&mut self, block: usize) -> &[u8] {
+        match self.spilled[block] {
+            Some(len) => &self.spill_slot(block)[..len],
+            None => &self.compressed[block],
+        }
+    }
+
+    fn protect(&self, block: usize, protection: libc::c_int) {
+        if unsafe { libc::mprotect(self.block_ptr(block) as *mut libc::c_void, self.block_size, protection) } != 0 {
+            // this might be running in the fault handler, where unwinding isn't possible
+            ::std::process::abort();
+        }
+    }
+
+    /// Compress all blocks that were written to again, making them read-only so further writes
+    /// are noticed, and move the blocks compressed by the fault handler out of their spill slots,
+    /// handing the memory of those back to the OS. May allocate, so it's never used by the fault handler.
+    fn clean_all(&mut self) {
+        for &block in &self.resident {
+            if self.states[block] == BlockState::Dirty {
+                let bytes = unsafe { ::std::slice::from_raw_parts(self.block_ptr(block), self.block_len(block)) };
+                self.compressed[block] = compress(bytes);
+                self.spilled[block] = None;
+                self.protect(block, libc::PROT_READ);
+                self.states[block] = BlockState::Clean;
+            }
+        }
+        let mut any_spilled = false;
+        for block in 0..self.states.len() {
+            if self.spilled[block].is_some() {
+                self.compressed[block] = self.compressed_block(block).to_vec();
+                self.spilled[block] = None;
+                any_spilled = true;
+            }
+        }
+        if any_spilled {
+            unsafe { libc::madvise(self.spill as *mut libc::c_void, self.spill_len(), libc::MADV_DONTNEED) };
+        }
+    }
+
+    /// Hand the memory of the block back to the OS, compressing it into its spill slot first
+    /// if it was written to
+    fn evict(&mut self, block: usize) {
+        if self.states[block] == BlockState::Dirty {
+            let bytes = unsafe { ::std::slice::from_raw_parts(self.block_ptr(block), self.block_len(block)) };
+            let len = compress_into(bytes, self.spill_slot(block));
+            self.spilled[block] = Some(len);
+        }
+        unsafe { libc::madvise(self.block_ptr(block) as *mut libc::c_void, self.block_size, libc::MADV_DONTNEED) };
+        self.protect(block, libc::PROT_NONE);
+        self.states[block] = BlockState::Evicted;
+    }
+
+    /// Make the faulting access to `block` possible.
+    /// Runs in the fault handler, so it neither allocates nor locks.
+    fn handle_fault(&mut self, block: usize) {
+        match self.states[block] {
+            BlockState::Evicted => {
+                while self.resident.len() >= self.cache_blocks {
+                    let oldest = self.resident.pop_front().expect("should have resident block");
+                    self.evict(oldest);
+                }
+                self.protect(block, libc::PROT_READ | libc::PROT_WRITE);
+                let bytes = unsafe { ::std::slice::from_raw_parts_mut(self.block_ptr(block), self.block_len(block)) };
+                decompress(self.compressed_block(block), bytes);
+                self.protect(block, libc::PROT_READ);
+                self.states[block] = BlockState::Clean;
+                self.resident.push_back(block);
+            }
+            BlockState::Clean => {
+                self.protect(block, libc::PROT_READ | libc::PROT_WRITE);
+                self.states[block] = BlockState::Dirty;
+            }
+            // another thread faulted on the block at the same time and made it writable already
+            BlockState::Dirty => {}
+        }
+    }
+
+    /// Only valid right after `clean_all`, when all compressed blocks are in `compressed`
+    fn encode(&self) -> Vec<u8> {
+        debug_assert!(self.spilled.iter().all(Option::is_none));
+        encode(self.len, self.block_size, &self.compressed)
+    }
+}
+
+/// A region, together with a spin lock that the threads accessing it take turns with.
+/// A thread holding the lock never touches the region's chunk memory (only blocks it knows
+/// to be accessible), so the fault handler can't interrupt it and then wait for itself.
+struct LockedRegion {
+    locked: AtomicBool,
+    region: UnsafeCell<Region>,
+}
+
+impl LockedRegion {
+    fn with<T, F: FnOnce(&mut Region) -> T>(&self, f: F) -> T {
+        while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            ::std::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.region.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+/// The persisted representation of a chunk: the header followed by all compressed blocks
+fn encode(len: usize, block_size: usize, compressed: &[Vec<u8>]) -> Vec<u8> {
+    let fields = [len, block_size, compressed.len()];
+    let header = fields.iter().copied().chain(compressed.iter().map(Vec::len));
+    let mut bytes = header.flat_map(|field| (field as u64).to_le_bytes()).collect::<Vec<_>>();
+    for compressed in compressed {
+        bytes.extend_from_slice(compressed);
+    }
+    bytes
+}
+
+/// The compressed blocks of a persisted chunk, with its length and block size
+fn decode(bytes: &[u8]) -> (usize, usize, Vec<Vec<u8>>) {
+    let (len, block_size, n_blocks) = (read_field(bytes, 0), read_field(bytes, 1), read_field(bytes, 2));
+    let mut offset = (3 + n_blocks) * FIELD_SIZE;
+    let compressed = (0..n_blocks)
+        .map(|block| {
+            let compressed_len = read_field(bytes, 3 + block);
+            offset += compressed_len;
+            bytes[offset - compressed_len..offset].to_vec()
+        })
+        .collect();
+    (len, block_size, compressed)
+}
+
+/// Maximum number of chunks of all `LazyCompressed` storages that can be loaded at the same time
+const MAX_REGIONS: usize = 4096;
+
+/// An entry of the region table, free while `region` is null. `base` and `mapped_len` are
+/// only set once `region` is, and `base` is cleared first again, so the fault handler
+/// only ever follows `region` for the mapping it faulted in.
+struct RegionSlot {
+    base: AtomicUsize,
+    mapped_len: AtomicUsize,
+    region: AtomicPtr<LockedRegion>,
+}
+
+/// The regions of all live chunks, which the fault handler looks up faulting addresses in
+/// without locking or allocating
+static REGIONS: [RegionSlot; MAX_REGIONS] = [const {
+    RegionSlot { base: AtomicUsize::new(0), mapped_len: AtomicUsize::new(0), region: AtomicPtr::new(::std::ptr::null_mut()) }
+}; MAX_REGIONS];
+
+/// Number of slots of `REGIONS` that were ever used, so the fault handler only looks at those
+static REGIONS_USED: AtomicUsize = AtomicUsize::new(0);
+
+/// Enter `region` into the region table, returning its slot
+fn register(region: *mut LockedRegion, base: usize, mapped_len: usize) -> usize {
+    let slot = REGIONS.iter()
+        .position(|slot| slot.region.compare_exchange(::std::ptr::null_mut(), region, Ordering::AcqRel, Ordering::Relaxed).is_ok())
+        .unwrap_or_else(|| panic!("Can't load more than {} LazyCompressed chunks at once", MAX_REGIONS));
+    REGIONS_USED.fetch_max(slot + 1, Ordering::AcqRel);
+    REGIONS[slot].mapped_len.store(mapped_len, Ordering::Release);
+    REGIONS[slot].base.store(base, Ordering::Release);
+    slot
+}
+
+fn unregister(slot: usize) {
+    REGIONS[slot].base.store(0, Ordering::Release);
+    REGIONS[slot].mapped_len.store(0, Ordering::Release);
+    REGIONS[slot].region.store(::std::ptr::null_mut(), Ordering::Release);
+}
+
+static INSTALL_FAULT_HANDLER: Once = Once::new();
+
+/// The handlers of `SIGSEGV` and `SIGBUS` from before the fault handler was installed,
+/// which faults outside of any region are passed on to
+static PREVIOUS_HANDLERS: OnceLock<[libc::sigaction; 2]> = OnceLock::new();
+
+const FAULT_SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGBUS];
+
+fn install_fault_handler() {
+    INSTALL_FAULT_HANDLER.call_once(|| unsafe {
+        let mut previous: [libc::sigaction; 2] = ::std::mem::zeroed();
+        for (signal, previous) in FAULT_SIGNALS.iter().zip(previous.iter_mut()) {
+            libc::sigaction(*signal, ::std::ptr::null(), previous);
+        }
+        PREVIOUS_HANDLERS.set(previous).unwrap_or_else(|_| unreachable!());
+
+        let mut action: libc::sigaction = ::std::mem::zeroed();
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) = handle_fault;
+        action.sa_sigaction = handler as libc::sighandler_t;
+        // run on the alternate signal stack (if any), like the stack overflow handler of `std`
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        for signal in &FAULT_SIGNALS {
+            libc::sigaction(*signal, &action, ::std::ptr::null_mut());
+        }
+    });
+}
+
+/// Handles faults in the mappings of chunks without locking or allocating, since it might
+/// have interrupted anything, including an allocation or a thread holding a lock
+extern "C" fn handle_fault(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let address = unsafe { (*info).si_addr() } as usize;
+    let handled = REGIONS[..REGIONS_USED.load(Ordering::Acquire)].iter()
+        .find_map(|slot| {
+            let base = slot.base.load(Ordering::Acquire);
+            let contains = base != 0 && address >= base && address < base + slot.mapped_len.load(Ordering::Acquire);
+            let region = slot.region.load(Ordering::Acquire);
+            if contains && !region.is_null() { Some((base, region)) } else { None }
+        })
+        .map(|(base, region)| unsafe { &*region }.with(|region| {
+            region.handle_fault((address - base) / region.block_size)
+        }));
+    if handled.is_none() {
+        unsafe { pass_on_fault(signal, info, context) };
+    }
+}
+
+/// Call the handler from before the fault handler was installed, or restore
+/// the default handling so the faulting access crashes the process when it is retried
+unsafe fn pass_on_fault(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let index = FAULT_SIGNALS.iter().position(|&fault_signal| fault_signal == signal).expect("should be a fault signal");
+    let previous = PREVIOUS_HANDLERS.get().expect("should have previous handlers")[index];
+    if previous.sa_flags & libc::SA_SIGINFO != 0 {
+        let previous = ::std::mem::transmute::<libc::sighandler_t, extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)>(previous.sa_sigaction);
+        previous(signal, info, context);
+    } else if previous.sa_sigaction == libc::SIG_DFL || previous.sa_sigaction == libc::SIG_IGN {
+        libc::signal(signal, libc::SIG_DFL);
+    } else {
+        let previous = ::std::mem::transmute::<libc::sighandler_t, extern "C" fn(libc::c_int)>(previous.sa_sigaction);
+        previous(signal);
+    }
+}
+
+/// Map `len` bytes of anonymous memory for chunk `ident`, which only take up memory once touched
+fn map(ident: &Ident, len: usize, protection: libc::c_int) -> usize {
+    let ptr = unsafe {
+        libc::mmap(
+            ::std::ptr::null_mut(),
+            len,
+            protection,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        panic!("Can't map memory for chunk {}: {}", ident.0, ::std::io::Error::last_os_error());
+    }
+    ptr as usize
+}
+
+/// The inner storage, together with the inner chunks that would be lost if they were dropped
+struct State<S: ChunkStorage> {
+    inner: S,
+    transient: RefCell<HashMap<String, Chunk>>,
+}
+
+impl<S: ChunkStorage> State<S> {
+    /// Call `f` with the contents of the inner chunk `ident`, if it exists
+    fn read<T, F: FnOnce(&[u8]) -> T>(&self, ident: &Ident, f: F) -> Option<T> {
+        if let Some(chunk) = self.transient.borrow().get(&ident.0) {
+            return Some(f(chunk));
+        }
+        if !self.inner.chunk_exists(ident) {
+            return None;
+        }
+        Some(f(&self.inner.load_chunk(ident.clone())))
+    }
+
+    /// Replace the contents of the inner chunk `ident` with `bytes`, returning its kind
+    fn write(&self, ident: &Ident, bytes: &[u8]) -> ChunkKind {
+        if self.inner.chunk_len(ident).is_some_and(|len| len != bytes.len()) {
+            self.remove(ident);
+        }
+        let (mut chunk, _) = self.inner.load_or_create_chunk(ident.clone(), bytes.len());
+        chunk.copy_from_slice(bytes);
+        let kind = chunk.kind();
+        if chunk.is_persistent() {
+            self.inner.flush_chunk(&chunk);
+        } else {
+            self.transient.borrow_mut().insert(ident.0.clone(), chunk);
+        }
+        kind
+    }
+
+    fn remove(&self, ident: &Ident) {
+        let cached = self.transient.borrow_mut().remove(&ident.0);
+        if let Some(chunk) = cached {
+            self.inner.forget_chunk(chunk);
+        } else if self.inner.chunk_exists(ident) {
+            self.inner.forget_chunk(self.inner.load_chunk(ident.clone()));
+        }
+    }
+}
+
+/// A `ChunkStorage` decorator that stores chunks compressed in an inner storage, in blocks
+/// that are only decompressed when they are accessed, so random access to a large chunk doesn't
+/// have to decompress all of it. Only a few blocks of each chunk are kept decompressed at a time.
+///
+/// Chunks are memory maps that start out inaccessible. Accessing a block faults, and
+/// a signal handler (installed for `SIGSEGV` and `SIGBUS` when the first storage is created)
+/// decompresses it, evicting the least recently decompressed block (compressing it again
+/// first if it was written to) if too many are decompressed already. Chunks live in memory while
+/// loaded, as a private copy of their compressed blocks, which are only stored in the inner storage
+/// again when a chunk is flushed or dropped.
+///
+/// The fault handler neither locks nor allocates: it finds chunks in a fixed table of
+/// at most 4096 loaded chunks, and compresses evicted blocks into memory reserved up front,
+/// which is only compacted again when a chunk is flushed or dropped.
+///
+/// Chunks have the kind of the inner storage: if it's transient, dropped chunks are gone.
+/// Accessing chunks from other threads is fine, but from other signal handlers it isn't.
+/// Neither is handing chunk memory to system calls, like `write(fd, &chunk)`: the kernel
+/// doesn't fault on evicted blocks like user code does, so such calls fail with `EFAULT`,
+/// unless the memory is copied out of the chunk first.
+pub struct LazyCompressed<S: ChunkStorage + 'static> {
+    state: Rc<State<S>>,
+    block_size: usize,
+    cache_blocks: usize,
+}
+
+struct LazyCompressedHandle<S: ChunkStorage + 'static> {
+    ident: Ident,
+    base: usize,
+    mapped_len: usize,
+    region: *mut LockedRegion,
+    /// Slot of the region in the region table
+    slot: usize,
+    kind: ChunkKind,
+    state: Rc<State<S>>,
+    /// Set by `forget_chunk`, so the chunk isn't stored again after it was removed
+    forgotten: bool,
+}
+
+impl<S: ChunkStorage + 'static> Drop for LazyCompressedHandle<S> {
+    fn drop(&mut self) {
+        unregister(self.slot);
+        let mut region = unsafe { Box::from_raw(self.region) }.region.into_inner();
+        if !self.forgotten {
+            match self.kind {
+                ChunkKind::Persistent => {
+                    region.clean_all();
+                    self.state.write(&self.ident, &region.encode());
+                }
+                ChunkKind::Transient => self.state.remove(&self.ident),
+            }
+        }
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.mapped_len);
+            libc::munmap(region.spill as *mut libc::c_void, region.spill_len());
+        }
+    }
+}
+
+impl<S: ChunkStorage + 'static> LazyCompressed<S> {
+    /// Wrap `inner`, compressing new chunks in blocks of `block_size` bytes (a multiple of the
+    /// page size), of which at most `cache_blocks` (at least 2) are decompressed per chunk at a time
+    pub fn new(inner: S, block_size: usize, cache_blocks: usize) -> LazyCompressed<S> {
+        assert!(
+            block_size > 0 && block_size.is_multiple_of(crate::mmap_storage::page_size()),
+            "Block size has to be a multiple of the page size"
+        );
+        // a single access can span two blocks, which both have to be decompressed at once
+        assert!(cache_blocks >= 2, "At least 2 blocks have to be kept decompressed");
+        install_fault_handler();
+        LazyCompressed {
+            state: Rc::new(State { inner, transient: RefCell::new(HashMap::new()) }),
+            block_size,
+            cache_blocks,
+        }
+    }
+
+    /// The number of blocks of `chunk` that are currently decompressed
+    pub fn decompressed_blocks(&self, chunk: &Chunk) -> usize {
+        unsafe { &*Self::handle(chunk).region }.with(|region| region.resident.len())
+    }
+
+    fn chunk(&self, ident: Ident, len: usize, block_size: usize, compressed: Vec<Vec<u8>>, kind: ChunkKind) -> Chunk {
+        let n_blocks = compressed.len();
+        let mapped_len = n_blocks * block_size;
+        let base = map(&ident, mapped_len, libc::PROT_NONE);
+        let region = Box::into_raw(Box::new(LockedRegion {
+            locked: AtomicBool::new(false),
+            region: UnsafeCell::new(Region {
+                base,
+                len,
+                block_size,
+                cache_blocks: self.cache_blocks,
+                states: vec![BlockState::Evicted; n_blocks],
+                compressed,
+                resident: VecDeque::with_capacity(n_blocks),
+                spill: map(&ident, n_blocks * max_compressed_len(block_size), libc::PROT_READ | libc::PROT_WRITE),
+                spilled: vec![None; n_blocks],
+            }),
+        }));
+        let handle = LazyCompressedHandle {
+            ident,
+            base,
+            mapped_len,
+            region,
+            slot: register(region, base, mapped_len),
+            kind,
+            state: Rc::clone(&self.state),
+            forgotten: false,
+        };
+        unsafe { Chunk::from_raw_parts(base as *mut u8, len, kind, Box::new(handle)) }
+    }
+
+    fn forget_handle(chunk: Chunk) -> ::std::io::Result<Ident> {
+        let mut handle = chunk._handle_to_drop.downcast::<LazyCompressedHandle<S>>()
+            .map_err(|_| crate::foreign_chunk_error("LazyCompressed storage"))?;
+        handle.forgotten = true;
+        Ok(handle.ident.clone())
+    }
+
+    fn handle(chunk: &Chunk) -> &LazyCompressedHandle<S> {
+        chunk._handle_to_drop.downcast_ref::<LazyCompressedHandle<S>>()
+            .expect("LazyCompressed storage got handed a foreign chunk.")
+    }
+}
+
+impl<S: ChunkStorage + 'static> ChunkStorage for LazyCompressed<S> {
+    /// Stores the compressed zeroed blocks right away
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        // map at least one block, since empty mappings aren't allowed
+        let n_blocks = ::std::cmp::max(size.div_ceil(self.block_size), 1);
+        let compressed = (0..n_blocks)
+            .map(|block| compress(&vec![0; ::std::cmp::min(self.block_size, size - ::std::cmp::min(size, block * self.block_size))]))
+            .collect::<Vec<_>>();
+        let kind = self.state.write(&ident, &encode(size, self.block_size, &compressed));
+        self.chunk(ident, size, self.block_size, compressed, kind)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        if self.chunk_exists(&ident) {
+            (self.load_chunk(ident), false)
+        } else {
+            (self.create_chunk(ident, size), true)
+        }
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let kind = if self.state.transient.borrow().contains_key(&ident.0) { ChunkKind::Transient } else { ChunkKind::Persistent };
+        let (len, block_size, compressed) = self.state.read(&ident, decode)
+            .unwrap_or_else(|| panic!("Can't load chunk {}", ident.0));
+        self.chunk(ident, len, block_size, compressed, kind)
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        match Self::forget_handle(chunk) {
+            Ok(ident) => self.state.remove(&ident),
+            Err(_) => crate::warn_foreign_chunk("LazyCompressed storage"),
+        }
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let ident = Self::forget_handle(chunk)?;
+        self.state.remove(&ident);
+        Ok(())
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.state.read(ident, |_| ()).is_some()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.state.read(ident, |bytes| read_field(bytes, 0))
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.state.inner.list_chunks(group)
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let bytes = self.state.read(ident, |bytes| {
+            let (len, block_size, compressed) = decode(bytes);
+            let mut decompressed = vec![0; len];
+            for (block, compressed) in compressed.iter().enumerate() {
+                let start = block * block_size;
+                decompress(compressed, &mut decompressed[start..::std::cmp::min(start + block_size, len)]);
+            }
+            decompressed
+        });
+        crate::checksum(&bytes.unwrap_or_else(|| panic!("Can't read chunk {}", ident.0)))
+    }
+
+    /// Compresses the blocks that were written to again and stores all blocks
+    fn flush_chunk(&self, chunk: &Chunk) {
+        let handle = match chunk._handle_to_drop.downcast_ref::<LazyCompressedHandle<S>>() {
+            Some(handle) => handle,
+            None => return crate::warn_foreign_flush("LazyCompressed storage"),
+        };
+        let encoded = unsafe { &*handle.region }.with(|region| {
+            region.clean_all();
+            region.encode()
+        });
+        self.state.write(&handle.ident, &encoded);
+    }
+}
@@ -5,80 +5,355 @@
 //! Its purpose is being able to abstract storage of entity-collections
 //! (such as actors in `Kay`) over both temporary heap memory and persistent
 //! mmap'ed memory used for both runtime and savegames.
+//!
+//! Without the (default) `std` feature, the crate is `no_std` and only needs `alloc`,
+//! offering all collections and `HeapStorage`, but none of the other storages.
 
 #![warn(missing_docs)]
 #![feature(vec_resize_default)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 
+mod live_chunks;
 mod heap_storage;
+#[cfg(feature = "std")]
+mod bump_heap_storage;
 #[cfg(feature = "mmap")]
 mod mmap_storage;
+#[cfg(feature = "mmap")]
+mod anon_mmap_storage;
+#[cfg(feature = "mmap")]
+mod snapshot_storage;
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+mod lazy_compressed_storage;
+#[cfg(feature = "direct_io")]
+mod direct_io_storage;
+#[cfg(feature = "kv")]
+mod kv_storage;
+#[cfg(feature = "std")]
+mod logging_storage;
+#[cfg(feature = "std")]
+mod quota_storage;
+#[cfg(feature = "std")]
+mod dedup_storage;
+#[cfg(feature = "std")]
+mod virtual_fs_storage;
 
 mod value;
+mod atomic_value;
 mod arena;
+mod typed_arena;
+mod generational_arena;
 mod vector;
+mod transaction;
 mod queue;
+mod tagged_queue;
+#[cfg(feature = "std")]
+mod queue_stream;
+mod deque;
 mod multi_arena;
+mod bit_vec;
+mod chunky_map;
+mod storage_ext;
+mod shared;
+mod save_coordinator;
+
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod conformance;
 
-pub use heap_storage::HeapStorage;
+pub use heap_storage::{HeapStorage, HeapStorageConfig, HeapStorageBuilder};
+#[cfg(feature = "std")]
+pub use bump_heap_storage::BumpHeapStorage;
 #[cfg(feature = "mmap")]
-pub use mmap_storage::MmapStorage;
+pub use mmap_storage::{MmapStorage, MmapOptions, MmapAdvice};
+#[cfg(feature = "mmap")]
+pub use anon_mmap_storage::AnonMmapStorage;
+#[cfg(feature = "mmap")]
+pub use snapshot_storage::SnapshotStorage;
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+pub use lazy_compressed_storage::LazyCompressed;
+#[cfg(feature = "direct_io")]
+pub use direct_io_storage::DirectIoStorage;
+#[cfg(feature = "kv")]
+pub use kv_storage::KvStorage;
+#[cfg(feature = "std")]
+pub use logging_storage::{Logging, StorageEvent};
+#[cfg(feature = "std")]
+pub use quota_storage::Quota;
+#[cfg(feature = "std")]
+pub use dedup_storage::Dedup;
+#[cfg(feature = "std")]
+pub use virtual_fs_storage::{VirtualFsStorage, InvalidExportError};
 
-pub use value::Value;
-pub use arena::{Arena, ArenaIndex};
-pub use vector::Vector;
-pub use queue::Queue;
-pub use multi_arena::{MultiArena, MultiArenaIndex};
+pub use value::{Value, Portable, PortableValue};
+pub use atomic_value::{AtomicValue, AtomicInt};
+pub use arena::{Arena, ArenaIndex, MissingChunksError, VerifyReport};
+#[cfg(feature = "std")]
+pub use arena::TryReserveError;
+pub use typed_arena::TypedArena;
+pub use generational_arena::{GenerationalArena, GenerationalIndex};
+pub use vector::{Vector, VectorSlice, Cursor, EnumerateIndices, Checkpoint, IntoIter};
+pub use transaction::Transaction;
+pub use queue::{Queue, QueueStats};
+pub use tagged_queue::TaggedQueue;
+#[cfg(feature = "std")]
+pub use queue_stream::{QueueReader, QueueWriter};
+pub use deque::Deque;
+pub use multi_arena::{MultiArena, MultiArenaIndex, SizedHandle, SizeTooLargeError};
+pub use bit_vec::BitVec;
+pub use chunky_map::ChunkyMap;
+pub use storage_ext::ChunkStorageExt;
+pub use shared::{SendableStorage, Shared};
+pub use save_coordinator::{Flushable, SaveCoordinator};
 
 /// A Chunk of general purpose memory, essentially acting as &mut [u8]
 /// which can be backed by different `ChunkStorage` providers.
 /// Dropping a Chunk deallocates its in-memory space
-/// but keeps any persisted version of that chunk.
+/// but keeps any persisted version of that chunk (see `ChunkKind`).
+///
+/// A Chunk owns whatever keeps its memory valid, so it stays usable
+/// even if the `ChunkStorage` it came from is dropped first.
 pub struct Chunk {
     ptr: *mut u8,
     len: usize,
-    _handle_to_drop: Box<dyn std::any::Any>
+    capacity: usize,
+    kind: ChunkKind,
+    _handle_to_drop: Box<dyn core::any::Any>,
+    /// Dropped after the handle, so the handle may refer to them
+    _retained: Vec<Box<dyn core::any::Any>>,
+}
+
+/// Whether a `Chunk` only lives in memory or is backed by persistent storage
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// The chunk's contents are lost once it is dropped
+    Transient,
+    /// The chunk's contents are persisted and can be loaded again after it is dropped
+    Persistent,
+}
+
+impl Chunk {
+    /// Create a chunk of `len` bytes at `ptr`, for use by `ChunkStorage` implementations.
+    /// `handle` is dropped together with the chunk and will be handed back to the storage
+    /// in `forget_chunk`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` has to stay valid for reads and writes of `len` bytes until `handle` is dropped,
+    /// regardless of whether the storage that created the chunk is still alive.
+    pub unsafe fn from_raw_parts(ptr: *mut u8, len: usize, kind: ChunkKind, handle: Box<dyn core::any::Any>) -> Chunk {
+        Self::from_raw_parts_with_capacity(ptr, len, len, kind, handle)
+    }
+
+    /// Create a chunk like `from_raw_parts`, for storages that allocate more memory than requested,
+    /// where `capacity` is the total number of bytes allocated at `ptr`, at least `len`
+    ///
+    /// # Safety
+    ///
+    /// See `from_raw_parts`, but for `capacity` instead of `len` bytes.
+    pub unsafe fn from_raw_parts_with_capacity(ptr: *mut u8, len: usize, capacity: usize, kind: ChunkKind, handle: Box<dyn core::any::Any>) -> Chunk {
+        debug_assert!(capacity >= len, "Chunk capacity has to be at least its length");
+        Chunk {
+            ptr,
+            len,
+            capacity,
+            kind,
+            _handle_to_drop: handle,
+            _retained: Vec::new(),
+        }
+    }
+
+    /// Number of bytes actually allocated for this chunk by its storage, which can be more
+    /// than its length, for example due to alignment padding. Only the first `len()` bytes
+    /// are part of the chunk (and persisted), the rest is just valid memory.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Set all bytes of the chunk to `byte`, such as for initializing one whose contents are garbage
+    pub fn fill(&mut self, byte: u8) {
+        unsafe { ::core::ptr::write_bytes(self.ptr, byte, self.len) };
+    }
+
+    /// Set all bytes of the chunk to zero, regardless of whether its storage zeroes new chunks
+    pub fn zero(&mut self) {
+        self.fill(0);
+    }
+
+    /// Keep `owner` alive for as long as this chunk, for storages (or storage wrappers)
+    /// handing out chunks whose memory is owned by something else, such as the storage itself
+    pub fn retain<T: core::any::Any>(&mut self, owner: T) {
+        self._retained.push(Box::new(owner));
+    }
+
+    /// Whether this chunk only lives in memory or is backed by persistent storage
+    pub fn kind(&self) -> ChunkKind {
+        self.kind
+    }
+
+    /// Is this chunk backed by persistent storage?
+    pub fn is_persistent(&self) -> bool {
+        self.kind == ChunkKind::Persistent
+    }
 }
 
-impl ::std::ops::Deref for Chunk {
+impl ::core::ops::Deref for Chunk {
     type Target=[u8];
 
     fn deref(&self) -> &[u8] {
-        unsafe {std::slice::from_raw_parts(self.ptr, self.len) }
+        unsafe {core::slice::from_raw_parts(self.ptr, self.len) }
     }
 }
 
-impl ::std::ops::DerefMut for Chunk {
+impl ::core::ops::DerefMut for Chunk {
     fn deref_mut(&mut self) -> &mut [u8] {
-        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) }
     }
 }
 
 /// A provider of backing storage for `Chunks`
+///
+/// Every storage creates chunks whose `len()` is exactly the requested size,
+/// storages that allocate more than that report it as the chunk's `capacity()`.
 pub trait ChunkStorage {
     /// Create a chunk with a given identifier
     fn create_chunk(&self, ident: Ident, size: usize) -> Chunk;
+    /// Like `create_chunk`, but returns an error instead of panicking if the backing space
+    /// can't be allocated. Storages that can't fail this way just create the chunk.
+    #[cfg(feature = "std")]
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        Ok(self.create_chunk(ident, size))
+    }
     /// Load a chunk with a given identifier, or create it if it doesn't exist
     /// returns (chunk, true) if the chunk was created new rather than loaded
     fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool);
     /// Load a chunk with a given identifier, assumes it exists
     fn load_chunk(&self, ident: Ident) -> Chunk;
     /// Deallocate a chunk and delete any persisted representation of it
-    /// (unlike Drop, which only unloads a chunk)
+    /// (unlike Drop, which only unloads a chunk).
+    /// Chunks that weren't created by this storage are only dropped, with a warning.
     fn forget_chunk(&self, chunk: Chunk);
+    /// Like `forget_chunk`, but returns an error if the chunk wasn't created by this storage
+    /// (in which case it is only dropped, without a warning) or, instead of panicking,
+    /// if its persisted representation can't be deleted.
+    #[cfg(feature = "std")]
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        self.forget_chunk(chunk);
+        Ok(())
+    }
+    /// Forget many chunks at once, which storages can implement
+    /// more efficiently than forgetting them one by one
+    fn forget_chunks(&self, chunks: Vec<Chunk>) {
+        for chunk in chunks {
+            self.forget_chunk(chunk);
+        }
+    }
+    /// Check whether a chunk with a given identifier exists (live or persisted)
+    fn chunk_exists(&self, ident: &Ident) -> bool;
+    /// Get the size of the chunk with a given identifier (live or persisted), if it exists
+    fn chunk_len(&self, ident: &Ident) -> Option<usize>;
+    /// List the identifiers of all existing chunks (live or persisted) that belong to `group`
+    /// (see `Ident::belongs_to`), in no particular order
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident>;
+    /// Compute a checksum over the contents of the chunk with a given identifier,
+    /// without loading it as a live `Chunk`
+    fn chunk_checksum(&self, ident: &Ident) -> u64;
+    /// Write any changes to a chunk's contents through to its persisted representation,
+    /// which is a no-op for storages without one (and, with a warning, for foreign chunks)
+    fn flush_chunk(&self, _chunk: &Chunk) {}
+    /// The size that chunks should be a multiple of to be aligned to pages,
+    /// which is 1 for storages without pages
+    fn page_size(&self) -> usize {
+        1
+    }
+    /// The kind of chunks this storage creates. Chunks of `Transient` storages
+    /// can't be loaded again, since nothing of them remains once they are dropped.
+    fn chunk_kind(&self) -> ChunkKind {
+        ChunkKind::Persistent
+    }
+    /// Compute a fingerprint over the identifiers and checksums of all chunks belonging to `group`,
+    /// which changes whenever any of them is created, forgotten or modified
+    fn group_fingerprint(&self, group: &Ident) -> u64 {
+        let mut idents = self.list_chunks(group);
+        idents.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut bytes = Vec::new();
+        for ident in idents {
+            bytes.extend_from_slice(ident.0.as_bytes());
+            bytes.push(0);
+            bytes.extend_from_slice(&self.chunk_checksum(&ident).to_le_bytes());
+        }
+        checksum(&bytes)
+    }
+}
+
+/// The byte that forgotten chunks are overwritten with by `HeapStorage` in debug builds
+/// (and by `MmapStorage` if configured), so use of stale pointers into them is easy to spot
+pub const FORGOTTEN_CHUNK_POISON: u8 = 0xDD;
+
+/// The error of `try_forget_chunk` for a chunk that wasn't created by `storage`
+#[cfg(feature = "std")]
+pub(crate) fn foreign_chunk_error(storage: &str) -> ::std::io::Error {
+    ::std::io::Error::new(::std::io::ErrorKind::InvalidInput, format!("{} got handed a foreign chunk.", storage))
+}
+
+/// Warn that `forget_chunk` of `storage` got handed a chunk it didn't create,
+/// which is then only dropped, rather than aborting while dropping collections.
+/// Without `std` there is nowhere to warn, so the chunk is dropped silently.
+pub(crate) fn warn_foreign_chunk(storage: &str) {
+    #[cfg(feature = "std")]
+    eprintln!("Warning: {} got handed a foreign chunk, dropping it without forgetting it.", storage);
+    #[cfg(not(feature = "std"))]
+    let _ = storage;
+}
+
+/// Warn that `flush_chunk` of `storage` got handed a chunk it didn't create, which isn't flushed
+#[cfg(feature = "std")]
+pub(crate) fn warn_foreign_flush(storage: &str) {
+    eprintln!("Warning: {} got handed a foreign chunk, not flushing it.", storage);
+}
+
+/// FNV-1a hash over `bytes`, used for chunk checksums
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
 }
 
 /// Identifies a chunk or chunk group uniquely
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Ident(pub String);
 
 impl Ident {
     /// Create a sub-identifier within a group
-    pub fn sub<T: ::std::fmt::Display>(&self, suffix: T) -> Ident {
+    pub fn sub<T: ::core::fmt::Display>(&self, suffix: T) -> Ident {
         Ident(format!("{}_{}", self.0, suffix))
     }
+
+    /// Whether this identifier is `group` itself, or was created from it using `sub`
+    pub fn belongs_to(&self, group: &Ident) -> bool {
+        self.0.starts_with(&group.0)
+            && (self.0.len() == group.0.len() || self.0[group.0.len()..].starts_with('_'))
+    }
+
+    /// The identifier of the top-level group this identifier belongs to,
+    /// which is everything before the first separator added by `sub`
+    pub fn group(&self) -> &str {
+        self.0.split('_').next().unwrap_or("")
+    }
 }
 
-impl<T: ::std::fmt::Display> From<T> for Ident {
+impl<T: ::core::fmt::Display> From<T> for Ident {
     fn from(source: T) -> Self {
         Ident(format!("{}", source))
     }
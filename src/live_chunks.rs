@@ -0,0 +1,39 @@
+use crate::Ident;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Address and length of each live chunk of a storage that can't load chunks, by identifier.
+///
+/// Collections sharing such a storage may use the same identifiers, so there can be
+/// several live chunks per identifier. Each is removed again by its address when it is dropped,
+/// which is unique among live chunks since each one has its own (nonempty) allocation.
+#[derive(Default)]
+pub(crate) struct LiveChunks(BTreeMap<String, Vec<(usize, usize)>>);
+
+impl LiveChunks {
+    pub(crate) fn insert(&mut self, ident: &Ident, address: usize, len: usize) {
+        self.0.entry(ident.0.clone()).or_default().push((address, len));
+    }
+
+    pub(crate) fn remove(&mut self, ident: &Ident, address: usize) {
+        if let Some(chunks) = self.0.get_mut(&ident.0) {
+            chunks.retain(|&(chunk_address, _)| chunk_address != address);
+            if chunks.is_empty() {
+                self.0.remove(&ident.0);
+            }
+        }
+    }
+
+    /// Address and length of the live chunk with the given identifier that was created last
+    pub(crate) fn latest(&self, ident: &Ident) -> Option<(usize, usize)> {
+        self.0.get(&ident.0).and_then(|chunks| chunks.last()).copied()
+    }
+
+    pub(crate) fn list(&self, group: &Ident) -> Vec<Ident> {
+        self.0.keys()
+            .map(|name| Ident(name.clone()))
+            .filter(|ident| ident.belongs_to(group))
+            .collect()
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A call made to a `ChunkStorage`, as recorded by `Logging`
+#[derive(Clone, Debug, PartialEq)]
+pub enum StorageEvent {
+    /// `create_chunk` was called
+    Create {
+        /// Identifier of the created chunk
+        ident: Ident,
+        /// Requested size of the created chunk
+        size: usize,
+    },
+    /// `load_or_create_chunk` was called
+    LoadOrCreate {
+        /// Identifier of the loaded or created chunk
+        ident: Ident,
+        /// Requested size of the chunk, used if it was created
+        size: usize,
+        /// Whether the chunk was created new rather than loaded
+        created_new: bool,
+    },
+    /// `load_chunk` was called
+    Load {
+        /// Identifier of the loaded chunk
+        ident: Ident,
+    },
+    /// `forget_chunk` was called
+    Forget {
+        /// Identifier of the forgotten chunk
+        ident: Ident,
+    },
+}
+
+/// A `ChunkStorage` decorator that forwards to an inner storage
+/// while recording every call made to it, for debugging
+pub struct Logging<S: ChunkStorage> {
+    inner: S,
+    log: RefCell<Vec<StorageEvent>>,
+    /// Identifiers of chunks handed out, by chunk pointer, so forgets can be attributed
+    idents: RefCell<HashMap<usize, Ident>>,
+}
+
+impl<S: ChunkStorage> Logging<S> {
+    /// Wrap `inner`, starting with an empty log
+    pub fn new(inner: S) -> Logging<S> {
+        Logging {
+            inner,
+            log: RefCell::new(Vec::new()),
+            idents: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Return all events recorded so far, clearing the log
+    pub fn take_log(&self) -> Vec<StorageEvent> {
+        self.log.take()
+    }
+
+    fn record(&self, event: StorageEvent) {
+        self.log.borrow_mut().push(event);
+    }
+
+    fn track(&self, chunk: &Chunk, ident: Ident) {
+        self.idents.borrow_mut().insert(chunk.ptr as usize, ident);
+    }
+}
+
+impl<S: ChunkStorage> ChunkStorage for Logging<S> {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        self.record(StorageEvent::Create { ident: ident.clone(), size });
+        let chunk = self.inner.create_chunk(ident.clone(), size);
+        self.track(&chunk, ident);
+        chunk
+    }
+
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        self.record(StorageEvent::Create { ident: ident.clone(), size });
+        let chunk = self.inner.try_create_chunk(ident.clone(), size)?;
+        self.track(&chunk, ident);
+        Ok(chunk)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        let (chunk, created_new) = self.inner.load_or_create_chunk(ident.clone(), size);
+        self.record(StorageEvent::LoadOrCreate { ident: ident.clone(), size, created_new });
+        self.track(&chunk, ident);
+        (chunk, created_new)
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        self.record(StorageEvent::Load { ident: ident.clone() });
+        let chunk = self.inner.load_chunk(ident.clone());
+        self.track(&chunk, ident);
+        chunk
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        let ident = self.idents.borrow_mut().remove(&(chunk.ptr as usize));
+        match ident {
+            Some(ident) => {
+                self.record(StorageEvent::Forget { ident });
+                self.inner.forget_chunk(chunk);
+            }
+            None => crate::warn_foreign_chunk("Logging storage"),
+        }
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let ident = self.idents.borrow_mut().remove(&(chunk.ptr as usize))
+            .ok_or_else(|| crate::foreign_chunk_error("Logging storage"))?;
+        self.record(StorageEvent::Forget { ident });
+        self.inner.try_forget_chunk(chunk)
+    }
+
+    fn forget_chunks(&self, chunks: Vec<Chunk>) {
+        let chunks = chunks.into_iter().filter(|chunk| {
+            let ident = self.idents.borrow_mut().remove(&(chunk.ptr as usize));
+            match ident {
+                Some(ident) => {
+                    self.record(StorageEvent::Forget { ident });
+                    true
+                }
+                None => {
+                    crate::warn_foreign_chunk("Logging storage");
+                    false
+                }
+            }
+        }).collect();
+        self.inner.forget_chunks(chunks);
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.inner.chunk_exists(ident)
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.inner.chunk_len(ident)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.inner.list_chunks(group)
+    }
+
+    fn group_fingerprint(&self, group: &Ident) -> u64 {
+        self.inner.group_fingerprint(group)
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        self.inner.chunk_checksum(ident)
+    }
+
+    fn flush_chunk(&self, chunk: &Chunk) {
+        self.inner.flush_chunk(chunk)
+    }
+
+    fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    fn chunk_kind(&self) -> ChunkKind {
+        self.inner.chunk_kind()
+    }
+}
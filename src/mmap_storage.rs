@@ -1,87 +1,543 @@
-use crate::{Chunk, ChunkStorage, Ident};
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::shared::SendableStorage;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::{OpenOptions, File};
+use std::io::{Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use memmap::MmapMut;
 
+/// The longest file name most file systems allow, in bytes
+const MAX_FILE_NAME_LEN: usize = 255;
+
+/// Name of the file recording the identifiers of chunks stored under hashed file names
+const MANIFEST_FILE_NAME: &str = ".chunky_manifest";
+
+/// Prefix of the temporary names of files being created by `load_or_create_chunk`
+const TEMP_FILE_PREFIX: &str = ".chunky_creating_";
+
+/// Makes temporary file names unique within a process
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
 /// A `ChunkStorage` that allocates chunks by mmapping files
 pub struct MmapStorage {
-    directory: PathBuf
+    directory: PathBuf,
+    options: MmapOptions,
+    /// Identifiers of chunks stored under hashed file names, by file name
+    manifest: Mutex<HashMap<String, String>>,
+}
+
+/// How the memory of mmap'ed chunks is expected to be accessed,
+/// passed on to the OS as a (best-effort) hint using `madvise`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MmapAdvice {
+    /// No particular access pattern
+    Normal,
+    /// Items are expected to be accessed in order, so pages can be read ahead aggressively
+    Sequential,
+    /// Items are expected to be accessed in random order, so read-ahead is wasted
+    Random,
+    /// The whole chunk is expected to be accessed soon, so it can be paged in ahead of time
+    WillNeed,
+}
+
+/// Options for how an `MmapStorage` creates and maps its files
+#[derive(Copy, Clone, Debug)]
+pub struct MmapOptions {
+    /// Access pattern hint for all mapped chunks
+    pub advice: MmapAdvice,
+    /// Whether new files are only grown to their size (creating sparse files on most file systems),
+    /// or filled with zeros so their space is actually allocated on disk
+    pub sparse: bool,
+    /// Whether to allocate the disk space of new files up front (using `posix_fallocate` where
+    /// available), so running out of space is reported when creating a chunk, rather than
+    /// crashing with `SIGBUS` when later writing to it. Takes precedence over `sparse`.
+    pub preallocate: bool,
+    /// Whether to put the files of each chunk group (see `Ident::group`) in a subdirectory of their own,
+    /// instead of putting all files in one directory
+    pub group_directories: bool,
+    /// Whether to store chunks whose identifiers are too long to be file names (over 255 bytes)
+    /// in files named after a hash of the identifier, recording the full identifiers
+    /// in a manifest file, so they can still be listed
+    pub hash_long_names: bool,
+    /// Whether to overwrite chunks with `FORGOTTEN_CHUNK_POISON` before forgetting them,
+    /// so use of stale pointers into them (or other chunks of the same file) is easy to spot
+    pub poison_on_forget: bool,
 }
 
-pub struct MmapStorageHandle(MmapMut, Ident);
+impl Default for MmapOptions {
+    fn default() -> Self {
+        MmapOptions {
+            advice: MmapAdvice::Normal,
+            sparse: true,
+            preallocate: false,
+            group_directories: false,
+            hash_long_names: false,
+            poison_on_forget: false,
+        }
+    }
+}
+
+/// Empty files can't be mapped, so chunks of them don't have a mapping
+pub struct MmapStorageHandle(Option<MmapMut>, Ident);
+
+impl MmapStorageHandle {
+    fn flush(&self) -> ::std::io::Result<()> {
+        self.0.as_ref().map_or(Ok(()), MmapMut::flush)
+    }
+
+    fn poison(&mut self) {
+        if let Some(ref mut mmap) = self.0 {
+            mmap.fill(crate::FORGOTTEN_CHUNK_POISON);
+        }
+    }
+}
 
 impl Drop for MmapStorageHandle {
     fn drop(&mut self) {
-        self.0.flush().expect(format!("Couldn't flush file {}", &((self.1).0)).as_str());
+        self.flush().expect(format!("Couldn't flush file {}", &((self.1).0)).as_str());
     }
 }
 
 impl MmapStorage {
-    /// Create a new MmapStorage which will put files in `directory`
+    /// Create a new MmapStorage which will put files in `directory`, creating it if needed
     pub fn new(directory: PathBuf) -> MmapStorage {
-        MmapStorage{directory}
+        Self::with_options(directory, MmapOptions::default())
+    }
+
+    /// Create a new MmapStorage which will put files in `directory`,
+    /// creating and mapping them according to `options`
+    pub fn with_options(directory: PathBuf, options: MmapOptions) -> MmapStorage {
+        ::std::fs::create_dir_all(&directory)
+            .unwrap_or_else(|_| panic!("Can't create directory {}", directory.to_string_lossy()));
+
+        let manifest = match ::std::fs::read_to_string(directory.join(MANIFEST_FILE_NAME)) {
+            Ok(manifest) if options.hash_long_names => manifest
+                .lines()
+                .map(|ident| (hashed_file_name(ident), ident.to_owned()))
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        MmapStorage{directory, options, manifest: Mutex::new(manifest)}
     }
 
-    fn chunk_from_file(file: File, file_path: &Path, ident: Ident) -> Chunk {
+    fn file_name<'a>(&self, ident: &'a Ident) -> Cow<'a, str> {
+        if self.options.hash_long_names && ident.0.len() > MAX_FILE_NAME_LEN {
+            Cow::Owned(hashed_file_name(&ident.0))
+        } else {
+            Cow::Borrowed(&ident.0)
+        }
+    }
+
+    fn file_path(&self, ident: &Ident) -> PathBuf {
+        let file_name = self.file_name(ident);
+        if self.options.group_directories {
+            self.directory.join(ident.group()).join(file_name.as_ref())
+        } else {
+            self.directory.join(file_name.as_ref())
+        }
+    }
+
+    /// The identifiers of all chunk files in `directory`
+    fn list_directory(&self, directory: &Path) -> Vec<Ident> {
+        let manifest = self.manifest.lock().unwrap();
+
+        match ::std::fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|file_name| file_name != MANIFEST_FILE_NAME && !file_name.starts_with(TEMP_FILE_PREFIX))
+                .map(|file_name| Ident(manifest.get(&file_name).cloned().unwrap_or(file_name)))
+                .collect(),
+            // a group directory that doesn't exist yet just doesn't contain any chunks
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The identifiers of all chunks in this storage, regardless of their group
+    pub(crate) fn list_all_chunks(&self) -> Vec<Ident> {
+        let mut idents = self.list_directory(&self.directory);
+        if self.options.group_directories {
+            let group_directories = ::std::fs::read_dir(&self.directory).into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false))
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>();
+            for group_directory in group_directories {
+                idents.extend(self.list_directory(&group_directory));
+            }
+        }
+        idents
+    }
+
+    /// Record the identifier of a chunk about to be created in the manifest, if it gets a hashed file name,
+    /// failing if another identifier already has the same hash
+    fn register_file_name(&self, ident: &Ident) -> ::std::io::Result<()> {
+        let file_name = match self.file_name(ident) {
+            Cow::Owned(file_name) => file_name,
+            Cow::Borrowed(_) => return Ok(()),
+        };
+
+        let mut manifest = self.manifest.lock().unwrap();
+        match manifest.get(&file_name) {
+            Some(registered) if *registered == ident.0 => Ok(()),
+            Some(registered) => Err(::std::io::Error::new(
+                ::std::io::ErrorKind::AlreadyExists,
+                format!("Hashed file name {} is already used by chunk {}", file_name, registered),
+            )),
+            None => {
+                let mut manifest_file = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(self.directory.join(MANIFEST_FILE_NAME))?;
+                writeln!(manifest_file, "{}", ident.0)?;
+                manifest.insert(file_name, ident.0.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Create the group directory of a file about to be created, if needed
+    fn create_group_directory(&self, file_path: &Path) -> ::std::io::Result<()> {
+        match file_path.parent() {
+            Some(group_directory) if self.options.group_directories => ::std::fs::create_dir_all(group_directory),
+            _ => Ok(()),
+        }
+    }
+
+    /// Grow a file of `old_size` bytes to `size` bytes, according to the options
+    fn grow_file(&self, mut file: &File, old_size: usize, size: usize) -> ::std::io::Result<()> {
+        file.seek(SeekFrom::Start(old_size as u64))?;
+        if self.options.preallocate {
+            preallocate(file, old_size, size)
+        } else if self.options.sparse {
+            file.set_len(size as u64)
+        } else {
+            file.write_all(&vec![0u8; size - old_size])
+        }
+    }
+
+    /// Create the file of a chunk of `size` bytes at `file_path` by growing it under a temporary name first,
+    /// then linking it into place (which fails if the file exists), so it only ever appears fully grown.
+    /// Returns `None` if the file already exists, such as when it was created concurrently.
+    /// On file systems without hard links, this falls back to `create_file_in_place`.
+    fn create_file_atomically(&self, file_path: &Path, size: usize) -> ::std::io::Result<Option<File>> {
+        let temp_path = file_path.with_file_name(format!(
+            "{}{}_{}",
+            TEMP_FILE_PREFIX, ::std::process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+                            .read(true)
+                            .write(true)
+                            .create_new(true)
+                            .open(&temp_path)?;
+        if let Err(err) = self.grow_file(&file, 0, size) {
+            let _ = ::std::fs::remove_file(&temp_path);
+            return Err(err);
+        }
+        let linked = ::std::fs::hard_link(&temp_path, file_path);
+        let _ = ::std::fs::remove_file(&temp_path);
+
+        match linked {
+            Ok(()) => Ok(Some(file)),
+            Err(err) if err.kind() == ::std::io::ErrorKind::AlreadyExists => Ok(None),
+            Err(err) if matches!(err.kind(), ::std::io::ErrorKind::Unsupported | ::std::io::ErrorKind::PermissionDenied) => {
+                self.create_file_in_place(file_path, size)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Create the file of a chunk of `size` bytes directly at `file_path` (which fails if the file exists),
+    /// like `create_file_atomically` but without needing hard links. Returns `None` if the file already exists.
+    /// Only who created the file is still decided atomically: concurrent loaders might see it before it is fully grown.
+    fn create_file_in_place(&self, file_path: &Path, size: usize) -> ::std::io::Result<Option<File>> {
+        let file = match OpenOptions::new().read(true).write(true).create_new(true).open(file_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ::std::io::ErrorKind::AlreadyExists => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if let Err(err) = self.grow_file(&file, 0, size) {
+            let _ = ::std::fs::remove_file(file_path);
+            return Err(err);
+        }
+        Ok(Some(file))
+    }
+
+    /// Check that the file of a chunk exists and has exactly `expected_size`, returning an error
+    /// if it doesn't, such as when it was only partially written before a crash
+    pub fn validate_chunk(&self, ident: &Ident, expected_size: usize) -> ::std::io::Result<()> {
+        let file_path = self.file_path(ident);
+        let size = ::std::fs::metadata(&file_path)?.len();
+        if size == expected_size as u64 {
+            Ok(())
+        } else {
+            Err(::std::io::Error::new(
+                ::std::io::ErrorKind::InvalidData,
+                format!("File {} of chunk {} has {} bytes instead of {}", file_path.to_string_lossy(), ident.0, size, expected_size),
+            ))
+        }
+    }
+
+    /// Grow the file of a chunk to `new_size`, preserving its contents, and map it again.
+    ///
+    /// A mapping can't be grown in place, so the chunk is flushed and unmapped first:
+    /// pointers into it are invalid afterwards, only the returned chunk can be used.
+    /// Other chunks loaded from the same file keep seeing its old size.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` for chunks that weren't created by this storage,
+    /// which are only dropped.
+    pub fn remap_grown(&self, chunk: Chunk, new_size: usize) -> ::std::io::Result<Chunk> {
+        assert!(new_size >= chunk.len(), "Chunks can only be grown");
+        let old_size = chunk.len();
+        let handle = chunk._handle_to_drop.downcast::<MmapStorageHandle>()
+            .map_err(|_| crate::foreign_chunk_error("MmapStorage"))?;
+        let file_path = self.file_path(&handle.1);
+        handle.flush()?;
+        let ident = handle.1.clone();
+        ::std::mem::drop(handle);
+
+        let file = OpenOptions::new()
+                            .read(true)
+                            .write(true)
+                            .open(&file_path)?;
+        self.grow_file(&file, old_size, new_size)?;
+
+        Ok(self.chunk_from_file(file, &file_path, ident))
+    }
+
+    fn chunk_from_file(&self, file: File, file_path: &Path, ident: Ident) -> Chunk {
+        let file_len = file.metadata()
+            .unwrap_or_else(|_| panic!("Can't read metadata of file {}", file_path.to_string_lossy()))
+            .len();
         let mut handle = MmapStorageHandle(
-            unsafe { MmapMut::map_mut(&file).expect(format!("Can't mmap file {}", file_path.to_string_lossy()).as_str())},
+            if file_len == 0 {
+                None
+            } else {
+                Some(unsafe { MmapMut::map_mut(&file).expect(format!("Can't mmap file {}", file_path.to_string_lossy()).as_str())})
+            },
             ident
         );
 
-        Chunk {
-            ptr: handle.0.as_mut_ptr(),
-            len: handle.0.len(),
-            _handle_to_drop: Box::new(handle)
-        }
+        let (ptr, len) = match handle.0 {
+            Some(ref mut mmap) => {
+                advise(mmap, self.options.advice);
+                (mmap.as_mut_ptr(), mmap.len())
+            }
+            None => (::std::ptr::NonNull::dangling().as_ptr(), 0),
+        };
+
+        unsafe { Chunk::from_raw_parts(ptr, len, ChunkKind::Persistent, Box::new(handle)) }
     }
 }
 
 impl ChunkStorage for MmapStorage {
     fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
-        let file_path = self.directory.join(&ident.0);
+        let file_path = self.file_path(&ident);
+        self.try_create_chunk(ident, size)
+            .unwrap_or_else(|err| panic!("Can't create file {}: {}", file_path.to_string_lossy(), err))
+    }
+
+    /// Fails if the file can't be created or grown to `size`, in which case it is removed again
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        let file_path = self.file_path(&ident);
+        self.create_group_directory(&file_path)?;
+        self.register_file_name(&ident)?;
         let file = OpenOptions::new()
                             .read(true)
                             .write(true)
                             .create_new(true)
-                            .open(&file_path).expect(format!("Can't create file {}", file_path.to_string_lossy()).as_str());
-        file.set_len(size as u64).expect(format!("Can't grow file {}", file_path.to_string_lossy()).as_str());
+                            .open(&file_path)?;
 
-        Self::chunk_from_file(file, &file_path, ident)
+        if let Err(err) = self.grow_file(&file, 0, size) {
+            ::std::mem::drop(file);
+            let _ = ::std::fs::remove_file(&file_path);
+            return Err(err);
+        }
+
+        Ok(self.chunk_from_file(file, &file_path, ident))
     }
 
+    /// Safe against concurrent callers (including other processes) for the same chunk:
+    /// exactly one of them creates it, and the others load it once it is fully grown
     fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
-        let file_path = self.directory.join(&ident.0);
-        let existed = ::std::fs::metadata(&file_path).is_ok();
+        let file_path = self.file_path(&ident);
+        self.create_group_directory(&file_path)
+            .unwrap_or_else(|_| panic!("Can't create directory for file {}", file_path.to_string_lossy()));
+        self.register_file_name(&ident)
+            .unwrap_or_else(|err| panic!("Can't create file {}: {}", file_path.to_string_lossy(), err));
 
-        let file = OpenOptions::new()
-                            .read(true)
-                            .write(true)
-                            .create(true)
-                            .open(&file_path).expect(format!("Can't load or create file {}", file_path.to_string_lossy()).as_str());
-        if !existed {
-            file.set_len(size as u64).expect(format!("Can't grow file {}", file_path.to_string_lossy()).as_str());
-        }
+        let created = if ::std::fs::metadata(&file_path).is_ok() {
+            None
+        } else {
+            self.create_file_atomically(&file_path, size)
+                .unwrap_or_else(|err| panic!("Can't create file {}: {}", file_path.to_string_lossy(), err))
+        };
 
-        (Self::chunk_from_file(file, &file_path, ident), !existed)
+        match created {
+            Some(file) => (self.chunk_from_file(file, &file_path, ident), true),
+            None => (self.load_chunk(ident), false),
+        }
     }
 
     fn load_chunk(&self, ident: Ident) -> Chunk {
-        let file_path = self.directory.join(&ident.0);
+        let file_path = self.file_path(&ident);
         let file = OpenOptions::new()
                             .read(true)
                             .write(true)
                             .open(&file_path).expect(format!("Can't load file {}", file_path.to_string_lossy()).as_str());
 
-        Self::chunk_from_file(file, &file_path, ident)
+        self.chunk_from_file(file, &file_path, ident)
     }
 
-    /// Deallocate a chunk and delete any persisted representation of it
-    /// (unlike Drop, which only unloads a chunk)
     fn forget_chunk(&self, chunk: Chunk) {
-        let handle = chunk._handle_to_drop.downcast::<MmapStorageHandle>().expect("MmapStorage got handed a foreign chunk.");
-        let ident = handle.1.clone();
-        let file_path = self.directory.join(&ident.0);
+        let file_path = match chunk._handle_to_drop.downcast_ref::<MmapStorageHandle>() {
+            Some(handle) => self.file_path(&handle.1),
+            None => return crate::warn_foreign_chunk("MmapStorage"),
+        };
+        self.try_forget_chunk(chunk).expect(format!("Couldn't remove file {}", file_path.to_string_lossy()).as_str());
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let mut handle = chunk._handle_to_drop.downcast::<MmapStorageHandle>()
+            .map_err(|_| crate::foreign_chunk_error("MmapStorage"))?;
+        if self.options.poison_on_forget {
+            handle.poison();
+        }
+        let file_path = self.file_path(&handle.1);
         std::mem::drop(handle);
-        ::std::fs::remove_file(&file_path).expect(format!("Couldn't remove file {}", file_path.to_string_lossy()).as_str());
+        ::std::fs::remove_file(&file_path)
+    }
+
+    /// Unmaps all chunks first, then removes all their files in one go
+    fn forget_chunks(&self, chunks: Vec<Chunk>) {
+        let file_paths = chunks.into_iter().filter_map(|chunk| {
+            match chunk._handle_to_drop.downcast::<MmapStorageHandle>() {
+                Ok(mut handle) => {
+                    if self.options.poison_on_forget {
+                        handle.poison();
+                    }
+                    Some(self.file_path(&handle.1))
+                }
+                Err(_) => {
+                    crate::warn_foreign_chunk("MmapStorage");
+                    None
+                }
+            }
+        }).collect::<Vec<_>>();
+
+        for file_path in file_paths {
+            ::std::fs::remove_file(&file_path).unwrap_or_else(|_| panic!("Couldn't remove file {}", file_path.to_string_lossy()));
+        }
+    }
+
+    fn flush_chunk(&self, chunk: &Chunk) {
+        let handle = match chunk._handle_to_drop.downcast_ref::<MmapStorageHandle>() {
+            Some(handle) => handle,
+            None => return crate::warn_foreign_flush("MmapStorage"),
+        };
+        handle.flush().unwrap_or_else(|_| panic!("Couldn't flush file {}", (handle.1).0));
+    }
+
+    fn page_size(&self) -> usize {
+        page_size()
     }
-}
\ No newline at end of file
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.file_path(ident).is_file()
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        ::std::fs::metadata(self.file_path(ident))
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len() as usize)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        let group_directory = self.file_path(group).parent().expect("should have directory").to_owned();
+        self.list_directory(&group_directory)
+            .into_iter()
+            .filter(|ident| ident.belongs_to(group))
+            .collect()
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let file_path = self.file_path(ident);
+        let bytes = ::std::fs::read(&file_path)
+            .unwrap_or_else(|_| panic!("Can't read file {}", file_path.to_string_lossy()));
+        crate::checksum(&bytes)
+    }
+}
+
+/// A file name for an identifier that is too long to be one, keeping a prefix of it
+/// for readability, followed by its hash
+fn hashed_file_name(ident: &str) -> String {
+    let mut prefix_len = 64;
+    while !ident.is_char_boundary(prefix_len) {
+        prefix_len -= 1;
+    }
+    format!("{}~{:016x}", &ident[..prefix_len], crate::checksum(ident.as_bytes()))
+}
+
+/// The OS page size, which mmap'ed chunks are mapped in units of
+#[cfg(unix)]
+pub(crate) fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn page_size() -> usize {
+    4096
+}
+
+/// Allocate `size` bytes of disk space for `file`, failing (e.g. with `ENOSPC`) if that's not possible
+#[cfg(unix)]
+fn preallocate(file: &File, _old_size: usize, size: usize) -> ::std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if size == 0 {
+        return Ok(());
+    }
+    // posix_fallocate returns the error number instead of setting errno
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, size as libc::off_t) } {
+        0 => Ok(()),
+        errno => Err(::std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Without `posix_fallocate`, write zeros to actually allocate the space
+#[cfg(not(unix))]
+fn preallocate(mut file: &File, old_size: usize, size: usize) -> ::std::io::Result<()> {
+    file.write_all(&vec![0u8; size - old_size])
+}
+
+/// Pass `advice` on to the OS for the whole mapping, ignoring failure since it is only a hint
+#[cfg(unix)]
+fn advise(mmap: &mut MmapMut, advice: MmapAdvice) {
+    let flag = match advice {
+        MmapAdvice::Normal => libc::MADV_NORMAL,
+        MmapAdvice::Sequential => libc::MADV_SEQUENTIAL,
+        MmapAdvice::Random => libc::MADV_RANDOM,
+        MmapAdvice::WillNeed => libc::MADV_WILLNEED,
+    };
+    if !mmap.is_empty() {
+        unsafe {
+            libc::madvise(mmap.as_mut_ptr() as *mut libc::c_void, mmap.len(), flag);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn advise(_mmap: &mut MmapMut, _advice: MmapAdvice) {}
+
+/// Mmap'ed chunks are owned mappings, which can be used from any thread
+unsafe impl SendableStorage for MmapStorage {}
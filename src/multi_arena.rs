@@ -1,12 +1,42 @@
-use crate::{ChunkStorage, Ident};
+use crate::{Chunk, ChunkStorage, Ident};
 use crate::arena::{Arena, ArenaIndex};
 use crate::vector::Vector;
-use ::std::rc::Rc;
+use crate::shared::{SendableStorage, Shared};
+use ::alloc::rc::Rc;
+#[cfg(feature = "std")]
+use ::std::collections::HashMap;
+use ::alloc::sync::Arc;
+use alloc::vec::Vec;
 
 /// Refers to an item in a `MultiArena`
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MultiArenaIndex(pub usize, pub ArenaIndex);
 
+/// Refers to an item in a `MultiArena` together with the size it was pushed with,
+/// so it can be read back and written to safely
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SizedHandle {
+    /// The index of the item
+    pub index: MultiArenaIndex,
+    /// The size the item was pushed with
+    pub size: usize,
+}
+
+/// Returned when an item is too large to be assigned to any bin of a `MultiArena`
+#[derive(Debug)]
+pub struct SizeTooLargeError {
+    /// The requested item size
+    pub size: usize,
+}
+
+impl ::core::fmt::Display for SizeTooLargeError {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "Item size {} is too large for any bin", self.size)
+    }
+}
+
+impl ::core::error::Error for SizeTooLargeError {}
+
 /// Based on a collection type for fixed-size items ("Bin"), creates a collection for
 /// heterogenously-sized items which will be stored in the most appropriately-sized bin.
 ///
@@ -20,19 +50,36 @@ pub struct MultiArena {
     /// The bin at index `i` will have item-size `base_size * 2 ^ i`
     bins: Vec<Option<Arena>>,
     used_bin_sizes: Vector<usize>,
+    /// Items larger than this go into the overflow bin, if set
+    max_item_size: Option<usize>,
+    /// The exactly-sized chunks holding the items of the overflow bin
+    overflow: Vec<Chunk>,
+    /// Sizes of all items of the overflow bin, if there is a maximum item size
+    overflow_sizes: Option<Vector<usize>>,
     storage: Rc<dyn ChunkStorage>
 }
 
 impl MultiArena {
+    /// The bin index of the overflow bin of a `MultiArena` with a maximum item size,
+    /// which stores each item larger than that in its own exactly-sized chunk
+    pub const OVERFLOW_BIN: usize = usize::MAX;
+
     /// Create a new `MultiArena` collection using `Arena` bins and a base size that represents
-    /// the smallest expected item size (used as the item size of the smallest-sized bin)
+    /// the smallest expected item size (used as the item size of the smallest-sized bin).
+    ///
+    /// Bin item sizes are `base_size` times a power of two, so any positive base size
+    /// gives consistent bins, but items are only aligned to the largest power of two dividing it.
     pub fn new(ident: Ident, typical_chunk_size: usize, base_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        assert!(base_size > 0, "MultiArena base size has to be positive");
         let mut multi_arena = MultiArena {
             typical_chunk_size,
             base_size,
             used_bin_sizes: Vector::<usize>::new(ident.sub("bin_sizes"), 1024, Rc::clone(&storage)),
             ident,
             bins: Vec::new(),
+            max_item_size: None,
+            overflow: Vec::new(),
+            overflow_sizes: None,
             storage
         };
 
@@ -40,49 +87,202 @@ impl MultiArena {
 
         for i in 0..n_bins {
             let size = *multi_arena.used_bin_sizes.at(i).unwrap();
-            multi_arena.get_or_insert_bin_for_size(size);
+            let (index, size_rounded_up) = multi_arena.bin_index_and_size(size).expect("Persisted bin size should be valid");
+            // the size is already persisted, so it mustn't be registered again
+            multi_arena.insert_bin(index, size_rounded_up);
         }
 
         multi_arena
     }
 
-    fn size_rounded_multiple(&self, size: usize) -> usize {
-        let size_rounded_to_base_size = (size + self.base_size - 1) / self.base_size;
-        size_rounded_to_base_size.next_power_of_two()
+    /// Create a new `MultiArena` like `new`, but with items larger than `max_item_size` going into
+    /// the overflow bin (see `OVERFLOW_BIN`) instead of a bin of their size rounded up to a power of two,
+    /// which would waste up to half of a chunk that is larger than typical.
+    /// It has to be loaded again with the same `max_item_size`.
+    pub fn new_with_max_item_size(ident: Ident, typical_chunk_size: usize, base_size: usize, max_item_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        let mut multi_arena = Self::new(ident, typical_chunk_size, base_size, storage);
+        let overflow_sizes = Vector::<usize>::new(multi_arena.ident.sub("overflow_sizes"), 1024, Rc::clone(&multi_arena.storage));
+        multi_arena.overflow = (0..overflow_sizes.len())
+            .map(|item_index| multi_arena.storage.load_chunk(multi_arena.overflow_ident(item_index)))
+            .collect();
+        multi_arena.max_item_size = Some(max_item_size);
+        multi_arena.overflow_sizes = Some(overflow_sizes);
+        multi_arena
+    }
+
+    /// Create a new `MultiArena` like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, typical_chunk_size: usize, base_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, typical_chunk_size, base_size, storage))
+    }
+
+    /// Get the index of the bin for items of size `size` and that bin's (rounded-up) item size
+    fn bin_index_and_size(&self, size: usize) -> Result<(usize, usize), SizeTooLargeError> {
+        let size_rounded_to_base_size = size.div_ceil(self.base_size);
+        let size_rounded_multiple = size_rounded_to_base_size
+            .checked_next_power_of_two()
+            .ok_or(SizeTooLargeError { size })?;
+        let size_rounded_up = size_rounded_multiple
+            .checked_mul(self.base_size)
+            .ok_or(SizeTooLargeError { size })?;
+        Ok((size_rounded_multiple.trailing_zeros() as usize, size_rounded_up))
+    }
+
+    /// Get the index of the Bin which stores items of size `size` (`OVERFLOW_BIN` if it's larger
+    /// than the maximum item size), or an error if `size` is too large for any bin
+    pub fn try_size_to_index(&self, size: usize) -> Result<usize, SizeTooLargeError> {
+        if self.is_oversized(size) {
+            return Ok(Self::OVERFLOW_BIN);
+        }
+        self.bin_index_and_size(size).map(|(index, _)| index)
+    }
+
+    pub(crate) fn storage(&self) -> &dyn ChunkStorage {
+        &*self.storage
+    }
+
+    fn is_oversized(&self, size: usize) -> bool {
+        self.max_item_size.is_some_and(|max_item_size| size > max_item_size)
+    }
+
+    fn overflow_ident(&self, item_index: usize) -> Ident {
+        self.ident.sub("overflow").sub(item_index)
+    }
+
+    fn overflow_chunk(&self, item_index: usize) -> &Chunk {
+        self.overflow.get(item_index).expect("No item at this index")
+    }
+
+    /// Add an item to the overflow bin, in a chunk of its own
+    fn push_overflow(&mut self, size: usize) -> (*mut u8, MultiArenaIndex) {
+        let item_index = self.overflow.len();
+        let mut chunk = self.storage.create_chunk(self.overflow_ident(item_index), size);
+        let ptr = chunk.as_mut_ptr();
+        self.overflow.push(chunk);
+        self.overflow_sizes.as_mut().expect("Should have overflow sizes").push(size);
+        (ptr, MultiArenaIndex(Self::OVERFLOW_BIN, ArenaIndex(item_index)))
+    }
+
+    /// Forget the chunk of an item of the overflow bin and move the last item into its place,
+    /// returning that unless the removed item was the last one.
+    /// Chunks are found by item index, so the last item is copied into a new chunk named after its new index.
+    fn swap_remove_overflow(&mut self, item_index: usize) -> Option<*const u8> {
+        assert!(item_index < self.overflow.len(), "No item at this index");
+        let last_index = self.overflow.len() - 1;
+        self.storage.forget_chunk(self.overflow.swap_remove(item_index));
+        self.overflow_sizes.as_mut().expect("Should have overflow sizes").pop();
+        if item_index == last_index {
+            return None;
+        }
+
+        let mut moved = self.storage.create_chunk(self.overflow_ident(item_index), self.overflow[item_index].len());
+        moved.copy_from_slice(&self.overflow[item_index]);
+        self.storage.flush_chunk(&moved);
+        let size = moved.len();
+        self.storage.forget_chunk(::core::mem::replace(&mut self.overflow[item_index], moved));
+        *self.overflow_sizes.as_mut().expect("Should have overflow sizes").at_mut(item_index).expect("Should have overflow size") = size;
+        Some(self.overflow[item_index].as_ptr())
     }
 
     /// Get the index of the Bin which stores items of size `size`
     pub fn size_to_index(&self, size: usize) -> usize {
-        (self.size_rounded_multiple(size) as f32).log2() as usize
+        self.try_size_to_index(size).unwrap_or_else(|err| panic!("{}", err))
     }
 
-    fn get_or_insert_bin_for_size(&mut self, size: usize) -> &mut Arena {
-        let index = self.size_to_index(size);
-        let size_rounded_up = self.size_rounded_multiple(size) * self.base_size;
-
+    /// Create (or load) the bin of the given bin index for items of size `size_rounded_up`
+    fn insert_bin(&mut self, index: usize, size_rounded_up: usize) -> &mut Arena {
         if index >= self.bins.len() {
             self.bins.resize_with(index + 1, Default::default)
         }
 
-        let maybe_bin = &mut self.bins[index];
+        let chunk_size = ::core::cmp::max(self.typical_chunk_size, size_rounded_up);
+        let bin = Arena::new(
+            self.ident.sub(size_rounded_up),
+            chunk_size,
+            size_rounded_up,
+            Rc::clone(&self.storage)
+        );
+        self.bins[index].get_or_insert(bin)
+    }
+
+    fn get_or_insert_bin_for_size(&mut self, size: usize) -> Result<&mut Arena, SizeTooLargeError> {
+        let (index, size_rounded_up) = self.bin_index_and_size(size)?;
+
+        if self.bins.get(index).is_some_and(Option::is_some) {
+            return Ok(self.bins[index].as_mut().unwrap());
+        }
+
+        self.used_bin_sizes.push(size_rounded_up);
+        Ok(self.insert_bin(index, size_rounded_up))
+    }
 
-        if let Some(ref mut bin) = *maybe_bin {
-            bin
-        } else {
-            self.used_bin_sizes.push(size_rounded_up);
-            let chunk_size = ::std::cmp::max(self.typical_chunk_size, size_rounded_up);
-            *maybe_bin = Some(Arena::new(
-                self.ident.sub(size_rounded_up),
-                chunk_size,
-                size_rounded_up,
-                Rc::clone(&self.storage)
-            ));
-            maybe_bin.as_mut().unwrap()
+    /// Make sure the bin for items of size `size` exists, creating it if needed,
+    /// and return its bin index. The bin is recreated when the `MultiArena` is loaded again.
+    pub fn ensure_bin(&mut self, size: usize) -> usize {
+        if self.is_oversized(size) {
+            return Self::OVERFLOW_BIN;
+        }
+        self.get_or_insert_bin_for_size(size).unwrap_or_else(|err| panic!("{}", err));
+        self.size_to_index(size)
+    }
+
+    /// Create chunks ahead of time in the bin for items of size `size`,
+    /// so that at least `additional` more of them can be pushed without allocating.
+    /// Items of the overflow bin get their chunk when they are pushed, so nothing is reserved for them.
+    pub fn reserve_bin(&mut self, size: usize, additional: usize) {
+        if self.is_oversized(size) {
+            return;
+        }
+        self.get_or_insert_bin_for_size(size)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .reserve(additional);
+    }
+
+    /// Forget trailing chunks that don't hold any items in the bin of the given bin index
+    pub fn shrink_bin(&mut self, bin_index: usize) {
+        if bin_index == Self::OVERFLOW_BIN {
+            return;
+        }
+        self.bins[bin_index]
+            .as_mut()
+            .expect("No bin at this index")
+            .shrink_to_fit();
+    }
+
+    /// Forget trailing chunks that don't hold any items in all bins
+    pub fn shrink_to_fit(&mut self) {
+        for bin in self.bins.iter_mut().filter_map(|maybe_bin| maybe_bin.as_mut()) {
+            bin.shrink_to_fit();
+        }
+    }
+
+    /// Forget the trailing chunks without items of all bins, like `shrink_to_fit`,
+    /// returning the number of bytes freed. Items are always packed towards the front
+    /// of their bin (removal moves the last item into the gap), so there is nothing else to compact.
+    pub fn defragment(&mut self) -> usize {
+        let (_, allocated_before) = self.utilization();
+        self.shrink_to_fit();
+        let (_, allocated_after) = self.utilization();
+        allocated_before - allocated_after
+    }
+
+    /// Forget all chunks of all bins, as well as the persisted bin sizes,
+    /// deleting any persisted representation of this multi arena
+    pub fn forget_all(self) {
+        for bin in self.bins.into_iter().flatten() {
+            bin.forget_all();
+        }
+        self.used_bin_sizes.forget_all();
+        self.storage.forget_chunks(self.overflow);
+        if let Some(overflow_sizes) = self.overflow_sizes {
+            overflow_sizes.forget_all();
         }
     }
 
     /// Get an (untyped) pointer to the item at the given index
     pub fn at(&self, index: MultiArenaIndex) -> *const u8 {
+        if index.0 == Self::OVERFLOW_BIN {
+            return self.overflow_chunk((index.1).0).as_ptr();
+        }
         unsafe {
             self.bins[index.0]
                 .as_ref()
@@ -91,8 +291,22 @@ impl MultiArena {
         }
     }
 
+    /// Get an (untyped) pointer to the item at the given index, together with the
+    /// item size of its bin, which is its pushed size rounded up (or exactly its pushed size in the overflow bin)
+    pub fn at_with_size(&self, index: MultiArenaIndex) -> (*const u8, usize) {
+        if index.0 == Self::OVERFLOW_BIN {
+            let chunk = self.overflow_chunk((index.1).0);
+            return (chunk.as_ptr(), chunk.len());
+        }
+        let bin = self.bins[index.0].as_ref().expect("No bin at this index");
+        (unsafe { bin.at(index.1) }, bin.item_size())
+    }
+
     /// Get an (untyped) mutable pointer to the item at the given index
     pub fn at_mut(&mut self, index: MultiArenaIndex) -> *mut u8 {
+        if index.0 == Self::OVERFLOW_BIN {
+            return self.overflow.get_mut((index.1).0).expect("No item at this index").as_mut_ptr();
+        }
         unsafe {
             self.bins[index.0]
                 .as_mut()
@@ -103,14 +317,55 @@ impl MultiArena {
 
     /// Add an item to the end of the bin corresponding to its size
     pub fn push(&mut self, size: usize) -> (*mut u8, MultiArenaIndex) {
-        let bin_index = self.size_to_index(size);
-        let bin = &mut self.get_or_insert_bin_for_size(size);
+        self.try_push(size).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `push`, but returns an error instead of panicking if `size` is too large for any bin
+    pub fn try_push(&mut self, size: usize) -> Result<(*mut u8, MultiArenaIndex), SizeTooLargeError> {
+        if self.is_oversized(size) {
+            return Ok(self.push_overflow(size));
+        }
+        let bin_index = self.try_size_to_index(size)?;
+        let bin = self.get_or_insert_bin_for_size(size)?;
         let (ptr, arena_index) = bin.push();
-        (ptr, MultiArenaIndex(bin_index, arena_index))
+        Ok((ptr, MultiArenaIndex(bin_index, arena_index)))
     }
 
-    /// Remove the item referenced by `index` from its bin by swapping with the bin's last item
+    /// Add an item of size `size` like `push`, returning a handle that remembers its size.
+    /// Its contents can then be accessed with `read` and `write`.
+    pub fn push_sized(&mut self, size: usize) -> SizedHandle {
+        let (_, index) = self.push(size);
+        SizedHandle { index, size }
+    }
+
+    /// Get the bytes of the item referenced by `handle`.
+    /// Panics if there is no such item, or if it's smaller than the handle's size.
+    pub fn read(&self, handle: SizedHandle) -> &[u8] {
+        self.assert_valid(handle);
+        unsafe { ::core::slice::from_raw_parts(self.at(handle.index), handle.size) }
+    }
+
+    /// Overwrite the bytes of the item referenced by `handle`, `bytes` has to be exactly as long as the item.
+    /// Panics if there is no such item, or if it's smaller than the handle's size.
+    pub fn write(&mut self, handle: SizedHandle, bytes: &[u8]) {
+        assert_eq!(bytes.len(), handle.size, "Item size mismatch");
+        self.assert_valid(handle);
+        unsafe { ::core::slice::from_raw_parts_mut(self.at_mut(handle.index), handle.size) }.copy_from_slice(bytes);
+    }
+
+    /// Handles can be built from arbitrary indices and sizes, so check that they refer to an item
+    /// that is at least as large as the handle says
+    fn assert_valid(&self, handle: SizedHandle) {
+        assert!((handle.index.1).0 < self.bin_len(handle.index.0), "No item at this index");
+        assert!(handle.size <= self.at_with_size(handle.index).1, "Handle size exceeds the item size");
+    }
+
+    /// Remove the item referenced by `index` from its bin by swapping with the bin's last item.
+    /// Items of the overflow bin are swapped the same way, by copying the last item into a new chunk.
     pub fn swap_remove_within_bin(&mut self, index: MultiArenaIndex) -> Option<*const u8> {
+        if index.0 == Self::OVERFLOW_BIN {
+            return self.swap_remove_overflow((index.1).0);
+        }
         unsafe {
             self.bins[index.0]
                 .as_mut()
@@ -127,13 +382,112 @@ impl MultiArena {
             .iter()
             .enumerate()
             .filter_map(|(index, maybe_bin)| maybe_bin.as_ref().map(|bin| (index, bin.len())))
+            .chain(self.overflow_sizes.as_ref().map(|_| (Self::OVERFLOW_BIN, self.bin_len(Self::OVERFLOW_BIN))))
+    }
+
+    /// Return `(item_size, count)` for each bin that contains items, sorted by item size,
+    /// where the item size is the bin's (rounded-up) item size, or the exact item size in the overflow bin
+    pub fn size_histogram(&self) -> Vec<(usize, usize)> {
+        let mut histogram = self.bins
+            .iter()
+            .filter_map(|maybe_bin| maybe_bin.as_ref())
+            .filter(|bin| !bin.is_empty())
+            .map(|bin| (bin.item_size(), bin.len()))
+            .collect::<Vec<_>>();
+        for chunk in self.overflow.iter() {
+            match histogram.iter_mut().find(|(item_size, _)| *item_size == chunk.len()) {
+                Some((_, count)) => *count += 1,
+                None => histogram.push((chunk.len(), 1)),
+            }
+        }
+        histogram.sort_unstable();
+        histogram
+    }
+
+    /// Iterate over the indices of and (untyped) pointers to all items, bin by bin
+    pub fn iter(&self) -> impl Iterator<Item = (MultiArenaIndex, *const u8)> + '_ {
+        self.bins
+            .iter()
+            .enumerate()
+            .filter_map(|(bin_index, maybe_bin)| maybe_bin.as_ref().map(|bin| (bin_index, bin)))
+            .flat_map(|(bin_index, bin)| {
+                (0..bin.len()).map(move |index| {
+                    (MultiArenaIndex(bin_index, ArenaIndex(index)), unsafe { bin.at(ArenaIndex(index)) })
+                })
+            })
+            .chain(self.overflow.iter().enumerate().map(|(item_index, chunk)| {
+                (MultiArenaIndex(Self::OVERFLOW_BIN, ArenaIndex(item_index)), chunk.as_ptr())
+            }))
+    }
+
+    /// Iterate over the indices of and (untyped) pointers to all items like `iter`, guaranteeing
+    /// ascending bin index order (with the overflow bin last), and ascending item index order within each bin.
+    /// The order only depends on the items' indices, not on the order bins were created or loaded in,
+    /// so dumping the items of equal multi arenas (such as one and its reloaded self) gives identical output.
+    pub fn iter_stable(&self) -> impl Iterator<Item = (MultiArenaIndex, *const u8)> + '_ {
+        // `bins` is indexed by bin index and the overflow bin has the highest one,
+        // so `iter` already has this order
+        self.iter()
+    }
+
+    /// Copy all items into a new `MultiArena` with a different base size,
+    /// returning it together with a map from old to new item indices
+    #[cfg(feature = "std")]
+    pub fn migrate(&self, new_base_size: usize, new_ident: Ident, storage: Rc<dyn ChunkStorage>) -> (MultiArena, HashMap<MultiArenaIndex, MultiArenaIndex>) {
+        let mut migrated = match self.max_item_size {
+            Some(max_item_size) => MultiArena::new_with_max_item_size(new_ident, self.typical_chunk_size, new_base_size, max_item_size, storage),
+            None => MultiArena::new(new_ident, self.typical_chunk_size, new_base_size, storage),
+        };
+        let mut new_indices = HashMap::new();
+
+        for (index, item_ptr) in self.iter() {
+            let (_, item_size) = self.at_with_size(index);
+            let (new_item_ptr, new_index) = migrated.push(item_size);
+            unsafe {
+                ::core::ptr::copy_nonoverlapping(item_ptr, new_item_ptr, item_size);
+            }
+            new_indices.insert(index, new_index);
+        }
+
+        (migrated, new_indices)
     }
 
     /// Get the length of the bin of the given bin index
     pub fn bin_len(&self, bin_index: usize) -> usize {
+        if bin_index == Self::OVERFLOW_BIN {
+            return self.overflow.len();
+        }
         self.bins[bin_index]
             .as_ref()
             .expect("No bin at this index")
             .len()
     }
+
+    /// Return `(live_bytes, allocated_bytes)` summed over all bins, where live bytes
+    /// count each item at its bin's (rounded-up) item size and allocated bytes
+    /// are the total size of all chunks of all bins
+    pub fn utilization(&self) -> (usize, usize) {
+        let overflow_bytes = self.overflow.iter().map(|chunk| chunk.len()).sum::<usize>();
+        self.bins
+            .iter()
+            .filter_map(|maybe_bin| maybe_bin.as_ref())
+            .fold((overflow_bytes, overflow_bytes), |(live, allocated), bin| {
+                (live + bin.len() * bin.item_size(), allocated + bin.allocated_bytes())
+            })
+    }
+}
+
+impl crate::Flushable for MultiArena {
+    fn flush(&self) {
+        for bin in self.bins.iter().flatten() {
+            bin.flush();
+        }
+        self.used_bin_sizes.flush();
+        for chunk in self.overflow.iter() {
+            self.storage.flush_chunk(chunk);
+        }
+        if let Some(ref overflow_sizes) = self.overflow_sizes {
+            overflow_sizes.flush();
+        }
+    }
 }
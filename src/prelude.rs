@@ -0,0 +1,29 @@
+//! Everything needed to set up storages and build collections on them,
+//! for glob-importing with `use chunky::prelude::*`
+
+pub use crate::{Chunk, ChunkKind, ChunkStorage, ChunkStorageExt, Ident};
+pub use crate::{SendableStorage, Shared};
+pub use crate::HeapStorage;
+#[cfg(feature = "std")]
+pub use crate::{BumpHeapStorage, VirtualFsStorage};
+#[cfg(feature = "mmap")]
+pub use crate::{MmapStorage, MmapOptions, MmapAdvice, AnonMmapStorage, SnapshotStorage};
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+pub use crate::LazyCompressed;
+#[cfg(feature = "direct_io")]
+pub use crate::DirectIoStorage;
+#[cfg(feature = "kv")]
+pub use crate::KvStorage;
+#[cfg(feature = "std")]
+pub use crate::{Logging, StorageEvent, Quota, Dedup};
+#[cfg(feature = "std")]
+pub use crate::{QueueReader, QueueWriter};
+pub use crate::{Value, Portable, PortableValue};
+pub use crate::{AtomicValue, AtomicInt};
+pub use crate::{Arena, ArenaIndex, TypedArena};
+pub use crate::{GenerationalArena, GenerationalIndex};
+pub use crate::{Vector, VectorSlice, Cursor, EnumerateIndices, Checkpoint, Transaction};
+pub use crate::{Queue, QueueStats, TaggedQueue, Deque};
+pub use crate::{MultiArena, MultiArenaIndex, SizedHandle};
+pub use crate::{BitVec, ChunkyMap};
+pub use crate::{Flushable, SaveCoordinator};
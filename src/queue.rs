@@ -1,7 +1,11 @@
-use crate::{Chunk, ChunkStorage, Ident};
-use crate::value::Value;
-use std::rc::Rc;
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::value::{Portable, PortableValue};
+use crate::shared::{SendableStorage, Shared};
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
+#[derive(Clone, Copy, Default)]
 struct QueueState {
     first_chunk_at: usize,
     last_chunk_at: usize,
@@ -10,13 +14,142 @@ struct QueueState {
     len: usize,
 }
 
+/// Persisted as five consecutive `u64`s, in field order
+impl Portable for QueueState {
+    const SIZE: usize = 5 * <usize as Portable>::SIZE;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        let fields = [self.first_chunk_at, self.last_chunk_at, self.read_at, self.write_at, self.len];
+        for (field, field_bytes) in fields.iter().zip(bytes.chunks_mut(<usize as Portable>::SIZE)) {
+            field.encode(field_bytes);
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let mut fields = bytes.chunks(<usize as Portable>::SIZE).map(usize::decode);
+        let mut next_field = || fields.next().expect("QueueState should have 5 fields");
+        QueueState {
+            first_chunk_at: next_field(),
+            last_chunk_at: next_field(),
+            read_at: next_field(),
+            write_at: next_field(),
+            len: next_field(),
+        }
+    }
+}
+
+/// Read offset beyond which a queue moves its chunks back to offset 0, see `drop_old_chunks`
+const REBASE_THRESHOLD: usize = 1 << 30;
+
+const OFFSET_OVERFLOW: &str = "Queue offsets overflowed, its unread items need to be read from time to time to rebase them";
+
+/// Size of a write-ahead log record: sequence number, kind, queue state, item checksum and checksum
+const WAL_RECORD_SIZE: usize = 4 * <u64 as Portable>::SIZE + QueueState::SIZE;
+/// Number of records the write-ahead log has room for
+const WAL_RECORDS: usize = 64;
+/// The first records of the log hold the latest two commits, alternating,
+/// so a commit torn by a crash leaves the previous one intact
+const WAL_COMMIT_SLOTS: usize = 2;
+
+const WAL_COMMIT: u64 = 1;
+const WAL_INTENT: u64 = 2;
+
+/// A record of the write-ahead log: either a commit with the queue state it made durable,
+/// or an enqueued item, with the queue state after it and the checksum of its header and contents
+struct WalRecord {
+    seq: u64,
+    kind: u64,
+    state: QueueState,
+    item_checksum: u64,
+}
+
+impl WalRecord {
+    fn write(&self, bytes: &mut [u8]) {
+        let u64_size = <u64 as Portable>::SIZE;
+        self.seq.encode(&mut bytes[..u64_size]);
+        self.kind.encode(&mut bytes[u64_size..2 * u64_size]);
+        self.state.encode(&mut bytes[2 * u64_size..2 * u64_size + QueueState::SIZE]);
+        self.item_checksum.encode(&mut bytes[2 * u64_size + QueueState::SIZE..3 * u64_size + QueueState::SIZE]);
+        let checksum = crate::checksum(&bytes[..WAL_RECORD_SIZE - u64_size]);
+        checksum.encode(&mut bytes[WAL_RECORD_SIZE - u64_size..WAL_RECORD_SIZE]);
+    }
+
+    /// Read a record, unless it was never written or torn by a crash
+    fn read(bytes: &[u8]) -> Option<WalRecord> {
+        let u64_size = <u64 as Portable>::SIZE;
+        let checksum = u64::decode(&bytes[WAL_RECORD_SIZE - u64_size..WAL_RECORD_SIZE]);
+        let seq = u64::decode(&bytes[..u64_size]);
+        if seq == 0 || checksum != crate::checksum(&bytes[..WAL_RECORD_SIZE - u64_size]) {
+            return None;
+        }
+        Some(WalRecord {
+            seq,
+            kind: u64::decode(&bytes[u64_size..2 * u64_size]),
+            state: QueueState::decode(&bytes[2 * u64_size..2 * u64_size + QueueState::SIZE]),
+            item_checksum: u64::decode(&bytes[2 * u64_size + QueueState::SIZE..3 * u64_size + QueueState::SIZE]),
+        })
+    }
+}
+
+/// The write-ahead log of a `Queue`, see `Queue::with_wal`
+struct WriteAheadLog {
+    chunk: Chunk,
+    /// Sequence number of the latest record
+    seq: u64,
+    /// Slot of the next commit record, alternating
+    next_commit: usize,
+    /// Slot of the next intent record
+    next_intent: usize,
+    /// The queue state before the latest enqueue, until its item is logged,
+    /// which happens once the next enqueue starts, because only then is the item fully written
+    unlogged: Option<QueueState>,
+}
+
+impl WriteAheadLog {
+    /// All valid records, with their slots
+    fn records(&self) -> impl Iterator<Item = (usize, WalRecord)> + '_ {
+        self.chunk.chunks(WAL_RECORD_SIZE)
+            .enumerate()
+            .filter_map(|(slot, bytes)| WalRecord::read(bytes).map(|record| (slot, record)))
+    }
+
+    /// Write a record into `slot` and flush it, so it is durable before the queue goes on
+    fn append(&mut self, slot: usize, kind: u64, state: QueueState, item_checksum: u64, storage: &dyn ChunkStorage) {
+        self.seq += 1;
+        WalRecord { seq: self.seq, kind, state, item_checksum }
+            .write(&mut self.chunk[slot * WAL_RECORD_SIZE..(slot + 1) * WAL_RECORD_SIZE]);
+        storage.flush_chunk(&self.chunk);
+    }
+}
+
+/// Cumulative counts of operations on a `Queue`, since it was created or loaded
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Number of items enqueued
+    pub enqueued: u64,
+    /// Number of items dequeued
+    pub dequeued: u64,
+    /// Number of chunks created, by `enqueue` or `reserve_bytes`
+    pub chunks_created: u64,
+    /// Number of chunks forgotten by `drop_old_chunks`
+    pub chunks_forgotten: u64,
+    /// Number of uncommitted enqueues replayed when recovering from the write-ahead log
+    pub replayed: u64,
+    /// Number of uncommitted enqueues discarded when recovering from the write-ahead log,
+    /// because their items didn't survive intact
+    pub rolled_back: u64,
+}
+
 /// A FIFO queue which stores heterogeneously sized items
 pub struct Queue {
     ident: Ident,
     typical_chunk_size: usize,
+    chunk_size_multiple: usize,
     chunks: Vec<Chunk>,
-    state: Value<QueueState>,
+    state: PortableValue<QueueState>,
     chunks_to_drop: Vec<Chunk>,
+    stats: QueueStats,
+    wal: Option<WriteAheadLog>,
     storage: Rc<dyn ChunkStorage>
 }
 
@@ -29,37 +162,228 @@ enum NextItemRef {
 impl Queue {
     /// Create a new queue
     pub fn new(ident: &Ident, typical_chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        Self::with_chunk_size_multiple(ident, typical_chunk_size, 1, false, storage)
+    }
+
+    /// Create a new queue like `new`, but with the sizes of all chunks it creates
+    /// rounded up to a multiple of the storage's page size
+    pub fn new_page_aligned(ident: &Ident, typical_chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        let page_size = storage.page_size();
+        Self::with_chunk_size_multiple(ident, typical_chunk_size, page_size, false, storage)
+    }
+
+    /// Create a new queue like `new`, which journals enqueues in a write-ahead log,
+    /// so a crash doesn't leave it with half-written items.
+    ///
+    /// `commit` makes all items enqueued and dequeued so far durable, by flushing their chunks
+    /// and recording the queue state. In between, each enqueue durably records the item before it,
+    /// together with a checksum of that item, because an item is only fully written once the next
+    /// enqueue starts. When the queue is loaded again, it recovers the state of the latest commit,
+    /// and replays the recorded items after it for as long as their contents survived the crash
+    /// intact, which depends on the storage writing out unflushed chunks (like `MmapStorage`
+    /// after a crash of the process) or not (like `DirectIoStorage`). The items after the first
+    /// one that didn't survive and the latest item, which isn't recorded yet, are lost.
+    pub fn with_wal(ident: &Ident, typical_chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        Self::with_chunk_size_multiple(ident, typical_chunk_size, 1, true, storage)
+    }
+
+    fn with_chunk_size_multiple(ident: &Ident, typical_chunk_size: usize, chunk_size_multiple: usize, wal: bool, storage: Rc<dyn ChunkStorage>) -> Self {
         let mut queue = Queue {
-            state: Value::load_or_default(ident.sub("q_state"), QueueState {
-                first_chunk_at: 0,
-                last_chunk_at: 0,
-                read_at: 0,
-                write_at: 0,
-                len: 0,
-            }, Rc::clone(&storage)),
+            state: PortableValue::load_or_default(ident.sub("q_state"), QueueState::default(), Rc::clone(&storage)),
             ident: ident.clone(),
             typical_chunk_size,
+            chunk_size_multiple,
             chunks: Vec::new(),
             chunks_to_drop: Vec::new(),
+            stats: QueueStats::default(),
+            wal: None,
             storage: storage
         };
 
-        // if the persisted write_at is > 0, persisted chunks need to be loaded
-        if queue.state.write_at > 0 {
-            let mut chunk_offset = queue.state.first_chunk_at;
-            while chunk_offset <= queue.state.last_chunk_at {
+        let unreplayed = if wal { queue.recover(ident) } else { Vec::new() };
+
+        // if the persisted write_at is > 0, persisted chunks need to be loaded.
+        // Chunks before first_chunk_at may have been forgotten by drop_old_chunks,
+        // but first_chunk_at never moves past last_chunk_at: dequeue only moves it on
+        // at a jump marker, which enqueue only writes when moving last_chunk_at on,
+        // so even a fully drained queue still has its last chunk.
+        let state = queue.state.get();
+        debug_assert!(state.first_chunk_at <= state.last_chunk_at);
+        let mut chunk_offset = state.first_chunk_at;
+        if state.write_at > 0 {
+            while chunk_offset <= state.last_chunk_at {
                 let chunk = queue.storage.load_chunk(ident.sub(chunk_offset));
                 chunk_offset += chunk.len();
                 queue.chunks.push(chunk);
             }
         }
 
+        // also load chunks beyond that were created by `reserve_bytes`, which only outlive
+        // the queue on persistent storages (live transient chunks belong to other queues of the same ident)
+        while queue.storage.chunk_kind() == ChunkKind::Persistent && queue.storage.chunk_exists(&ident.sub(chunk_offset)) {
+            let chunk = queue.storage.load_chunk(ident.sub(chunk_offset));
+            chunk_offset += chunk.len();
+            queue.chunks.push(chunk);
+        }
+
+        if wal {
+            queue.replay(unreplayed);
+        }
+
         queue
     }
 
+    /// Load the write-ahead log and reset the state to that of its latest commit,
+    /// or make the current state the first commit of a new log.
+    /// Returns the items recorded after that commit, in order, to be replayed once the chunks are loaded.
+    fn recover(&mut self, ident: &Ident) -> Vec<WalRecord> {
+        let (chunk, created_new) = self.storage.load_or_create_chunk(ident.sub("q_wal"), WAL_RECORDS * WAL_RECORD_SIZE);
+        let mut wal = WriteAheadLog { chunk, seq: 0, next_commit: 0, next_intent: WAL_COMMIT_SLOTS, unlogged: None };
+
+        let last_commit = if created_new {
+            None
+        } else {
+            wal.seq = wal.records().map(|(_, record)| record.seq).max().unwrap_or(0);
+            wal.records()
+                .filter(|(slot, record)| *slot < WAL_COMMIT_SLOTS && record.kind == WAL_COMMIT)
+                .max_by_key(|(_, record)| record.seq)
+        };
+
+        let unreplayed = match last_commit {
+            Some((slot, commit)) => {
+                let mut items: Vec<WalRecord> = wal.records()
+                    .map(|(_, record)| record)
+                    .filter(|record| record.kind == WAL_INTENT && record.seq > commit.seq)
+                    .collect();
+                items.sort_by_key(|record| record.seq);
+                self.state.set(commit.state);
+                wal.next_commit = (slot + 1) % WAL_COMMIT_SLOTS;
+                items
+            }
+            None => {
+                wal.append(0, WAL_COMMIT, self.state.get(), 0, &*self.storage);
+                wal.next_commit = 1;
+                Vec::new()
+            }
+        };
+
+        self.wal = Some(wal);
+        unreplayed
+    }
+
+    /// Apply the recorded items after the latest commit, up to the first one that is missing
+    /// or whose contents don't match its checksum anymore, and commit the result,
+    /// so the discarded ones are never considered again
+    fn replay(&mut self, items: Vec<WalRecord>) {
+        if items.is_empty() {
+            return;
+        }
+        let chunks_at = self.state.get().first_chunk_at;
+        let mut state = self.state.get();
+        for (seq, item) in (items[0].seq..).zip(items.iter()) {
+            if item.seq != seq || self.item_checksum(chunks_at, &state, &item.state) != Some(item.item_checksum) {
+                break;
+            }
+            state = item.state;
+            self.stats.replayed += 1;
+        }
+        self.stats.rolled_back = items.len() as u64 - self.stats.replayed;
+
+        // replayed dequeues may have moved on past loaded chunks
+        let mut chunk_at = chunks_at;
+        while chunk_at < state.first_chunk_at {
+            let chunk = self.chunks.remove(0);
+            chunk_at += chunk.len();
+            self.chunks_to_drop.push(chunk);
+        }
+        self.state.set(state);
+        self.commit();
+    }
+
+    /// Checksum of the header and contents of the item enqueued from state `from` to state `to`,
+    /// unless its chunk isn't loaded, where the loaded chunks start at offset `chunks_at`
+    fn item_checksum(&self, chunks_at: usize, from: &QueueState, to: &QueueState) -> Option<u64> {
+        // an item that didn't fit into the chunk at `from` starts at the beginning of the next one
+        let start = ::core::cmp::max(from.write_at, to.last_chunk_at);
+        if to.write_at <= start {
+            return None;
+        }
+        let chunk = &self.chunks[self.chunk_index_at(chunks_at, to.last_chunk_at)?];
+        chunk.get(start - to.last_chunk_at..to.write_at - to.last_chunk_at).map(crate::checksum)
+    }
+
+    /// Make all items enqueued and dequeued so far durable, by flushing the chunks
+    /// of the queue and recording its state in the write-ahead log.
+    /// Does nothing for queues without a write-ahead log (see `with_wal`).
+    pub fn commit(&mut self) {
+        if let Some(ref mut wal) = self.wal {
+            for chunk in &self.chunks {
+                self.storage.flush_chunk(chunk);
+            }
+            wal.append(wal.next_commit, WAL_COMMIT, self.state.get(), 0, &*self.storage);
+            wal.next_commit = (wal.next_commit + 1) % WAL_COMMIT_SLOTS;
+            wal.next_intent = WAL_COMMIT_SLOTS;
+            wal.unlogged = None;
+        }
+    }
+
+    /// Record the latest enqueued item in the write-ahead log (if any), now that it is fully written,
+    /// committing instead if the log is full
+    fn log_item(&mut self) {
+        let from = match self.wal.as_ref().and_then(|wal| wal.unlogged) {
+            Some(from) => from,
+            None => return,
+        };
+        if self.wal.as_ref().is_some_and(|wal| wal.next_intent == WAL_RECORDS) {
+            return self.commit();
+        }
+        let state = self.state.get();
+        let item_checksum = self.item_checksum(state.first_chunk_at, &from, &state)
+            .expect("The latest enqueued item should be in a loaded chunk");
+        if let Some(ref mut wal) = self.wal {
+            wal.append(wal.next_intent, WAL_INTENT, state, item_checksum, &*self.storage);
+            wal.next_intent += 1;
+            wal.unlogged = None;
+        }
+    }
+
+    /// Use the queue as a byte stream to write to, in messages of (at most) `message_size` bytes
+    #[cfg(feature = "std")]
+    pub fn writer(&mut self, message_size: usize) -> crate::queue_stream::QueueWriter<'_> {
+        crate::queue_stream::QueueWriter::new(self, message_size)
+    }
+
+    /// Use the queue as a byte stream to read what was written with `writer` from
+    #[cfg(feature = "std")]
+    pub fn reader(&mut self) -> crate::queue_stream::QueueReader<'_> {
+        crate::queue_stream::QueueReader::new(self)
+    }
+
+    /// Create a new queue like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: &Ident, typical_chunk_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, typical_chunk_size, storage))
+    }
+
+    /// Size of a new chunk which has room for at least `min_size` bytes
+    fn new_chunk_size(&self, min_size: usize) -> usize {
+        ::core::cmp::max(self.typical_chunk_size, min_size).next_multiple_of(self.chunk_size_multiple)
+    }
+
+    /// Index in `chunks` of the chunk which starts at the offset `chunk_at`, if it exists
+    fn chunk_index_at(&self, first_chunk_at: usize, chunk_at: usize) -> Option<usize> {
+        let mut offset = first_chunk_at;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if offset == chunk_at {
+                return Some(index);
+            }
+            offset += chunk.len();
+        }
+        None
+    }
+
     /// Number of items in the queue
     pub fn len(&self) -> usize {
-        self.state.len
+        self.state.get().len
     }
 
     /// Is the queue empty?
@@ -67,110 +391,298 @@ impl Queue {
         self.len() == 0
     }
 
+    /// The size of the largest item that can be enqueued without jumping to a new chunk,
+    /// for producers that want to size or defer items to avoid wasting the rest of the current one.
+    /// Without a current chunk, this is how much fits into a new chunk of the typical size.
+    pub fn bytes_until_chunk_end(&self) -> usize {
+        let state = self.state.get();
+        // the item's header and one more header (even if just a jump marker) need to fit
+        let headers_size = 2 * ::core::mem::size_of::<NextItemRef>();
+        match self.chunk_index_at(state.first_chunk_at, state.last_chunk_at) {
+            Some(chunk_index) => {
+                let offset = state.write_at - state.last_chunk_at;
+                self.chunks[chunk_index].len().saturating_sub(offset + headers_size)
+            }
+            None => self.new_chunk_size(0).saturating_sub(headers_size),
+        }
+    }
+
+    /// Counts of operations on the queue so far
+    pub fn stats(&self) -> QueueStats {
+        self.stats
+    }
+
     /// Enqueue an item of a given size. Returns a pointer that the item can be written to.
     ///
     /// This is handled like this so items of heterogeneous types can be enqueued.
     // TODO: return done_guard to mark as concurrently readable
-    #[allow(clippy::cast_ptr_alignment)]
     pub unsafe fn enqueue(&mut self, size: usize) -> *mut u8 {
+        self.log_item();
+        let before = self.state.get();
+        let payload_ptr = self.enqueue_unlogged(size);
+        if let Some(ref mut wal) = self.wal {
+            wal.unlogged = Some(before);
+        }
+        payload_ptr
+    }
+
+    #[allow(clippy::cast_ptr_alignment)]
+    unsafe fn enqueue_unlogged(&mut self, size: usize) -> *mut u8 {
         enum EnqueueResult {
             Success(*mut u8),
+            RetryInNextChunk,
             RetryInNewChunkOfSize(usize),
         };
 
+        let mut state = self.state.get();
+        let write_chunk_index = self.chunk_index_at(state.first_chunk_at, state.last_chunk_at);
+        let n_chunks = self.chunks.len();
+
         let result = {
-            let ref_size = ::std::mem::size_of::<NextItemRef>();
+            let ref_size = ::core::mem::size_of::<NextItemRef>();
 
             // one more next item ref needs to fit afterwards,
             // even if it will just be a jump marker!
             let min_space = ref_size + size + ref_size;
+            let new_chunk_size = self.new_chunk_size(min_space);
 
-            if let Some(chunk) = self.chunks.last_mut() {
-                let offset = self.state.write_at - self.state.last_chunk_at;
+            if let Some(chunk_index) = write_chunk_index {
+                let chunk = &mut self.chunks[chunk_index];
+                let offset = state.write_at - state.last_chunk_at;
                 let entry_ptr = chunk.as_mut_ptr().offset(offset as isize);
                 if offset + min_space <= chunk.len() {
                     // store the item size as a header
                     *(entry_ptr as *mut NextItemRef) = NextItemRef::SameChunk(ref_size + size);
                     let payload_ptr = entry_ptr.offset(ref_size as isize);
-                    self.state.write_at += ref_size + size;
-                    self.state.len += 1;
+                    state.write_at = state.write_at.checked_add(ref_size + size).expect(OFFSET_OVERFLOW);
+                    state.len += 1;
                     // return the pointer to where the item can be written
                     EnqueueResult::Success(payload_ptr)
                 } else {
                     // store a jump marker instead of item size
                     *(entry_ptr as *mut NextItemRef) = NextItemRef::NextChunk;
-                    let new_chunk_size = ::std::cmp::max(self.typical_chunk_size, min_space);
                     // retry at the beginning of a new chunk
-                    self.state.last_chunk_at += chunk.len();
-                    self.state.write_at = self.state.last_chunk_at;
-                    EnqueueResult::RetryInNewChunkOfSize(new_chunk_size)
+                    state.last_chunk_at = state.last_chunk_at.checked_add(chunk.len()).expect(OFFSET_OVERFLOW);
+                    state.write_at = state.last_chunk_at;
+                    if chunk_index + 1 < n_chunks {
+                        // a chunk was already reserved
+                        EnqueueResult::RetryInNextChunk
+                    } else {
+                        EnqueueResult::RetryInNewChunkOfSize(new_chunk_size)
+                    }
                 }
             } else {
                 // create first chunk
-                let new_chunk_size = ::std::cmp::max(self.typical_chunk_size, min_space);
                 EnqueueResult::RetryInNewChunkOfSize(new_chunk_size)
             }
 
         };
 
+        self.state.set(state);
+
         match result {
-            EnqueueResult::Success(payload_ptr) => payload_ptr,
+            EnqueueResult::Success(payload_ptr) => {
+                self.stats.enqueued += 1;
+                payload_ptr
+            }
+            EnqueueResult::RetryInNextChunk => self.enqueue_unlogged(size),
             EnqueueResult::RetryInNewChunkOfSize(new_chunk_size) => {
                 self.chunks.push(self.storage.create_chunk(
-                    self.ident.sub(self.state.last_chunk_at),
+                    self.ident.sub(state.last_chunk_at),
                     new_chunk_size,
                 ));
-                self.enqueue(size)
+                self.stats.chunks_created += 1;
+                self.enqueue_unlogged(size)
             }
         }
     }
 
+    /// Create chunks ahead of time so that at least `additional` bytes are available
+    /// after the current write position, so enqueueing them doesn't allocate.
+    ///
+    /// Note that each enqueued item also takes up space for a small header.
+    pub fn reserve_bytes(&mut self, additional: usize) {
+        let state = self.state.get();
+        let ref_size = ::core::mem::size_of::<NextItemRef>();
+        let chunks_end = state.first_chunk_at + self.chunks.iter().map(|chunk| chunk.len()).sum::<usize>();
+        let mut available = if self.chunks.is_empty() {
+            0
+        } else {
+            // keep space for the jump marker at the end of each chunk
+            chunks_end - state.write_at - ref_size
+        };
+        let mut chunk_at = if self.chunks.is_empty() { state.last_chunk_at } else { chunks_end };
+
+        while available < additional {
+            let new_chunk_size = self.new_chunk_size(additional - available + ref_size);
+            let chunk = self.storage.create_chunk(self.ident.sub(chunk_at), new_chunk_size);
+            chunk_at += chunk.len();
+            available += chunk.len() - ref_size;
+            self.chunks.push(chunk);
+            self.stats.chunks_created += 1;
+        }
+    }
+
     /// Dequeue an item. Returns a pointer to the item in the queue, unless the queue is empty.
     // TODO: return done_guard to mark as droppable
     pub unsafe fn dequeue(&mut self) -> Option<*const u8> {
+        self.dequeue_with_len().map(|(payload_ptr, _)| payload_ptr)
+    }
+
+    /// Like `dequeue`, but also returns the size the item was enqueued with
+    ///
+    /// # Safety
+    ///
+    /// The pointer is only valid until the chunk it points into is dropped with `drop_old_chunks`.
+    pub unsafe fn dequeue_with_len(&mut self) -> Option<(*const u8, usize)> {
         enum DequeueResult {
             Empty,
-            Success(*const u8),
+            Success(*const u8, usize),
             RetryInNextChunk,
         };
 
-        let result = if self.state.read_at == self.state.write_at {
+        let mut state = self.state.get();
+
+        let result = if state.read_at == state.write_at {
             DequeueResult::Empty
         } else {
-            let offset = self.state.read_at - self.state.first_chunk_at;
+            let offset = state.read_at - state.first_chunk_at;
             let chunk = &mut self.chunks[0];
             let entry_ptr = chunk.as_mut_ptr().offset(offset as isize);
 
             #[allow(clippy::cast_ptr_alignment)]
             match *(entry_ptr as *mut NextItemRef) {
                 NextItemRef::NextChunk => {
-                    self.state.first_chunk_at += chunk.len();
-                    self.state.read_at = self.state.first_chunk_at;
+                    state.first_chunk_at += chunk.len();
+                    state.read_at = state.first_chunk_at;
                     DequeueResult::RetryInNextChunk
                 }
                 NextItemRef::SameChunk(total_size) => {
-                    let payload_ptr = entry_ptr.offset(::std::mem::size_of::<NextItemRef>() as isize);
-                    self.state.read_at += total_size;
-                    self.state.len -= 1;
-                    DequeueResult::Success(payload_ptr)
+                    let ref_size = ::core::mem::size_of::<NextItemRef>();
+                    let payload_ptr = entry_ptr.add(ref_size);
+                    state.read_at += total_size;
+                    state.len -= 1;
+                    DequeueResult::Success(payload_ptr, total_size - ref_size)
                 }
             }
         };
 
+        self.state.set(state);
+
         match result {
             DequeueResult::Empty => None,
-            DequeueResult::Success(payload_ptr) => Some(payload_ptr),
+            DequeueResult::Success(payload_ptr, size) => {
+                self.stats.dequeued += 1;
+                Some((payload_ptr, size))
+            }
             DequeueResult::RetryInNextChunk => {
                 self.chunks_to_drop.push(self.chunks.remove(0));
-                self.dequeue()
+                self.dequeue_with_len()
             }
         }
     }
 
-    /// Delete chunks which have already been read
+    /// Iterate over pointers to all items in the queue, in the order `dequeue` would return them,
+    /// without dequeuing them.
+    ///
+    /// # Safety
+    ///
+    /// The pointers are only valid until the queue is next mutated.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub unsafe fn iter(&self) -> impl Iterator<Item = *const u8> + '_ {
+        let state = self.state.get();
+        let mut chunk_index = 0;
+        let mut chunk_at = state.first_chunk_at;
+        let mut read_at = state.read_at;
+
+        ::core::iter::from_fn(move || loop {
+            if read_at == state.write_at {
+                return None;
+            }
+
+            let chunk = &self.chunks[chunk_index];
+            let entry_ptr = chunk.as_ptr().add(read_at - chunk_at);
+
+            match *(entry_ptr as *const NextItemRef) {
+                NextItemRef::NextChunk => {
+                    chunk_at += chunk.len();
+                    read_at = chunk_at;
+                    chunk_index += 1;
+                }
+                NextItemRef::SameChunk(total_size) => {
+                    read_at += total_size;
+                    return Some(entry_ptr.add(::core::mem::size_of::<NextItemRef>()));
+                }
+            }
+        })
+    }
+
+    /// Delete chunks which have already been read.
+    /// With a write-ahead log, this commits first, so recovery never needs them again.
+    ///
+    /// The offsets of items only ever grow, so once the read position got large, this also
+    /// moves the remaining chunks (including ones created by `reserve_bytes`) to start
+    /// at offset 0 again, by copying them. This waits until the chunks before the read position
+    /// take up at least as much as the remaining ones, so the copies never overlap them.
     pub unsafe fn drop_old_chunks(&mut self) {
+        if !self.chunks_to_drop.is_empty() {
+            self.commit();
+        }
         for chunk in self.chunks_to_drop.drain(..) {
             self.storage.forget_chunk(chunk);
+            self.stats.chunks_forgotten += 1;
         }
+        self.rebase_if_read_far();
     }
-}
\ No newline at end of file
+
+    fn rebase_if_read_far(&mut self) {
+        let state = self.state.get();
+        let base = state.first_chunk_at;
+        let live_len = self.chunks.iter().map(|chunk| chunk.len()).sum::<usize>();
+        if base < REBASE_THRESHOLD || base < live_len {
+            return;
+        }
+
+        let mut copies = Vec::with_capacity(self.chunks.len());
+        let mut chunk_at = 0;
+        for chunk in &self.chunks {
+            let ident = self.ident.sub(chunk_at);
+            // left behind by a crash during an earlier rebase
+            if self.storage.chunk_kind() == ChunkKind::Persistent && self.storage.chunk_exists(&ident) {
+                self.storage.forget_chunk(self.storage.load_chunk(ident.clone()));
+            }
+            let mut copy = self.storage.create_chunk(ident, chunk.len());
+            copy.copy_from_slice(chunk);
+            self.storage.flush_chunk(&copy);
+            self.stats.chunks_created += 1;
+            chunk_at += chunk.len();
+            copies.push(copy);
+        }
+        let old_chunks = ::core::mem::replace(&mut self.chunks, copies);
+
+        // make the rebased state durable before forgetting the old chunks, so a crash in between
+        // at worst leaves behind unused chunks, rather than a state referring to forgotten ones
+        self.state.set(QueueState {
+            first_chunk_at: 0,
+            last_chunk_at: state.last_chunk_at - base,
+            read_at: state.read_at - base,
+            write_at: state.write_at - base,
+            len: state.len,
+        });
+        self.commit();
+
+        for chunk in old_chunks {
+            self.storage.forget_chunk(chunk);
+            self.stats.chunks_forgotten += 1;
+        }
+    }
+}
+
+impl crate::Flushable for Queue {
+    fn flush(&self) {
+        for chunk in &self.chunks {
+            self.storage.flush_chunk(chunk);
+        }
+        self.state.flush(&*self.storage);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::queue::Queue;
+use std::io;
+
+/// Space taken up by the header in front of each message: its length as a little-endian `u64`
+const LEN_SIZE: usize = 8;
+
+/// Messages are padded to a multiple of this, to keep the queue's entries 8-byte aligned
+const MESSAGE_ALIGN: usize = 8;
+
+/// An `io::Write` adapter that enqueues the written bytes into a `Queue`,
+/// buffering them into messages of a fixed size.
+///
+/// Buffered bytes are only enqueued once a message is full, or on `flush`,
+/// which also commits the queue (see `Queue::commit`). Dropping the writer flushes it.
+pub struct QueueWriter<'a> {
+    queue: &'a mut Queue,
+    buffer: Vec<u8>,
+    message_size: usize,
+}
+
+impl<'a> QueueWriter<'a> {
+    /// Start writing to `queue`, in messages of (at most) `message_size` bytes
+    pub fn new(queue: &'a mut Queue, message_size: usize) -> Self {
+        assert!(message_size > 0, "Message size must be positive");
+        QueueWriter {
+            queue,
+            buffer: Vec::with_capacity(message_size),
+            message_size,
+        }
+    }
+
+    fn enqueue_buffer(&mut self) {
+        let len = self.buffer.len();
+        unsafe {
+            let entry_ptr = self.queue.enqueue(LEN_SIZE + len.next_multiple_of(MESSAGE_ALIGN));
+            ::std::ptr::copy_nonoverlapping((len as u64).to_le_bytes().as_ptr(), entry_ptr, LEN_SIZE);
+            ::std::ptr::copy_nonoverlapping(self.buffer.as_ptr(), entry_ptr.add(LEN_SIZE), len);
+        }
+        self.buffer.clear();
+    }
+}
+
+impl<'a> io::Write for QueueWriter<'a> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let n_bytes = ::std::cmp::min(bytes.len(), self.message_size - self.buffer.len());
+        self.buffer.extend_from_slice(&bytes[..n_bytes]);
+        if self.buffer.len() == self.message_size {
+            self.enqueue_buffer();
+        }
+        Ok(n_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.enqueue_buffer();
+        }
+        self.queue.commit();
+        Ok(())
+    }
+}
+
+impl<'a> Drop for QueueWriter<'a> {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+/// An `io::Read` adapter that dequeues messages written by a `QueueWriter` from a `Queue`,
+/// yielding their bytes in order, regardless of where messages start and end.
+///
+/// Reading returns 0 bytes once the queue is drained, like a file at its end,
+/// but more bytes can be read after more were written.
+/// Chunks are dropped (see `Queue::drop_old_chunks`) once all of their messages were read.
+pub struct QueueReader<'a> {
+    queue: &'a mut Queue,
+    /// The message currently being read, which stays valid until the next `drop_old_chunks`
+    message_ptr: *const u8,
+    message_len: usize,
+    read_at: usize,
+}
+
+impl<'a> QueueReader<'a> {
+    /// Start reading from `queue`
+    pub fn new(queue: &'a mut Queue) -> Self {
+        QueueReader {
+            queue,
+            message_ptr: ::std::ptr::null(),
+            message_len: 0,
+            read_at: 0,
+        }
+    }
+
+    /// Dequeue the next non-empty message, returning false if the queue is drained
+    fn next_message(&mut self) -> bool {
+        unsafe {
+            // the current message was read completely, so its chunk may be dropped
+            self.queue.drop_old_chunks();
+            while let Some(entry_ptr) = self.queue.dequeue() {
+                let mut len_bytes = [0u8; LEN_SIZE];
+                ::std::ptr::copy_nonoverlapping(entry_ptr, len_bytes.as_mut_ptr(), LEN_SIZE);
+                self.message_ptr = entry_ptr.add(LEN_SIZE);
+                self.message_len = u64::from_le_bytes(len_bytes) as usize;
+                self.read_at = 0;
+                if self.message_len > 0 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<'a> io::Read for QueueReader<'a> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if buffer.is_empty() || (self.read_at == self.message_len && !self.next_message()) {
+            return Ok(0);
+        }
+        let n_bytes = ::std::cmp::min(buffer.len(), self.message_len - self.read_at);
+        unsafe {
+            ::std::ptr::copy_nonoverlapping(self.message_ptr.add(self.read_at), buffer.as_mut_ptr(), n_bytes);
+        }
+        self.read_at += n_bytes;
+        Ok(n_bytes)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A `ChunkStorage` decorator that forwards to an inner storage, while limiting
+/// the total size of all chunks it holds to a fixed number of bytes.
+///
+/// Each chunk counts towards the limit once, by identifier, with the length it was created
+/// or first loaded with, for as long as it exists in the inner storage: persistent chunks
+/// until they are forgotten, no matter how often they are dropped and loaded again, and
+/// transient chunks until they are forgotten or their last handle is dropped.
+/// Only creating chunks can fail, loading existing ones always succeeds.
+pub struct Quota<S: ChunkStorage> {
+    state: Rc<State<S>>,
+    limit: usize,
+}
+
+/// The inner storage, together with what its chunks count towards the quota
+struct State<S: ChunkStorage> {
+    inner: S,
+    used: Cell<usize>,
+    /// Counted lengths of chunks, by identifier
+    lens: RefCell<HashMap<String, usize>>,
+}
+
+impl<S: ChunkStorage> State<S> {
+    /// Count `ident` with `len` bytes, replacing what it counted with before
+    fn count(&self, ident: &Ident, len: usize) {
+        let before = self.lens.borrow_mut().insert(ident.0.clone(), len).unwrap_or(0);
+        self.used.set(self.used.get() - before + len);
+    }
+
+    /// Count `ident` with `len` bytes, unless it is already counted
+    fn count_once(&self, ident: &Ident, len: usize) {
+        if !self.lens.borrow().contains_key(&ident.0) {
+            self.count(ident, len);
+        }
+    }
+
+    /// Stop counting `ident` if it doesn't exist in the inner storage anymore
+    fn recount(&self, ident: &Ident) {
+        if !self.inner.chunk_exists(ident) {
+            if let Some(len) = self.lens.borrow_mut().remove(&ident.0) {
+                self.used.set(self.used.get() - len);
+            }
+        }
+    }
+}
+
+/// Owns the inner chunk, so dropping the last handle of a transient chunk can be accounted for
+struct QuotaHandle<S: ChunkStorage + 'static> {
+    ident: Ident,
+    inner: Option<Chunk>,
+    state: Rc<State<S>>,
+}
+
+impl<S: ChunkStorage + 'static> Drop for QuotaHandle<S> {
+    fn drop(&mut self) {
+        if let Some(chunk) = self.inner.take() {
+            drop(chunk);
+            self.state.recount(&self.ident);
+        }
+    }
+}
+
+impl<S: ChunkStorage + 'static> Quota<S> {
+    /// Wrap `inner`, allowing at most `limit` bytes of chunks
+    pub fn new(inner: S, limit: usize) -> Quota<S> {
+        Quota {
+            state: Rc::new(State {
+                inner,
+                used: Cell::new(0),
+                lens: RefCell::new(HashMap::new()),
+            }),
+            limit,
+        }
+    }
+
+    /// The maximum total size of all chunks in bytes
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The total size of all chunks currently counted in bytes
+    pub fn used(&self) -> usize {
+        self.state.used.get()
+    }
+
+    /// Fail if creating `ident` with `size` bytes would exceed the limit
+    fn check(&self, ident: &Ident, size: usize) -> ::std::io::Result<()> {
+        let replaced = self.state.lens.borrow().get(&ident.0).cloned().unwrap_or(0);
+        if self.used() - replaced + size > self.limit {
+            Err(::std::io::Error::new(
+                ::std::io::ErrorKind::QuotaExceeded,
+                format!(
+                    "Creating chunk {} of {} bytes would exceed the quota ({} of {} bytes used)",
+                    ident.0, size, self.used(), self.limit
+                ),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn wrap(&self, ident: Ident, chunk: Chunk) -> Chunk {
+        let (ptr, len, capacity, kind) = (chunk.ptr, chunk.len, chunk.capacity, chunk.kind);
+        let handle = Box::new(QuotaHandle {
+            ident,
+            inner: Some(chunk),
+            state: Rc::clone(&self.state),
+        });
+        // the inner chunk, and with it its memory, lives as long as the handle
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, len, capacity, kind, handle) }
+    }
+
+    /// Take the inner chunk out of one of our chunks, together with its identifier
+    fn unwrap(chunk: Chunk) -> ::std::io::Result<(Ident, Chunk)> {
+        let mut handle = chunk._handle_to_drop.downcast::<QuotaHandle<S>>()
+            .map_err(|_| crate::foreign_chunk_error("Quota storage"))?;
+        let inner = handle.inner.take().expect("Quota handle without inner chunk");
+        Ok((handle.ident.clone(), inner))
+    }
+}
+
+impl<S: ChunkStorage + 'static> ChunkStorage for Quota<S> {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        self.try_create_chunk(ident, size).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        self.check(&ident, size)?;
+        let chunk = self.state.inner.try_create_chunk(ident.clone(), size)?;
+        self.state.count(&ident, chunk.len());
+        Ok(self.wrap(ident, chunk))
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        if !self.state.inner.chunk_exists(&ident) {
+            self.check(&ident, size).unwrap_or_else(|err| panic!("{}", err));
+        }
+        let (chunk, created_new) = self.state.inner.load_or_create_chunk(ident.clone(), size);
+        if created_new {
+            self.state.count(&ident, chunk.len());
+        } else {
+            self.state.count_once(&ident, chunk.len());
+        }
+        (self.wrap(ident, chunk), created_new)
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let chunk = self.state.inner.load_chunk(ident.clone());
+        self.state.count_once(&ident, chunk.len());
+        self.wrap(ident, chunk)
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        match Self::unwrap(chunk) {
+            Ok((ident, chunk)) => {
+                self.state.inner.forget_chunk(chunk);
+                self.state.recount(&ident);
+            }
+            Err(_) => crate::warn_foreign_chunk("Quota storage"),
+        }
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let (ident, chunk) = Self::unwrap(chunk)?;
+        let result = self.state.inner.try_forget_chunk(chunk);
+        self.state.recount(&ident);
+        result
+    }
+
+    fn forget_chunks(&self, chunks: Vec<Chunk>) {
+        let (idents, chunks): (Vec<Ident>, Vec<Chunk>) = chunks.into_iter().filter_map(|chunk| {
+            let unwrapped = Self::unwrap(chunk).ok();
+            if unwrapped.is_none() {
+                crate::warn_foreign_chunk("Quota storage");
+            }
+            unwrapped
+        }).unzip();
+        self.state.inner.forget_chunks(chunks);
+        for ident in &idents {
+            self.state.recount(ident);
+        }
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.state.inner.chunk_exists(ident)
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.state.inner.chunk_len(ident)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.state.inner.list_chunks(group)
+    }
+
+    fn group_fingerprint(&self, group: &Ident) -> u64 {
+        self.state.inner.group_fingerprint(group)
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        self.state.inner.chunk_checksum(ident)
+    }
+
+    fn flush_chunk(&self, chunk: &Chunk) {
+        match chunk._handle_to_drop.downcast_ref::<QuotaHandle<S>>().and_then(|handle| handle.inner.as_ref()) {
+            Some(inner) => self.state.inner.flush_chunk(inner),
+            None => crate::warn_foreign_flush("Quota storage"),
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        self.state.inner.page_size()
+    }
+
+    fn chunk_kind(&self) -> ChunkKind {
+        self.state.inner.chunk_kind()
+    }
+}
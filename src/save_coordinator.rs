@@ -0,0 +1,112 @@
+use crate::{ChunkStorage, Ident, Portable, PortableValue};
+use alloc::rc::Rc;
+
+/// A collection whose chunks can be written through to their persisted representation,
+/// so it can take part in a save of a `SaveCoordinator`
+pub trait Flushable {
+    /// Flush all loaded chunks of the collection, including the ones holding its length or state
+    fn flush(&self);
+}
+
+/// The state recorded in the manifest of a `SaveCoordinator`
+#[derive(Copy, Clone)]
+struct SaveState {
+    /// Number of the last save that was started
+    generation: u64,
+    /// Whether the last save that was started was also completed
+    complete: bool,
+}
+
+/// Persisted as two consecutive `u64`s, in field order
+impl Portable for SaveState {
+    const SIZE: usize = 2 * <u64 as Portable>::SIZE;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        let (generation_bytes, complete_bytes) = bytes.split_at_mut(<u64 as Portable>::SIZE);
+        self.generation.encode(generation_bytes);
+        (self.complete as u64).encode(complete_bytes);
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let (generation_bytes, complete_bytes) = bytes.split_at(<u64 as Portable>::SIZE);
+        SaveState {
+            generation: u64::decode(generation_bytes),
+            complete: u64::decode(complete_bytes) != 0,
+        }
+    }
+}
+
+/// Saves many collections sharing one storage together, so they can be loaded again
+/// in a consistent state, such as all the collections making up a game world.
+///
+/// A save marks the manifest chunk as incomplete, flushes all given collections,
+/// and only then marks the manifest as complete (flushing it again). With storages
+/// like `MmapStorage`, where flushing a chunk waits for it to be written, this acts as a barrier:
+/// if a save is interrupted (by a crash or panic), the manifest stays marked incomplete,
+/// so a torn save can be detected when loading it with `last_save_is_complete`.
+///
+/// Changes to collections on such storages are persisted right away, not just when saving,
+/// so the manifest is also marked incomplete as soon as it is loaded, and has to be marked
+/// incomplete again with `mark_dirty` before changing any collection after a save.
+/// Loading the collections after a crash then detects that they were changed after the last save.
+pub struct SaveCoordinator {
+    manifest: PortableValue<SaveState>,
+    /// Whether the collections were in the state of the last save when the manifest
+    /// was loaded, or since the last save if there was one since
+    last_save_is_complete: bool,
+    storage: Rc<dyn ChunkStorage>,
+}
+
+impl SaveCoordinator {
+    /// Load the manifest in the chunk with the given identifier, or create it
+    /// (as if a save had been completed) if there wasn't any save yet, then mark it incomplete
+    pub fn new(ident: Ident, storage: Rc<dyn ChunkStorage>) -> SaveCoordinator {
+        let manifest = PortableValue::load_or_default(ident, SaveState { generation: 0, complete: true }, Rc::clone(&storage));
+        let state = manifest.get();
+        let mut coordinator = SaveCoordinator {
+            manifest,
+            last_save_is_complete: state.complete,
+            storage,
+        };
+        coordinator.set_state(SaveState { complete: false, ..state });
+        coordinator
+    }
+
+    /// Number of the last save that was started, 0 if there wasn't any
+    pub fn generation(&self) -> u64 {
+        self.manifest.get().generation
+    }
+
+    /// Whether the collections were in the state of the last completed save when the manifest was loaded,
+    /// which is false for torn saves and collections changed after the last save.
+    /// After a save, this is true until `mark_dirty` is called.
+    pub fn last_save_is_complete(&self) -> bool {
+        self.last_save_is_complete
+    }
+
+    /// Mark the manifest as incomplete until the next save is completed,
+    /// which has to be done before changing any of the collections after a save
+    pub fn mark_dirty(&mut self) {
+        let state = self.manifest.get();
+        if state.complete {
+            self.set_state(SaveState { complete: false, ..state });
+        }
+        self.last_save_is_complete = false;
+    }
+
+    /// Flush all of `flushables` together, recording the save as complete only afterwards
+    pub fn save(&mut self, flushables: &[&dyn Flushable]) {
+        let generation = self.generation() + 1;
+        self.set_state(SaveState { generation, complete: false });
+        for flushable in flushables {
+            flushable.flush();
+        }
+        self.set_state(SaveState { generation, complete: true });
+        self.last_save_is_complete = true;
+    }
+
+    fn set_state(&mut self, state: SaveState) {
+        self.manifest.set(state);
+        self.manifest.flush(&*self.storage);
+    }
+}
@@ -0,0 +1,590 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use crate::arena::{Arena, ArenaIndex};
+#[cfg(feature = "std")]
+use crate::arena::TryReserveError;
+use crate::typed_arena::TypedArena;
+use crate::generational_arena::{GenerationalArena, GenerationalIndex};
+use crate::vector::{Vector, Checkpoint};
+use crate::transaction::Transaction;
+use crate::queue::Queue;
+#[cfg(feature = "std")]
+use crate::queue_stream::{QueueReader, QueueWriter};
+use crate::tagged_queue::TaggedQueue;
+use crate::deque::Deque;
+use crate::multi_arena::{MultiArena, MultiArenaIndex, SizedHandle, SizeTooLargeError};
+use crate::bit_vec::BitVec;
+use crate::chunky_map::ChunkyMap;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// A `ChunkStorage` which can be shared between threads and whose chunks can be sent between threads.
+///
+/// # Safety
+///
+/// Implementors guarantee that the handles of all chunks they create or load,
+/// as well as the memory they point to, can be moved to and used from another thread.
+pub unsafe trait SendableStorage: ChunkStorage + Send + Sync {}
+
+/// Adapts a thread-safe storage to the `Rc<dyn ChunkStorage>` that collections hold
+struct SharedStorage(Arc<dyn SendableStorage>);
+
+impl ChunkStorage for SharedStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        self.0.create_chunk(ident, size)
+    }
+
+    #[cfg(feature = "std")]
+    fn try_create_chunk(&self, ident: Ident, size: usize) -> ::std::io::Result<Chunk> {
+        self.0.try_create_chunk(ident, size)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        self.0.load_or_create_chunk(ident, size)
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        self.0.load_chunk(ident)
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        self.0.forget_chunk(chunk)
+    }
+
+    #[cfg(feature = "std")]
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        self.0.try_forget_chunk(chunk)
+    }
+
+    fn forget_chunks(&self, chunks: Vec<Chunk>) {
+        self.0.forget_chunks(chunks)
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.0.chunk_exists(ident)
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.0.chunk_len(ident)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.0.list_chunks(group)
+    }
+
+    fn group_fingerprint(&self, group: &Ident) -> u64 {
+        self.0.group_fingerprint(group)
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        self.0.chunk_checksum(ident)
+    }
+
+    fn flush_chunk(&self, chunk: &Chunk) {
+        self.0.flush_chunk(chunk)
+    }
+
+    fn page_size(&self) -> usize {
+        self.0.page_size()
+    }
+
+    fn chunk_kind(&self) -> ChunkKind {
+        self.0.chunk_kind()
+    }
+}
+
+/// A collection built on a `SendableStorage` (using the collection's `new_shared` constructor),
+/// which can be sent to another thread. It dereferences to the collection for reading,
+/// and forwards the collection's mutating methods (except ones like `Vector::split_off`,
+/// which would hand out the collection's storage to a new collection).
+///
+/// Collections refer to their storage through an `Rc`, which is why they are not `Send` themselves.
+/// A shared collection gets its own `Rc` around the shared storage, which is only ever cloned
+/// into parts of the same collection, so all clones are always sent together.
+/// There is no mutable access to the collection itself, since it could then be swapped out
+/// for one on a storage that isn't thread-safe.
+pub struct Shared<C> {
+    collection: C,
+}
+
+impl<C> Shared<C> {
+    /// Wrap the collection built by `build` on `storage`, which must not leak its
+    /// `Rc<dyn ChunkStorage>` anywhere but into the collection
+    pub(crate) fn build<F: FnOnce(Rc<dyn ChunkStorage>) -> C>(storage: Arc<dyn SendableStorage>, build: F) -> Shared<C> {
+        Shared {
+            collection: build(Rc::new(SharedStorage(storage))),
+        }
+    }
+
+    /// Unwrap the collection, which then can't be sent to another thread anymore
+    pub fn into_inner(self) -> C {
+        self.collection
+    }
+}
+
+impl<C> ::core::ops::Deref for Shared<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.collection
+    }
+}
+
+impl Shared<Arena> {
+    /// See `Arena::iter_mut_ptrs`
+    pub fn iter_mut_ptrs(&mut self) -> impl Iterator<Item = *mut u8> + '_ {
+        self.collection.iter_mut_ptrs()
+    }
+
+    /// See `Arena::push`
+    pub fn push(&mut self) -> (*mut u8, ArenaIndex) {
+        self.collection.push()
+    }
+
+    /// See `Arena::set_len`
+    ///
+    /// # Safety
+    ///
+    /// As for `Arena::set_len`
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        self.collection.set_len(new_len)
+    }
+
+    /// See `Arena::reserve`
+    pub fn reserve(&mut self, additional: usize) {
+        self.collection.reserve(additional)
+    }
+
+    /// See `Arena::extend_from_ptr`
+    ///
+    /// # Safety
+    ///
+    /// As for `Arena::extend_from_ptr`
+    pub unsafe fn extend_from_ptr(&mut self, src: *const u8, count: usize) {
+        self.collection.extend_from_ptr(src, count)
+    }
+
+    /// See `Arena::try_reserve`
+    #[cfg(feature = "std")]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.collection.try_reserve(additional)
+    }
+
+    /// See `Arena::shrink_to_fit`
+    pub fn shrink_to_fit(&mut self) {
+        self.collection.shrink_to_fit()
+    }
+
+    /// See `Arena::pop_away`
+    pub fn pop_away(&mut self) {
+        self.collection.pop_away()
+    }
+
+    /// See `Arena::truncate`
+    pub fn truncate(&mut self, new_len: usize) {
+        self.collection.truncate(new_len)
+    }
+
+    /// See `Arena::clear`
+    pub fn clear(&mut self) {
+        self.collection.clear()
+    }
+
+    /// See `Arena::swap_remove`
+    ///
+    /// # Safety
+    ///
+    /// As for `Arena::swap_remove`
+    pub unsafe fn swap_remove(&mut self, index: ArenaIndex) -> Option<*const u8> {
+        self.collection.swap_remove(index)
+    }
+
+    /// See `Arena::retain_swap`
+    ///
+    /// # Safety
+    ///
+    /// As for `Arena::retain_swap`
+    pub unsafe fn retain_swap<F: FnMut(*const u8) -> bool>(&mut self, keep: F) {
+        self.collection.retain_swap(keep)
+    }
+
+    /// See `Arena::drain_filter`
+    ///
+    /// # Safety
+    ///
+    /// As for `Arena::drain_filter`
+    pub unsafe fn drain_filter<F: FnMut(*const u8) -> bool>(&mut self, remove: F) -> Vec<Vec<u8>> {
+        self.collection.drain_filter(remove)
+    }
+
+    /// See `Arena::get_mut_ptr`
+    pub fn get_mut_ptr(&mut self, index: ArenaIndex) -> Option<*mut u8> {
+        self.collection.get_mut_ptr(index)
+    }
+
+    /// See `Arena::at_mut`
+    ///
+    /// # Safety
+    ///
+    /// As for `Arena::at_mut`
+    pub unsafe fn at_mut(&mut self, index: ArenaIndex) -> *mut u8 {
+        self.collection.at_mut(index)
+    }
+}
+
+impl<T: Send> Shared<TypedArena<T>> {
+    /// See `TypedArena::push`
+    pub fn push(&mut self, item: T) -> ArenaIndex {
+        self.collection.push(item)
+    }
+
+    /// See `TypedArena::get_mut`
+    pub fn get_mut(&mut self, index: ArenaIndex) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    /// See `TypedArena::swap_remove`
+    pub fn swap_remove(&mut self, index: ArenaIndex) -> Option<T> {
+        self.collection.swap_remove(index)
+    }
+}
+
+impl<T: Send> Shared<GenerationalArena<T>> {
+    /// See `GenerationalArena::push`
+    pub fn push(&mut self, item: T) -> GenerationalIndex {
+        self.collection.push(item)
+    }
+
+    /// See `GenerationalArena::get_mut`
+    pub fn get_mut(&mut self, index: GenerationalIndex) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    /// See `GenerationalArena::swap_remove`
+    pub fn swap_remove(&mut self, index: GenerationalIndex) -> Option<T> {
+        self.collection.swap_remove(index)
+    }
+}
+
+impl<Item: Clone + Send> Shared<Vector<Item>> {
+    /// See `Vector::at_mut`
+    pub fn at_mut(&mut self, index: usize) -> Option<&mut Item> {
+        self.collection.at_mut(index)
+    }
+
+    /// See `Vector::try_as_mut_slice`
+    pub fn try_as_mut_slice(&mut self) -> Option<&mut [Item]> {
+        self.collection.try_as_mut_slice()
+    }
+
+    /// See `Vector::push`
+    pub fn push(&mut self, item: Item) {
+        self.collection.push(item)
+    }
+
+    /// See `Vector::try_reserve`
+    #[cfg(feature = "std")]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.collection.try_reserve(additional)
+    }
+
+    /// See `Vector::shrink_to_fit`
+    pub fn shrink_to_fit(&mut self) {
+        self.collection.shrink_to_fit()
+    }
+
+    /// See `Vector::pop`
+    pub fn pop(&mut self) -> Option<Item> {
+        self.collection.pop()
+    }
+
+    /// See `Vector::truncate`
+    pub fn truncate(&mut self, len: usize) {
+        self.collection.truncate(len)
+    }
+
+    /// See `Vector::clear`
+    pub fn clear(&mut self) {
+        self.collection.clear()
+    }
+
+    /// See `Vector::swap_remove`
+    pub fn swap_remove(&mut self, index: usize) -> Item {
+        self.collection.swap_remove(index)
+    }
+
+    /// See `Vector::swap`
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.collection.swap(a, b)
+    }
+
+    /// See `Vector::rotate_left`
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.collection.rotate_left(mid)
+    }
+
+    /// See `Vector::rotate_right`
+    pub fn rotate_right(&mut self, k: usize) {
+        self.collection.rotate_right(k)
+    }
+
+    /// See `Vector::transaction`
+    pub fn transaction(&mut self) -> Transaction<'_, Item> {
+        self.collection.transaction()
+    }
+
+    /// See `Vector::rollback`
+    pub fn rollback(&mut self, checkpoint: Checkpoint<Item>) {
+        self.collection.rollback(checkpoint)
+    }
+
+    /// See `Vector::append`
+    pub fn append(&mut self, other: &mut Vector<Item>) {
+        self.collection.append(other)
+    }
+
+    /// See `Vector::fill`
+    pub fn fill(&mut self, value: Item) {
+        self.collection.fill(value)
+    }
+
+    /// See `Vector::fill_with`
+    pub fn fill_with<F: FnMut() -> Item>(&mut self, f: F) {
+        self.collection.fill_with(f)
+    }
+
+    /// See `Vector::map_in_place`
+    pub fn map_in_place<F: FnMut(Item) -> Item>(&mut self, f: F) {
+        self.collection.map_in_place(f)
+    }
+}
+
+impl Shared<Queue> {
+    /// See `Queue::commit`
+    pub fn commit(&mut self) {
+        self.collection.commit()
+    }
+
+    /// See `Queue::writer`
+    #[cfg(feature = "std")]
+    pub fn writer(&mut self, message_size: usize) -> QueueWriter<'_> {
+        self.collection.writer(message_size)
+    }
+
+    /// See `Queue::reader`
+    #[cfg(feature = "std")]
+    pub fn reader(&mut self) -> QueueReader<'_> {
+        self.collection.reader()
+    }
+
+    /// See `Queue::enqueue`
+    ///
+    /// # Safety
+    ///
+    /// As for `Queue::enqueue`
+    pub unsafe fn enqueue(&mut self, size: usize) -> *mut u8 {
+        self.collection.enqueue(size)
+    }
+
+    /// See `Queue::reserve_bytes`
+    pub fn reserve_bytes(&mut self, additional: usize) {
+        self.collection.reserve_bytes(additional)
+    }
+
+    /// See `Queue::dequeue`
+    ///
+    /// # Safety
+    ///
+    /// As for `Queue::dequeue`
+    pub unsafe fn dequeue(&mut self) -> Option<*const u8> {
+        self.collection.dequeue()
+    }
+
+    /// See `Queue::dequeue_with_len`
+    ///
+    /// # Safety
+    ///
+    /// As for `Queue::dequeue_with_len`
+    pub unsafe fn dequeue_with_len(&mut self) -> Option<(*const u8, usize)> {
+        self.collection.dequeue_with_len()
+    }
+
+    /// See `Queue::drop_old_chunks`
+    ///
+    /// # Safety
+    ///
+    /// As for `Queue::drop_old_chunks`
+    pub unsafe fn drop_old_chunks(&mut self) {
+        self.collection.drop_old_chunks()
+    }
+}
+
+impl Shared<TaggedQueue> {
+    /// See `TaggedQueue::enqueue`
+    ///
+    /// # Safety
+    ///
+    /// As for `TaggedQueue::enqueue`
+    pub unsafe fn enqueue(&mut self, tag: u32, size: usize) -> *mut u8 {
+        self.collection.enqueue(tag, size)
+    }
+
+    /// See `TaggedQueue::dequeue`
+    ///
+    /// # Safety
+    ///
+    /// As for `TaggedQueue::dequeue`
+    pub unsafe fn dequeue(&mut self) -> Option<(u32, *const u8, usize)> {
+        self.collection.dequeue()
+    }
+
+    /// See `TaggedQueue::drop_old_chunks`
+    ///
+    /// # Safety
+    ///
+    /// As for `TaggedQueue::drop_old_chunks`
+    pub unsafe fn drop_old_chunks(&mut self) {
+        self.collection.drop_old_chunks()
+    }
+}
+
+impl Shared<Deque> {
+    /// See `Deque::push_back`
+    pub fn push_back(&mut self) -> *mut u8 {
+        self.collection.push_back()
+    }
+
+    /// See `Deque::push_front`
+    pub fn push_front(&mut self) -> *mut u8 {
+        self.collection.push_front()
+    }
+
+    /// See `Deque::pop_front`
+    ///
+    /// # Safety
+    ///
+    /// As for `Deque::pop_front`
+    pub unsafe fn pop_front(&mut self) -> Option<*const u8> {
+        self.collection.pop_front()
+    }
+
+    /// See `Deque::pop_back`
+    ///
+    /// # Safety
+    ///
+    /// As for `Deque::pop_back`
+    pub unsafe fn pop_back(&mut self) -> Option<*const u8> {
+        self.collection.pop_back()
+    }
+
+    /// See `Deque::drop_old_chunks`
+    ///
+    /// # Safety
+    ///
+    /// As for `Deque::drop_old_chunks`
+    pub unsafe fn drop_old_chunks(&mut self) {
+        self.collection.drop_old_chunks()
+    }
+}
+
+impl Shared<MultiArena> {
+    /// See `MultiArena::ensure_bin`
+    pub fn ensure_bin(&mut self, size: usize) -> usize {
+        self.collection.ensure_bin(size)
+    }
+
+    /// See `MultiArena::reserve_bin`
+    pub fn reserve_bin(&mut self, size: usize, additional: usize) {
+        self.collection.reserve_bin(size, additional)
+    }
+
+    /// See `MultiArena::shrink_bin`
+    pub fn shrink_bin(&mut self, bin_index: usize) {
+        self.collection.shrink_bin(bin_index)
+    }
+
+    /// See `MultiArena::shrink_to_fit`
+    pub fn shrink_to_fit(&mut self) {
+        self.collection.shrink_to_fit()
+    }
+
+    /// See `MultiArena::defragment`
+    pub fn defragment(&mut self) -> usize {
+        self.collection.defragment()
+    }
+
+    /// See `MultiArena::at_mut`
+    pub fn at_mut(&mut self, index: MultiArenaIndex) -> *mut u8 {
+        self.collection.at_mut(index)
+    }
+
+    /// See `MultiArena::push`
+    pub fn push(&mut self, size: usize) -> (*mut u8, MultiArenaIndex) {
+        self.collection.push(size)
+    }
+
+    /// See `MultiArena::try_push`
+    pub fn try_push(&mut self, size: usize) -> Result<(*mut u8, MultiArenaIndex), SizeTooLargeError> {
+        self.collection.try_push(size)
+    }
+
+    /// See `MultiArena::push_sized`
+    pub fn push_sized(&mut self, size: usize) -> SizedHandle {
+        self.collection.push_sized(size)
+    }
+
+    /// See `MultiArena::write`
+    pub fn write(&mut self, handle: SizedHandle, bytes: &[u8]) {
+        self.collection.write(handle, bytes)
+    }
+
+    /// See `MultiArena::swap_remove_within_bin`
+    pub fn swap_remove_within_bin(&mut self, index: MultiArenaIndex) -> Option<*const u8> {
+        self.collection.swap_remove_within_bin(index)
+    }
+}
+
+impl Shared<BitVec> {
+    /// See `BitVec::set`
+    pub fn set(&mut self, index: usize) {
+        self.collection.set(index)
+    }
+
+    /// See `BitVec::clear`
+    pub fn clear(&mut self, index: usize) {
+        self.collection.clear(index)
+    }
+
+    /// See `BitVec::resize`
+    pub fn resize(&mut self, new_len: usize) {
+        self.collection.resize(new_len)
+    }
+}
+
+impl<K: ::core::hash::Hash + Eq + Send, V: Send> Shared<ChunkyMap<K, V>> {
+    /// See `ChunkyMap::get_mut`
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.collection.get_mut(key)
+    }
+
+    /// See `ChunkyMap::insert`
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.collection.insert(key, value)
+    }
+
+    /// See `ChunkyMap::remove`
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.collection.remove(key)
+    }
+}
+
+unsafe impl Send for Shared<Arena> {}
+unsafe impl<T: Send> Send for Shared<TypedArena<T>> {}
+unsafe impl<T: Send> Send for Shared<GenerationalArena<T>> {}
+unsafe impl<Item: Clone + Send> Send for Shared<Vector<Item>> {}
+unsafe impl Send for Shared<Queue> {}
+unsafe impl Send for Shared<TaggedQueue> {}
+unsafe impl Send for Shared<Deque> {}
+unsafe impl Send for Shared<MultiArena> {}
+unsafe impl Send for Shared<BitVec> {}
+unsafe impl<K: ::core::hash::Hash + Eq + Send, V: Send> Send for Shared<ChunkyMap<K, V>> {}
@@ -0,0 +1,171 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident, MmapStorage};
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Alignment of all chunks handed out, matching what the system allocator guarantees
+const CHUNK_ALIGN: usize = 16;
+
+/// The zeroed heap allocation holding the contents of a chunk
+struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+    layout: Layout,
+}
+
+impl Buffer {
+    fn new(len: usize) -> Buffer {
+        // zero-sized allocations aren't allowed, so allocate at least one byte
+        let layout = Layout::from_size_align(::std::cmp::max(len, 1), CHUNK_ALIGN).expect("Invalid chunk size");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        Buffer { ptr, len, layout }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+/// A `ChunkStorage` that keeps all chunks in memory, like `HeapStorage`, so using them
+/// never causes any disk I/O, but which persists them only when explicitly asked to.
+///
+/// Chunks are kept after they are dropped (they are `Persistent`), so collections can be loaded
+/// again from the storage. `commit` writes all of them to a directory in the layout of an
+/// `MmapStorage` (with default options), from which `open` loads them back into memory.
+pub struct SnapshotStorage {
+    chunks: RefCell<HashMap<Ident, Rc<Buffer>>>,
+}
+
+/// Keeps the buffer alive for as long as the chunk, even if it is forgotten through another chunk
+struct SnapshotStorageHandle(Rc<Buffer>);
+
+impl SnapshotStorage {
+    /// Create an empty `SnapshotStorage`
+    pub fn new() -> SnapshotStorage {
+        SnapshotStorage { chunks: RefCell::new(HashMap::new()) }
+    }
+
+    /// Load all chunks committed to `directory` into memory
+    pub fn open(directory: PathBuf) -> SnapshotStorage {
+        let files = MmapStorage::new(directory);
+        let chunks = files.list_all_chunks()
+            .into_iter()
+            .map(|ident| {
+                let file_chunk = files.load_chunk(ident.clone());
+                let buffer = Buffer::new(file_chunk.len());
+                unsafe { ::std::ptr::copy_nonoverlapping(file_chunk.as_ptr(), buffer.ptr, buffer.len) };
+                (ident, Rc::new(buffer))
+            })
+            .collect();
+        SnapshotStorage { chunks: RefCell::new(chunks) }
+    }
+
+    /// Write the current contents of all chunks to `directory`, replacing any chunks
+    /// committed there before and removing the ones that were forgotten since.
+    ///
+    /// This isn't atomic: if it is interrupted, `directory` is left with a mix of old and new chunks.
+    pub fn commit(&self, directory: PathBuf) -> ::std::io::Result<()> {
+        let files = MmapStorage::new(directory);
+        let chunks = self.chunks.borrow();
+
+        for ident in files.list_all_chunks() {
+            if chunks.get(&ident).map(|buffer| buffer.len) != files.chunk_len(&ident) {
+                files.try_forget_chunk(files.load_chunk(ident))?;
+            }
+        }
+
+        for (ident, buffer) in chunks.iter() {
+            let mut file_chunk = if files.chunk_exists(ident) {
+                files.load_chunk(ident.clone())
+            } else {
+                files.try_create_chunk(ident.clone(), buffer.len)?
+            };
+            file_chunk.copy_from_slice(buffer.bytes());
+            files.flush_chunk(&file_chunk);
+        }
+        Ok(())
+    }
+
+    fn chunk(buffer: Rc<Buffer>) -> Chunk {
+        let (ptr, len, capacity) = (buffer.ptr, buffer.len, buffer.layout.size());
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, len, capacity, ChunkKind::Persistent, Box::new(SnapshotStorageHandle(buffer))) }
+    }
+
+    /// Remove the chunk's buffer from the storage, if the chunk was created by it
+    fn forget_handle(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        let handle = chunk._handle_to_drop.downcast::<SnapshotStorageHandle>()
+            .map_err(|_| crate::foreign_chunk_error("SnapshotStorage"))?;
+        self.chunks.borrow_mut().retain(|_, buffer| !Rc::ptr_eq(buffer, &handle.0));
+        Ok(())
+    }
+}
+
+impl Default for SnapshotStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkStorage for SnapshotStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        let buffer = Rc::new(Buffer::new(size));
+        self.chunks.borrow_mut().insert(ident, Rc::clone(&buffer));
+        Self::chunk(buffer)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        if self.chunk_exists(&ident) {
+            (self.load_chunk(ident), false)
+        } else {
+            (self.create_chunk(ident, size), true)
+        }
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let buffer = self.chunks.borrow().get(&ident).cloned()
+            .unwrap_or_else(|| panic!("Can't load chunk {}", ident.0));
+        Self::chunk(buffer)
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        if self.forget_handle(chunk).is_err() {
+            crate::warn_foreign_chunk("SnapshotStorage");
+        }
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        self.forget_handle(chunk)
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.chunks.borrow().contains_key(ident)
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.chunks.borrow().get(ident).map(|buffer| buffer.len)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.chunks.borrow().keys()
+            .filter(|ident| ident.belongs_to(group))
+            .cloned()
+            .collect()
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let chunks = self.chunks.borrow();
+        let buffer = chunks.get(ident).unwrap_or_else(|| panic!("Can't read chunk {}", ident.0));
+        crate::checksum(buffer.bytes())
+    }
+}
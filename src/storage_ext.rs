@@ -0,0 +1,92 @@
+use crate::{ChunkStorage, Ident};
+use crate::value::{Value, Portable, PortableValue};
+use crate::atomic_value::{AtomicValue, AtomicInt};
+use crate::arena::Arena;
+use crate::typed_arena::TypedArena;
+use crate::vector::Vector;
+use crate::queue::Queue;
+use crate::tagged_queue::TaggedQueue;
+use crate::deque::Deque;
+use crate::multi_arena::MultiArena;
+use crate::bit_vec::BitVec;
+use crate::chunky_map::ChunkyMap;
+use alloc::rc::Rc;
+
+/// Build collections directly from a shared storage handle,
+/// instead of passing `Rc::clone(&storage)` to each constructor
+pub trait ChunkStorageExt {
+    /// Load or create a `Value`, see `Value::load_or_default`
+    fn value<V>(&self, ident: Ident, default: V) -> Value<V>;
+    /// Load or create a `PortableValue`, see `PortableValue::load_or_default`
+    fn portable_value<V: Portable>(&self, ident: Ident, default: V) -> PortableValue<V>;
+    /// Load or create an `AtomicValue`, see `AtomicValue::load_or_default`
+    fn atomic_value<T: AtomicInt>(&self, ident: Ident, default: T) -> AtomicValue<T>;
+    /// Load or create an `Arena`, see `Arena::new`
+    fn arena(&self, ident: Ident, chunk_size: usize, item_size: usize) -> Arena;
+    /// Load or create a `TypedArena`, see `TypedArena::new`
+    fn typed_arena<T>(&self, ident: Ident, chunk_size: usize) -> TypedArena<T>;
+    /// Load or create a `Vector`, see `Vector::new`
+    fn vector<Item: Clone>(&self, ident: Ident, chunk_size: usize) -> Vector<Item>;
+    /// Load or create a `Queue`, see `Queue::new`
+    fn queue(&self, ident: &Ident, typical_chunk_size: usize) -> Queue;
+    /// Load or create a `TaggedQueue`, see `TaggedQueue::new`
+    fn tagged_queue(&self, ident: &Ident, typical_chunk_size: usize) -> TaggedQueue;
+    /// Load or create a `Deque`, see `Deque::new`
+    fn deque(&self, ident: Ident, chunk_size: usize, item_size: usize) -> Deque;
+    /// Load or create a `MultiArena`, see `MultiArena::new`
+    fn multi_arena(&self, ident: Ident, typical_chunk_size: usize, base_size: usize) -> MultiArena;
+    /// Load or create a `BitVec`, see `BitVec::new`
+    fn bit_vec(&self, ident: Ident, chunk_size: usize) -> BitVec;
+    /// Load or create a `ChunkyMap`, see `ChunkyMap::new`
+    fn chunky_map<K: ::core::hash::Hash + Eq, V>(&self, ident: Ident, chunk_size: usize) -> ChunkyMap<K, V>;
+}
+
+impl ChunkStorageExt for Rc<dyn ChunkStorage> {
+    fn value<V>(&self, ident: Ident, default: V) -> Value<V> {
+        Value::load_or_default(ident, default, Rc::clone(self))
+    }
+
+    fn portable_value<V: Portable>(&self, ident: Ident, default: V) -> PortableValue<V> {
+        PortableValue::load_or_default(ident, default, Rc::clone(self))
+    }
+
+    fn atomic_value<T: AtomicInt>(&self, ident: Ident, default: T) -> AtomicValue<T> {
+        AtomicValue::load_or_default(ident, default, Rc::clone(self))
+    }
+
+    fn arena(&self, ident: Ident, chunk_size: usize, item_size: usize) -> Arena {
+        Arena::new(ident, chunk_size, item_size, Rc::clone(self))
+    }
+
+    fn typed_arena<T>(&self, ident: Ident, chunk_size: usize) -> TypedArena<T> {
+        TypedArena::new(ident, chunk_size, Rc::clone(self))
+    }
+
+    fn vector<Item: Clone>(&self, ident: Ident, chunk_size: usize) -> Vector<Item> {
+        Vector::new(ident, chunk_size, Rc::clone(self))
+    }
+
+    fn queue(&self, ident: &Ident, typical_chunk_size: usize) -> Queue {
+        Queue::new(ident, typical_chunk_size, Rc::clone(self))
+    }
+
+    fn tagged_queue(&self, ident: &Ident, typical_chunk_size: usize) -> TaggedQueue {
+        TaggedQueue::new(ident, typical_chunk_size, Rc::clone(self))
+    }
+
+    fn deque(&self, ident: Ident, chunk_size: usize, item_size: usize) -> Deque {
+        Deque::new(ident, chunk_size, item_size, Rc::clone(self))
+    }
+
+    fn multi_arena(&self, ident: Ident, typical_chunk_size: usize, base_size: usize) -> MultiArena {
+        MultiArena::new(ident, typical_chunk_size, base_size, Rc::clone(self))
+    }
+
+    fn bit_vec(&self, ident: Ident, chunk_size: usize) -> BitVec {
+        BitVec::new(ident, chunk_size, Rc::clone(self))
+    }
+
+    fn chunky_map<K: ::core::hash::Hash + Eq, V>(&self, ident: Ident, chunk_size: usize) -> ChunkyMap<K, V> {
+        ChunkyMap::new(ident, chunk_size, Rc::clone(self))
+    }
+}
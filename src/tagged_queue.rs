@@ -0,0 +1,86 @@
+use crate::{ChunkStorage, Ident};
+use crate::queue::Queue;
+use crate::shared::{SendableStorage, Shared};
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// Space taken up by the header in front of each payload:
+/// the tag and the payload size as little-endian `u32`s
+const TAG_SIZE: usize = 8;
+
+/// Payloads are padded to a multiple of this, to keep the queue's entries 8-byte aligned
+const PAYLOAD_ALIGN: usize = 8;
+
+/// A `Queue` of heterogeneously typed messages, each enqueued with a `u32` tag
+/// that tells the consumer which type to interpret it as
+pub struct TaggedQueue {
+    queue: Queue,
+}
+
+impl TaggedQueue {
+    /// Create a new tagged queue
+    pub fn new(ident: &Ident, typical_chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        TaggedQueue {
+            queue: Queue::new(ident, typical_chunk_size, storage),
+        }
+    }
+
+    /// Create a new tagged queue like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: &Ident, typical_chunk_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, typical_chunk_size, storage))
+    }
+
+    /// Number of messages in the queue
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Is the queue empty?
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Enqueue a message of a given size with `tag`. Returns a pointer that the message can be written to.
+    ///
+    /// # Safety
+    ///
+    /// See `Queue::enqueue`.
+    pub unsafe fn enqueue(&mut self, tag: u32, size: usize) -> *mut u8 {
+        assert!(size <= u32::MAX as usize, "Message too large for tagged queue");
+        let entry_ptr = self.queue.enqueue(TAG_SIZE + size.next_multiple_of(PAYLOAD_ALIGN));
+        ::core::ptr::copy_nonoverlapping(tag.to_le_bytes().as_ptr(), entry_ptr, 4);
+        ::core::ptr::copy_nonoverlapping((size as u32).to_le_bytes().as_ptr(), entry_ptr.add(4), 4);
+        entry_ptr.add(TAG_SIZE)
+    }
+
+    /// Dequeue a message. Returns its tag, a pointer to it in the queue and its size,
+    /// unless the queue is empty.
+    ///
+    /// # Safety
+    ///
+    /// See `Queue::dequeue`.
+    pub unsafe fn dequeue(&mut self) -> Option<(u32, *const u8, usize)> {
+        self.queue.dequeue().map(|entry_ptr| {
+            let mut tag_bytes = [0u8; 4];
+            let mut size_bytes = [0u8; 4];
+            ::core::ptr::copy_nonoverlapping(entry_ptr, tag_bytes.as_mut_ptr(), 4);
+            ::core::ptr::copy_nonoverlapping(entry_ptr.add(4), size_bytes.as_mut_ptr(), 4);
+            (u32::from_le_bytes(tag_bytes), entry_ptr.add(TAG_SIZE), u32::from_le_bytes(size_bytes) as usize)
+        })
+    }
+
+    /// Delete chunks which have already been read
+    ///
+    /// # Safety
+    ///
+    /// See `Queue::drop_old_chunks`.
+    pub unsafe fn drop_old_chunks(&mut self) {
+        self.queue.drop_old_chunks()
+    }
+}
+
+impl crate::Flushable for TaggedQueue {
+    fn flush(&self) {
+        self.queue.flush();
+    }
+}
@@ -0,0 +1,73 @@
+use crate::vector::Vector;
+use alloc::vec::Vec;
+
+enum Operation<Item> {
+    Pushed,
+    Popped(Item),
+}
+
+/// A batch of mutations to a `Vector` which is rolled back when dropped, unless committed.
+///
+/// Mutations are applied to the vector (and thus its chunks) right away,
+/// while a journal of them is kept so their inverse can be replayed on rollback.
+pub struct Transaction<'a, Item: Clone> {
+    vector: &'a mut Vector<Item>,
+    journal: Vec<Operation<Item>>,
+    committed: bool,
+}
+
+impl<'a, Item: Clone> Transaction<'a, Item> {
+    /// Start a transaction on `vector`
+    pub fn new(vector: &'a mut Vector<Item>) -> Self {
+        Transaction {
+            vector,
+            journal: Vec::new(),
+            committed: false,
+        }
+    }
+
+    /// Push an item onto the vector
+    pub fn push(&mut self, item: Item) {
+        self.vector.push(item);
+        self.journal.push(Operation::Pushed);
+    }
+
+    /// Remove and return the last item, if the vector wasn't empty
+    pub fn pop(&mut self) -> Option<Item> {
+        let maybe_item = self.vector.pop();
+        if let Some(ref item) = maybe_item {
+            self.journal.push(Operation::Popped(item.clone()));
+        }
+        maybe_item
+    }
+
+    /// Keep all mutations made in this transaction,
+    /// flushing the vector to its persisted representation (if any)
+    pub fn commit(mut self) {
+        crate::Flushable::flush(&*self.vector);
+        self.committed = true;
+    }
+}
+
+impl<'a, Item: Clone> ::core::ops::Deref for Transaction<'a, Item> {
+    type Target = Vector<Item>;
+
+    fn deref(&self) -> &Vector<Item> {
+        self.vector
+    }
+}
+
+impl<'a, Item: Clone> Drop for Transaction<'a, Item> {
+    fn drop(&mut self) {
+        if !self.committed {
+            for operation in self.journal.drain(..).rev() {
+                match operation {
+                    Operation::Pushed => {
+                        self.vector.pop();
+                    }
+                    Operation::Popped(item) => self.vector.push(item),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{ChunkStorage, Ident};
+use crate::arena::{Arena, ArenaIndex};
+use crate::shared::{SendableStorage, Shared};
+use core::marker::PhantomData;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// An `Arena` which stores items of a known type, accessed by their `ArenaIndex`.
+///
+/// Unlike `Vector`, it is meant to be used like the raw `Arena`: items are referred to
+/// by index and removed by swapping in the last item, which then takes over the removed index.
+pub struct TypedArena<T> {
+    arena: Arena,
+    _marker: PhantomData<T>,
+}
+
+impl<T> TypedArena<T> {
+    /// Create a new typed arena given a chunk group identifier and chunk size
+    pub fn new(ident: Ident, chunk_size: usize, storage: Rc<dyn ChunkStorage>) -> Self {
+        let item_size = ::core::mem::size_of::<T>();
+        TypedArena {
+            arena: Arena::new(ident, ::core::cmp::max(item_size, chunk_size), item_size, storage),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create a new typed arena like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, storage: Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, storage))
+    }
+
+    /// Number of items in the arena
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Is the arena empty?
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    /// Add an item to the end, returning its index
+    pub fn push(&mut self, item: T) -> ArenaIndex {
+        let (item_ptr, index) = self.arena.push();
+        unsafe { ::core::ptr::write(item_ptr as *mut T, item) };
+        index
+    }
+
+    /// Whether there is an item at `index`, such as one deserialized from elsewhere
+    pub fn contains(&self, index: ArenaIndex) -> bool {
+        self.arena.contains(index)
+    }
+
+    /// Get a reference to the item at `index`
+    pub fn get(&self, index: ArenaIndex) -> Option<&T> {
+        self.arena.get_ptr(index).map(|item_ptr| unsafe { &*(item_ptr as *const T) })
+    }
+
+    /// Get a mutable reference to the item at `index`
+    pub fn get_mut(&mut self, index: ArenaIndex) -> Option<&mut T> {
+        self.arena.get_mut_ptr(index).map(|item_ptr| unsafe { &mut *(item_ptr as *mut T) })
+    }
+
+    /// Remove and return the item at `index`, moving the last item to `index` in its place
+    pub fn swap_remove(&mut self, index: ArenaIndex) -> Option<T> {
+        if self.contains(index) {
+            unsafe {
+                let item = ::core::ptr::read(self.arena.at(index) as *const T);
+                self.arena.swap_remove(index);
+                Some(item)
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// Dropping a typed arena drops all its items, but keeps its chunks' persisted representation (if any)
+impl<T> Drop for TypedArena<T> {
+    fn drop(&mut self) {
+        if ::core::mem::needs_drop::<T>() {
+            for index in 0..self.len() {
+                unsafe { ::core::ptr::drop_in_place(self.arena.at_mut(ArenaIndex(index)) as *mut T) };
+            }
+        }
+    }
+}
+
+impl<T> crate::Flushable for TypedArena<T> {
+    fn flush(&self) {
+        self.arena.flush();
+    }
+}
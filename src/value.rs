@@ -1,49 +1,294 @@
 use crate::{Chunk, ChunkStorage, Ident};
-use std::rc::Rc;
-use std::marker::PhantomData;
+use alloc::rc::Rc;
+use core::marker::PhantomData;
+use core::convert::TryFrom;
 
 /// A single value stored in a chunk
+///
+/// Zero-sized values don't need any storage, so no chunk is created for them.
+///
+/// The value is stored in its native layout, so processes built for different targets
+/// can't share it (such as by mmapping the same file), unless they only access it
+/// with `read_le` and `write_le`.
 pub struct Value<V> {
-    chunk: Chunk,
+    chunk: Option<Chunk>,
+    storage: Rc<dyn ChunkStorage>,
     _marker: PhantomData<*mut V>,
 }
 
 impl<V> Value<V> {
     /// Load the value in the chunk with the given identifier, or create it using a default value
     pub fn load_or_default(ident: Ident, default: V, storage: Rc<dyn ChunkStorage>) -> Value<V> {
-        let (mut chunk, created_new) = storage.load_or_create_chunk(ident, ::std::mem::size_of::<V>());
+        if ::core::mem::size_of::<V>() == 0 {
+            let mut value = Value::<V> {
+                chunk: None,
+                storage,
+                _marker: PhantomData,
+            };
+            unsafe { ::core::ptr::write(value.ptr(), default) };
+            return value;
+        }
+
+        let (chunk, created_new) = storage.load_or_create_chunk(ident, ::core::mem::size_of::<V>());
+        let mut value = Self::from_chunk(chunk, storage);
 
         if created_new {
             unsafe {
-                ::std::ptr::write(chunk.as_mut_ptr() as *mut V, default);
+                ::core::ptr::write(value.ptr(), default);
             }
         }
 
-        Value {
-            chunk,
+        value
+    }
+
+    /// Load the value in the existing chunk with the given identifier, like `load_or_default`,
+    /// but if the chunk's size differs from that of `V` (because `V`'s layout changed since it was
+    /// persisted), produce the value by passing the old bytes to `migrate` instead, and replace the chunk
+    /// with one of the new size holding it.
+    ///
+    /// The migrated value is first written to a staging chunk, and the old chunk is only forgotten
+    /// once that is flushed, so a migration that is interrupted is either redone from the old bytes
+    /// or finished from the staged value the next time this is called.
+    pub fn load_migrating<F: FnOnce(&[u8]) -> V>(ident: Ident, storage: Rc<dyn ChunkStorage>, migrate: F) -> Value<V> {
+        let size = ::core::mem::size_of::<V>();
+        let staged_ident = ident.sub("migrated");
+        if storage.chunk_exists(&staged_ident) {
+            if storage.chunk_len(&ident).is_some_and(|len| len != size) {
+                // interrupted before the old chunk was forgotten, so the staged value might be incomplete
+                storage.forget_chunk(storage.load_chunk(staged_ident.clone()));
+            } else {
+                let staged = storage.load_chunk(staged_ident);
+                return Self::replace_with_staged(ident, staged, storage);
+            }
+        }
+
+        let old_chunk = storage.load_chunk(ident.clone());
+        if old_chunk.len() == size && !old_chunk.is_empty() {
+            return Self::from_chunk(old_chunk, storage);
+        }
+        if size == 0 {
+            let migrated = migrate(&old_chunk);
+            storage.forget_chunk(old_chunk);
+            return Self::load_or_default(ident, migrated, storage);
+        }
+
+        let mut staged = storage.create_chunk(staged_ident, size);
+        unsafe { ::core::ptr::write_unaligned(staged.as_mut_ptr() as *mut V, migrate(&old_chunk)) };
+        storage.flush_chunk(&staged);
+        storage.forget_chunk(old_chunk);
+        Self::replace_with_staged(ident, staged, storage)
+    }
+
+    /// Copy a completely written migrated value from its staging chunk into a new chunk
+    /// with the value's identifier (replacing whatever is left there), then forget the staging chunk
+    fn replace_with_staged(ident: Ident, staged: Chunk, storage: Rc<dyn ChunkStorage>) -> Value<V> {
+        if storage.chunk_exists(&ident) {
+            storage.forget_chunk(storage.load_chunk(ident.clone()));
+        }
+        let mut chunk = storage.create_chunk(ident, staged.len());
+        chunk.copy_from_slice(&staged);
+        storage.flush_chunk(&chunk);
+        storage.forget_chunk(staged);
+        Self::from_chunk(chunk, storage)
+    }
+
+    fn from_chunk(chunk: Chunk, storage: Rc<dyn ChunkStorage>) -> Value<V> {
+        assert!(
+            chunk.len() >= ::core::mem::size_of::<V>() && (chunk.as_ptr() as *const V).is_aligned(),
+            "Chunk is too small or not aligned for value"
+        );
+
+        Value::<V> {
+            chunk: Some(chunk),
+            storage,
             _marker: PhantomData,
         }
     }
+
+    /// Pointer to the stored value, which is dangling (but aligned) for zero-sized values
+    fn ptr(&mut self) -> *mut V {
+        match self.chunk {
+            Some(ref mut chunk) => chunk.as_mut_ptr() as *mut V,
+            None => ::core::ptr::NonNull::dangling().as_ptr(),
+        }
+    }
+
+    /// Modify the value in place using `f`, then flush it to its persisted representation (if any)
+    pub fn modify<F: FnOnce(&mut V)>(&mut self, f: F) {
+        f(&mut **self);
+        if let Some(ref chunk) = self.chunk {
+            self.storage.flush_chunk(chunk);
+        }
+    }
 }
 
-impl<V> ::std::ops::Deref for Value<V> {
+/// Reading and writing a value in its `Portable` (little-endian) representation, rather than
+/// its native layout, so processes built for different targets can share it (such as by mmapping
+/// the same file). All processes sharing the value have to use only these to access it.
+impl<V: Portable> Value<V> {
+    fn portable_bytes(&self) -> &[u8] {
+        let chunk = self.chunk.as_ref().expect("Zero-sized values have no portable representation");
+        &chunk[..V::SIZE]
+    }
+
+    /// Decode the value from its portable representation
+    pub fn read_le(&self) -> V {
+        V::decode(self.portable_bytes())
+    }
+
+    /// Store `value` in its portable representation, then flush it to its persisted representation
+    pub fn write_le(&mut self, value: V) {
+        let chunk = self.chunk.as_mut().expect("Zero-sized values have no portable representation");
+        value.encode(&mut chunk[..V::SIZE]);
+        self.storage.flush_chunk(chunk);
+    }
+}
+
+/// Guarding a value against stray writes by changing its memory protection (using `mprotect`).
+///
+/// This only works on chunks which have their own pages, such as the ones of `MmapStorage` and
+/// `AnonMmapStorage`: the chunk has to start at a page boundary (otherwise protecting fails with
+/// `ErrorKind::InvalidInput`), and the protection applies to all pages the chunk is on,
+/// including memory after its end up to the next page boundary.
+#[cfg(all(feature = "mmap", unix))]
+impl<V> Value<V> {
+    /// Make the value read-only, so any write to it (including through `DerefMut`) faults,
+    /// until `unprotect` is called
+    pub fn protect(&self) -> ::std::io::Result<()> {
+        self.set_protection(libc::PROT_READ)
+    }
+
+    /// Make the value writable again after `protect`
+    pub fn unprotect(&self) -> ::std::io::Result<()> {
+        self.set_protection(libc::PROT_READ | libc::PROT_WRITE)
+    }
+
+    fn set_protection(&self, protection: libc::c_int) -> ::std::io::Result<()> {
+        if let Some(ref chunk) = self.chunk {
+            let page_size = crate::mmap_storage::page_size();
+            if !(chunk.as_ptr() as usize).is_multiple_of(page_size) {
+                return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidInput, "Only page-aligned chunks can be protected"));
+            }
+            if unsafe { libc::mprotect(chunk.as_ptr() as *mut libc::c_void, chunk.len(), protection) } != 0 {
+                return Err(::std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<V> ::core::ops::Deref for Value<V> {
     type Target = V;
 
     fn deref(&self) -> &V {
-        unsafe { (self.chunk.as_ptr() as *const V).as_ref().unwrap() }
+        match self.chunk {
+            Some(ref chunk) => unsafe { &*(chunk.as_ptr() as *const V) },
+            None => unsafe { &*::core::ptr::NonNull::dangling().as_ptr() },
+        }
     }
 }
 
-impl<V> ::std::ops::DerefMut for Value<V> {
+impl<V> ::core::ops::DerefMut for Value<V> {
     fn deref_mut(&mut self) -> &mut V {
-        unsafe { (self.chunk.as_mut_ptr() as *mut V).as_mut().unwrap() }
+        unsafe { &mut *self.ptr() }
     }
 }
 
 impl<V> Drop for Value<V> {
     fn drop(&mut self) {
         unsafe {
-            ::std::ptr::drop_in_place(self.chunk.as_mut_ptr());
+            ::core::ptr::drop_in_place(self.ptr());
         };
     }
-}
\ No newline at end of file
+}
+
+/// A type with a fixed-size, platform independent (little-endian) byte representation,
+/// so it can be persisted in a chunk and read back on a different architecture
+pub trait Portable: Sized {
+    /// Size of the encoded representation in bytes
+    const SIZE: usize;
+    /// Write the encoded representation into `bytes`, which is `SIZE` long
+    fn encode(&self, bytes: &mut [u8]);
+    /// Read a value back from its encoded representation in `bytes`, which is `SIZE` long
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+impl Portable for u64 {
+    const SIZE: usize = 8;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let mut buffer = [0u8; 8];
+        buffer.copy_from_slice(bytes);
+        u64::from_le_bytes(buffer)
+    }
+}
+
+/// `usize` is always persisted as a `u64`
+impl Portable for usize {
+    const SIZE: usize = 8;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        (*self as u64).encode(bytes);
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        usize::try_from(u64::decode(bytes)).expect("Persisted value doesn't fit in usize")
+    }
+}
+
+/// A single value stored in a chunk using its `Portable` representation
+///
+/// Unlike `Value`, it can't be dereferenced in place,
+/// but has to be explicitly decoded with `get` and encoded with `set`.
+pub struct PortableValue<V: Portable> {
+    chunk: Chunk,
+    _marker: PhantomData<*mut V>,
+}
+
+impl<V: Portable> PortableValue<V> {
+    /// Load the value in the chunk with the given identifier, or create it using a default value
+    pub fn load_or_default(ident: Ident, default: V, storage: Rc<dyn ChunkStorage>) -> PortableValue<V> {
+        let (mut chunk, created_new) = storage.load_or_create_chunk(ident, V::SIZE);
+
+        if created_new {
+            default.encode(&mut chunk[..V::SIZE]);
+        }
+
+        PortableValue {
+            chunk,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Decode the stored value
+    pub fn get(&self) -> V {
+        V::decode(&self.chunk[..V::SIZE])
+    }
+
+    /// Encode and store a new value
+    pub fn set(&mut self, value: V) {
+        value.encode(&mut self.chunk[..V::SIZE]);
+    }
+
+    /// Write the value through to its persisted representation (if any) in `storage`
+    pub(crate) fn flush(&self, storage: &dyn ChunkStorage) {
+        storage.flush_chunk(&self.chunk);
+    }
+
+    /// Give up the value, returning the chunk it is stored in
+    pub(crate) fn into_chunk(self) -> Chunk {
+        self.chunk
+    }
+}
+
+impl<V> crate::Flushable for Value<V> {
+    fn flush(&self) {
+        if let Some(ref chunk) = self.chunk {
+            self.storage.flush_chunk(chunk);
+        }
+    }
+}
@@ -1,6 +1,11 @@
-use crate::{ChunkStorage, Ident};
+use crate::{Chunk, ChunkStorage, Ident};
 use crate::arena::{Arena, ArenaIndex};
-use std::marker::PhantomData;
+use crate::transaction::Transaction;
+use crate::shared::{SendableStorage, Shared};
+use core::marker::PhantomData;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
 
 /// A vector which stores items of a known type in an `Arena`
 pub struct Vector<Item: Clone> {
@@ -10,14 +15,30 @@ pub struct Vector<Item: Clone> {
 
 impl<Item: Clone> Vector<Item> {
     /// Create a new chunky vector
-    pub fn new(ident: Ident, chunk_size: usize, storage: ::std::rc::Rc<dyn ChunkStorage>) -> Self {
-        let item_size = ::std::mem::size_of::<Item>();
+    pub fn new(ident: Ident, chunk_size: usize, storage: ::alloc::rc::Rc<dyn ChunkStorage>) -> Self {
+        let item_size = ::core::mem::size_of::<Item>();
         Vector {
-            arena: Arena::new(ident, ::std::cmp::max(item_size, chunk_size), item_size, storage),
+            arena: Arena::new(ident, ::core::cmp::max(item_size, chunk_size), item_size, storage),
             _marker: PhantomData,
         }
     }
 
+    /// Create a new chunky vector like `new`, on a thread-safe storage, so it can be sent between threads
+    pub fn new_shared(ident: Ident, chunk_size: usize, storage: ::alloc::sync::Arc<dyn SendableStorage>) -> Shared<Self> {
+        Shared::build(storage, |storage| Self::new(ident, chunk_size, storage))
+    }
+
+    /// Create a new chunky vector holding the items of `data`, creating all needed chunks up front
+    pub fn from_vec(ident: Ident, chunk_size: usize, storage: ::alloc::rc::Rc<dyn ChunkStorage>, mut data: Vec<Item>) -> Self {
+        let mut vector = Vector::new(ident, chunk_size, storage);
+        unsafe {
+            vector.arena.extend_from_ptr(data.as_ptr() as *const u8, data.len());
+            // the moved items are now owned by `vector`, so `data` only frees its buffer
+            data.set_len(0);
+        }
+        vector
+    }
+
     /// Get the number of elements in the vector
     pub fn len(&self) -> usize {
         self.arena.len()
@@ -28,6 +49,21 @@ impl<Item: Clone> Vector<Item> {
         self.len() == 0
     }
 
+    /// Size of each chunk in bytes, which is at least the item size
+    pub fn chunk_size(&self) -> usize {
+        self.arena.chunk_size()
+    }
+
+    /// Size of each item in bytes
+    pub fn item_size(&self) -> usize {
+        self.arena.item_size()
+    }
+
+    /// Number of items stored in each chunk
+    pub fn items_per_chunk(&self) -> usize {
+        self.arena.items_per_chunk()
+    }
+
     /// Get a reference to the item at `index`
     pub fn at(&self, index: usize) -> Option<&Item> {
         if index < self.len() {
@@ -46,14 +82,117 @@ impl<Item: Clone> Vector<Item> {
         }
     }
 
+    /// Get all items as a slice, if they are stored contiguously in a single chunk
+    pub fn try_as_slice(&self) -> Option<&[Item]> {
+        let mut runs = self.arena.chunk_runs();
+        match (runs.next(), runs.next()) {
+            (None, _) => Some(&[]),
+            (Some((chunk_ptr, n_items)), None) => {
+                Some(unsafe { ::core::slice::from_raw_parts(chunk_ptr as *const Item, n_items) })
+            }
+            _ => None,
+        }
+    }
+
+    /// Get all items as a mutable slice, if they are stored contiguously in a single chunk
+    pub fn try_as_mut_slice(&mut self) -> Option<&mut [Item]> {
+        let mut runs = self.arena.chunk_runs();
+        match (runs.next(), runs.next()) {
+            (None, _) => Some(&mut []),
+            (Some((chunk_ptr, n_items)), None) => {
+                Some(unsafe { ::core::slice::from_raw_parts_mut(chunk_ptr as *mut Item, n_items) })
+            }
+            _ => None,
+        }
+    }
+
+    /// Iterate over references to all items in order
+    pub fn iter(&self) -> impl Iterator<Item = &Item> + '_ {
+        self.arena.chunk_runs().flat_map(|(chunk_ptr, n_items)| {
+            unsafe { ::core::slice::from_raw_parts(chunk_ptr as *const Item, n_items) }.iter()
+        })
+    }
+
+    /// Iterate over all overlapping windows of `size` consecutive items, like `slice::windows`.
+    ///
+    /// Since windows can straddle chunk boundaries, each one is gathered into a `Vec` of references,
+    /// costing an allocation and `size` lookups per window.
+    pub fn windows(&self, size: usize) -> impl Iterator<Item = Vec<&Item>> + '_ {
+        assert!(size > 0, "window size must be nonzero");
+        let n_windows = (self.len() + 1).saturating_sub(size);
+        (0..n_windows).map(move |start| self.gather(start, size))
+    }
+
+    /// Iterate over non-overlapping groups of `size` consecutive items, like `slice::chunks_exact`,
+    /// leaving out the last `len % size` items.
+    ///
+    /// Like `windows`, each group is gathered into a `Vec` of references.
+    pub fn chunks_exact(&self, size: usize) -> impl Iterator<Item = Vec<&Item>> + '_ {
+        assert!(size > 0, "chunk size must be nonzero");
+        (0..self.len() / size).map(move |group| self.gather(group * size, size))
+    }
+
+    fn gather(&self, start: usize, size: usize) -> Vec<&Item> {
+        (start..start + size)
+            .map(|index| self.at(index).expect("should be in bounds"))
+            .collect()
+    }
+
+    /// Forget all chunks of this vector, deleting any persisted representation of it.
+    ///
+    /// This is meant for intentionally discarding a collection's data, so items are not dropped.
+    pub fn forget_all(self) {
+        let vector = ::core::mem::ManuallyDrop::new(self);
+        unsafe { ::core::ptr::read(&vector.arena) }.forget_all();
+    }
+
+    /// Get a view of the items in `range`, such as for handing a part of the vector to a function.
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, range: ::core::ops::Range<usize>) -> VectorSlice<'_, Item> {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "Slice range {}..{} out of bounds (len {})", range.start, range.end, self.len()
+        );
+        VectorSlice { vector: self, start: range.start, end: range.end }
+    }
+
+    /// Iterate over all items in order together with their indices, like `iter().enumerate()`,
+    /// but with `nth` jumping straight to the requested item
+    pub fn iter_enumerate_indices(&self) -> EnumerateIndices<'_, Item> {
+        EnumerateIndices { cursor: self.cursor() }
+    }
+
+    /// Get a cursor for reading the items in order, starting at the first one
+    pub fn cursor(&self) -> Cursor<'_, Item> {
+        Cursor {
+            vector: self,
+            index: 0,
+            item_ptr: ::core::ptr::null(),
+            left_in_chunk: 0,
+        }
+    }
+
     /// Push an item onto the vector
     pub fn push(&mut self, item: Item) {
         unsafe {
             let item_ptr = self.arena.push().0 as *mut Item;
-            *item_ptr = item;
+            ::core::ptr::write(item_ptr, item);
         }
     }
 
+    /// Create chunks ahead of time so that at least `additional` more items can be pushed
+    /// without allocating, returning an error (and leaving the vector as it was) if the storage
+    /// can't create them
+    #[cfg(feature = "std")]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), crate::arena::TryReserveError> {
+        self.arena.try_reserve(additional)
+    }
+
+    /// Forget trailing chunks that don't hold any items, such as ones created by `try_reserve`
+    pub fn shrink_to_fit(&mut self) {
+        self.arena.shrink_to_fit();
+    }
+
     /// Remove and return the last item, if the vector wasn't empty
     pub fn pop(&mut self) -> Option<Item> {
         if self.arena.len() == 0 {
@@ -62,10 +201,510 @@ impl<Item: Clone> Vector<Item> {
             unsafe {
                 let item_ptr: *const Item =
                     self.arena.at(ArenaIndex(self.arena.len() - 1)) as *const Item;
-                let item = Some(::std::ptr::read(item_ptr));
+                let item = Some(::core::ptr::read(item_ptr));
                 self.arena.pop_away();
                 item
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Shorten the vector to `len` items, dropping the rest.
+    /// Has no effect if the vector is not longer than `len`.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            // discard the slots even if dropping an item panics,
+            // so no item is ever dropped twice (at worst the remaining ones leak)
+            struct DiscardOnDrop<'a, Item: Clone>(&'a mut Vector<Item>, usize);
+
+            impl<'a, Item: Clone> Drop for DiscardOnDrop<'a, Item> {
+                fn drop(&mut self) {
+                    self.0.discard_from(self.1);
+                }
+            }
+
+            let guard = DiscardOnDrop(self, len);
+            let old_len = guard.0.len();
+            unsafe { guard.0.drop_range(len, old_len) };
+        }
+    }
+
+    /// Remove all items, dropping them
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Remove and return the item at `index`, replacing it with the last item.
+    ///
+    /// This does not preserve order, but is O(1).
+    pub fn swap_remove(&mut self, index: usize) -> Item {
+        let len = self.len();
+        assert!(index < len, "swap_remove index {} out of bounds (len {})", index, len);
+        unsafe {
+            let item_ptr = self.arena.at(ArenaIndex(index)) as *mut Item;
+            let item = ::core::ptr::read(item_ptr);
+            if index != len - 1 {
+                let last_ptr = self.arena.at(ArenaIndex(len - 1)) as *const Item;
+                ::core::ptr::copy_nonoverlapping(last_ptr, item_ptr, 1);
+            }
+            self.discard_from(len - 1);
+            item
+        }
+    }
+
+    /// Swap the items at `a` and `b`, which may be in different chunks
+    pub fn swap(&mut self, a: usize, b: usize) {
+        let len = self.len();
+        assert!(a < len && b < len, "swap indices {} and {} out of bounds (len {})", a, b, len);
+        if a != b {
+            unsafe {
+                ::core::ptr::swap_nonoverlapping(
+                    self.arena.at_mut(ArenaIndex(a)) as *mut Item,
+                    self.arena.at_mut(ArenaIndex(b)) as *mut Item,
+                    1,
+                );
+            }
+        }
+    }
+
+    /// Reverse the order of the items in `[start, end)`
+    fn reverse_range(&mut self, start: usize, end: usize) {
+        let (mut low, mut high) = (start, end);
+        while low + 1 < high {
+            high -= 1;
+            self.swap(low, high);
+            low += 1;
+        }
+    }
+
+    /// Rotate the items in place so that the item at `mid` becomes the first one
+    pub fn rotate_left(&mut self, mid: usize) {
+        let len = self.len();
+        assert!(mid <= len, "rotation {} out of bounds (len {})", mid, len);
+        // reversing both parts, then everything, puts them in swapped order
+        self.reverse_range(0, mid);
+        self.reverse_range(mid, len);
+        self.reverse_range(0, len);
+    }
+
+    /// Rotate the items in place so that the last `k` items become the first ones
+    pub fn rotate_right(&mut self, k: usize) {
+        let len = self.len();
+        assert!(k <= len, "rotation {} out of bounds (len {})", k, len);
+        self.rotate_left(len - k);
+    }
+
+    /// Create a deep copy of this vector with the identifier `new_ident` in `storage`
+    pub fn clone_to(&self, new_ident: Ident, storage: ::alloc::rc::Rc<dyn ChunkStorage>) -> Vector<Item> {
+        let mut clone = Vector::new(new_ident, self.arena.chunk_size(), storage);
+        for item in self.iter() {
+            clone.push(item.clone());
+        }
+        clone
+    }
+
+    /// Start a transaction of mutations which are rolled back unless committed
+    pub fn transaction(&mut self) -> Transaction<'_, Item> {
+        Transaction::new(self)
+    }
+
+    /// Record the current length, so `rollback` can remove all items pushed after this.
+    ///
+    /// This is cheap, but only undoes appending. To also undo changes to existing items, use `checkpoint_from`.
+    pub fn checkpoint(&self) -> Checkpoint<Item> {
+        self.checkpoint_from(self.len())
+    }
+
+    /// Record the current length and save copies of the items `[start, len)`,
+    /// so `rollback` can also undo changes to and removals of them
+    pub fn checkpoint_from(&self, start: usize) -> Checkpoint<Item> {
+        let len = self.len();
+        assert!(start <= len, "checkpoint start {} out of bounds (len {})", start, len);
+        Checkpoint {
+            len,
+            saved_from: start,
+            saved: (start..len).map(|index| self.at(index).expect("should have item").clone()).collect(),
+        }
+    }
+
+    /// Return to the state recorded by `checkpoint`, dropping all items pushed since,
+    /// forgetting chunks that were created for them, and restoring the saved items (if any).
+    ///
+    /// Panics if items before the checkpoint's saved items were removed since.
+    pub fn rollback(&mut self, checkpoint: Checkpoint<Item>) {
+        assert!(
+            self.len() >= checkpoint.saved_from,
+            "Can't roll back, items before {} were removed since the checkpoint",
+            checkpoint.saved_from
+        );
+        self.truncate(checkpoint.saved_from);
+        for item in checkpoint.saved {
+            self.push(item);
+        }
+        debug_assert_eq!(self.len(), checkpoint.len);
+    }
+
+    /// Split the vector in two at `at`, moving the items `[at, len)`
+    /// into a new vector with the identifier `new_ident` in the same storage
+    pub fn split_off(&mut self, at: usize, new_ident: Ident) -> Vector<Item> {
+        assert!(at <= self.len(), "split index {} out of bounds (len {})", at, self.len());
+        let mut tail = Vector::new(new_ident, self.arena.chunk_size(), Rc::clone(self.arena.storage()));
+
+        for index in at..self.len() {
+            unsafe {
+                tail.push(::core::ptr::read(self.arena.at(ArenaIndex(index)) as *const Item));
+            }
+        }
+
+        // the moved items are now owned by `tail`, so just discard their slots
+        self.discard_from(at);
+
+        tail
+    }
+
+    /// Move all items of `other` onto the end of this vector, leaving `other` empty
+    pub fn append(&mut self, other: &mut Vector<Item>) {
+        let other_items_per_chunk = other.items_per_chunk();
+        self.arena.reserve(other.len());
+
+        // copy each of the runs of items that are contiguous within one chunk of `other` at once
+        let mut index = 0;
+        while index < other.len() {
+            let run = ::core::cmp::min(other_items_per_chunk - index % other_items_per_chunk, other.len() - index);
+            unsafe {
+                self.arena.extend_from_ptr(other.arena.at(ArenaIndex(index)), run);
+            }
+            index += run;
+        }
+
+        // the moved items are now owned by `self`, so `other` just discards its slots,
+        // forgetting its chunks along the way
+        other.discard_from(0);
+    }
+
+    /// Overwrite every item with a clone of `value`, dropping the old items, without changing the length
+    pub fn fill(&mut self, value: Item) {
+        let len = self.len();
+        if len > 0 {
+            for index in 0..len - 1 {
+                *self.at_mut(index).unwrap() = value.clone();
+            }
+            *self.at_mut(len - 1).unwrap() = value;
+        }
+    }
+
+    /// Overwrite every item with the result of calling `f`, dropping the old items, without changing the length
+    pub fn fill_with<F: FnMut() -> Item>(&mut self, mut f: F) {
+        for index in 0..self.len() {
+            *self.at_mut(index).unwrap() = f();
+        }
+    }
+
+    /// Replace every item with the result of applying `f` to it, in place
+    ///
+    /// If `f` panics, the item it was given is gone, so the vector is
+    /// truncated to the items before it (dropping the items after it).
+    pub fn map_in_place<F: FnMut(Item) -> Item>(&mut self, mut f: F) {
+        // only dropped (instead of forgotten) if `f` panics on the item at `index`
+        struct TruncateOnUnwind<'a, Item: Clone> {
+            vector: &'a mut Vector<Item>,
+            index: usize,
+        }
+
+        impl<'a, Item: Clone> Drop for TruncateOnUnwind<'a, Item> {
+            fn drop(&mut self) {
+                let len = self.vector.len();
+                unsafe { self.vector.drop_range(self.index + 1, len) };
+                self.vector.discard_from(self.index);
+            }
+        }
+
+        let runs = self.arena.chunk_runs().collect::<Vec<_>>();
+        let mut guard = TruncateOnUnwind { vector: self, index: 0 };
+
+        for (chunk_ptr, n_items) in runs {
+            let chunk_ptr = chunk_ptr as *mut Item;
+            for offset in 0..n_items {
+                unsafe {
+                    let item_ptr = chunk_ptr.add(offset);
+                    let old_item = ::core::ptr::read(item_ptr);
+                    ::core::ptr::write(item_ptr, f(old_item));
+                }
+                guard.index += 1;
+            }
+        }
+
+        ::core::mem::forget(guard);
+    }
+
+    /// Shrink the vector to `new_len` items without dropping the discarded ones
+    fn discard_from(&mut self, new_len: usize) {
+        while self.len() > new_len {
+            self.arena.pop_away();
+        }
+    }
+
+    /// Drop the items `[start, end)` in place, one chunk run at a time, without removing their slots.
+    ///
+    /// Unsafe because the dropped items must not be used (or dropped) again,
+    /// so their slots have to be discarded or overwritten afterwards.
+    unsafe fn drop_range(&mut self, start: usize, end: usize) {
+        if !::core::mem::needs_drop::<Item>() {
+            return;
+        }
+
+        let items_per_chunk = self.arena.items_per_chunk();
+        let mut run_start = start;
+
+        while run_start < end {
+            let run_end = ::core::cmp::min(end, (run_start / items_per_chunk + 1) * items_per_chunk);
+            let run_ptr = self.arena.at(ArenaIndex(run_start)) as *mut Item;
+            ::core::ptr::drop_in_place(::core::ptr::slice_from_raw_parts_mut(run_ptr, run_end - run_start));
+            run_start = run_end;
+        }
+    }
+}
+
+/// Dropping a vector drops all its items, but keeps its chunks' persisted representation (if any)
+impl<Item: Clone> Drop for Vector<Item> {
+    fn drop(&mut self) {
+        let len = self.len();
+        unsafe { self.drop_range(0, len) };
+    }
+}
+
+/// Consuming a vector yields its items in order, forgetting its chunks (deleting any persisted
+/// representation of them) once all of their items were yielded, see `IntoIter`
+impl<Item: Clone> IntoIterator for Vector<Item> {
+    type Item = Item;
+    type IntoIter = IntoIter<Item>;
+
+    fn into_iter(self) -> IntoIter<Item> {
+        let vector = ::core::mem::ManuallyDrop::new(self);
+        let arena = unsafe { ::core::ptr::read(&vector.arena) };
+        let (remaining, items_per_chunk) = (arena.len(), arena.items_per_chunk());
+        let (chunks, storage) = arena.into_chunks();
+        IntoIter {
+            chunks: chunks.into(),
+            items_per_chunk,
+            remaining,
+            offset: 0,
+            storage,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// An iterator moving the items out of a `Vector`, see `IntoIterator for Vector`.
+///
+/// Dropping it before it is exhausted drops the remaining items,
+/// and forgets the remaining chunks of the vector as well.
+pub struct IntoIter<Item: Clone> {
+    /// Chunks that still hold items, followed by the chunk of the vector's length
+    chunks: VecDeque<Chunk>,
+    items_per_chunk: usize,
+    /// Number of items left, the first of which is at `offset` in the first chunk
+    remaining: usize,
+    offset: usize,
+    storage: Rc<dyn ChunkStorage>,
+    _marker: PhantomData<Item>,
+}
+
+impl<Item: Clone> Iterator for IntoIter<Item> {
+    type Item = Item;
+
+    fn next(&mut self) -> Option<Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        if self.offset == self.items_per_chunk {
+            let exhausted = self.chunks.pop_front().expect("should have chunk left");
+            self.storage.forget_chunk(exhausted);
+            self.offset = 0;
+        }
+
+        let item = unsafe { ::core::ptr::read((self.chunks[0].as_ptr() as *const Item).add(self.offset)) };
+        self.offset += 1;
+        self.remaining -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<Item: Clone> Drop for IntoIter<Item> {
+    fn drop(&mut self) {
+        // items are moved out before being dropped, so if dropping one panics,
+        // the ones after it leak instead of being dropped twice
+        for item in &mut *self {
+            ::core::mem::drop(item);
+        }
+        self.storage.forget_chunks(self.chunks.drain(..).collect());
+    }
+}
+
+/// A view of a sub-range of the items of a `Vector`, see `Vector::slice`
+pub struct VectorSlice<'a, Item: Clone> {
+    vector: &'a Vector<Item>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a, Item: Clone> VectorSlice<'a, Item> {
+    /// Number of items in the view
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Is the view empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get a reference to the item at `index`, relative to the start of the view
+    pub fn at(&self, index: usize) -> Option<&'a Item> {
+        if index < self.len() {
+            self.vector.at(self.start + index)
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over references to all items of the view in order
+    pub fn iter(&self) -> impl Iterator<Item = &'a Item> + 'a {
+        let mut cursor = self.vector.cursor();
+        cursor.seek(self.start);
+        cursor.take(self.len())
+    }
+
+    /// Get a view of the items in `range` of this view, relative to its start.
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, range: ::core::ops::Range<usize>) -> VectorSlice<'a, Item> {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "Slice range {}..{} out of bounds (len {})", range.start, range.end, self.len()
+        );
+        VectorSlice { vector: self.vector, start: self.start + range.start, end: self.start + range.end }
+    }
+}
+
+/// A cursor for reading the items of a `Vector` in order, which can be repositioned with `seek`.
+///
+/// It remembers its position within the current chunk,
+/// so advancing to the next item is just a pointer increment.
+pub struct Cursor<'a, Item: Clone> {
+    vector: &'a Vector<Item>,
+    index: usize,
+    item_ptr: *const Item,
+    left_in_chunk: usize,
+}
+
+impl<'a, Item: Clone> Cursor<'a, Item> {
+    /// Move the cursor so the next item read is the one at `index`
+    pub fn seek(&mut self, index: usize) {
+        self.index = index;
+        self.left_in_chunk = 0;
+    }
+
+    /// Number of items left to read
+    pub fn remaining(&self) -> usize {
+        self.vector.len().saturating_sub(self.index)
+    }
+}
+
+impl<'a, Item: Clone> Iterator for Cursor<'a, Item> {
+    type Item = &'a Item;
+
+    fn next(&mut self) -> Option<&'a Item> {
+        if self.index >= self.vector.len() {
+            return None;
+        }
+
+        if self.left_in_chunk == 0 {
+            let items_per_chunk = self.vector.arena.items_per_chunk();
+            self.item_ptr = unsafe { self.vector.arena.at(ArenaIndex(self.index)) as *const Item };
+            self.left_in_chunk = items_per_chunk - self.index % items_per_chunk;
+        }
+
+        let item = unsafe { &*self.item_ptr };
+        self.item_ptr = self.item_ptr.wrapping_add(1);
+        self.left_in_chunk -= 1;
+        self.index += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining(), Some(self.remaining()))
+    }
+}
+
+/// An iterator over the items of a `Vector` and their indices, see `Vector::iter_enumerate_indices`
+pub struct EnumerateIndices<'a, Item: Clone> {
+    cursor: Cursor<'a, Item>,
+}
+
+impl<'a, Item: Clone> Iterator for EnumerateIndices<'a, Item> {
+    type Item = (usize, &'a Item);
+
+    fn next(&mut self) -> Option<(usize, &'a Item)> {
+        let index = self.cursor.index;
+        self.cursor.next().map(|item| (index, item))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<(usize, &'a Item)> {
+        let index = self.cursor.index.saturating_add(n);
+        self.cursor.seek(index);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.cursor.size_hint()
+    }
+}
+
+/// A state of a `Vector` that it can be returned to with `Vector::rollback`, see `Vector::checkpoint`
+pub struct Checkpoint<Item: Clone> {
+    len: usize,
+    saved_from: usize,
+    saved: Vec<Item>,
+}
+
+#[cfg(feature = "rayon")]
+impl<Item: Clone + Sync> Vector<Item> {
+    /// Iterate over references to all items in parallel, see `IntoParallelIterator for &Vector`
+    pub fn par_iter(&self) -> <&Self as rayon::iter::IntoParallelIterator>::Iter {
+        rayon::iter::IntoParallelIterator::into_par_iter(self)
+    }
+
+    fn chunk_slices(&self) -> Vec<&[Item]> {
+        self.arena.chunk_runs()
+            .map(|(chunk_ptr, n_items)| unsafe { ::core::slice::from_raw_parts(chunk_ptr as *const Item, n_items) })
+            .collect()
+    }
+}
+
+/// Work is split along chunk boundaries, so the items of each chunk are visited by a single thread
+#[cfg(feature = "rayon")]
+impl<'a, Item: Clone + Sync> rayon::iter::IntoParallelIterator for &'a Vector<Item> {
+    type Iter = rayon::iter::FlatMapIter<rayon::vec::IntoIter<&'a [Item]>, fn(&'a [Item]) -> ::core::slice::Iter<'a, Item>>;
+    type Item = &'a Item;
+
+    fn into_par_iter(self) -> Self::Iter {
+        use rayon::iter::ParallelIterator;
+        self.chunk_slices().into_par_iter().flat_map_iter(<[Item]>::iter as fn(&'a [Item]) -> ::core::slice::Iter<'a, Item>)
+    }
+}
+
+impl<Item: Clone + PartialEq> PartialEq for Vector<Item> {
+    fn eq(&self, other: &Vector<Item>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<Item: Clone> crate::Flushable for Vector<Item> {
+    fn flush(&self) {
+        self.arena.flush();
+    }
+}
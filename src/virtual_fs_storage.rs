@@ -0,0 +1,208 @@
+use crate::{Chunk, ChunkKind, ChunkStorage, Ident};
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// Alignment of all chunks handed out, matching what the system allocator guarantees
+const CHUNK_ALIGN: usize = 16;
+
+/// The zeroed heap allocation holding the contents of a "file"
+struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+    layout: Layout,
+}
+
+impl Buffer {
+    fn new(len: usize) -> Buffer {
+        // zero-sized allocations aren't allowed, so allocate at least one byte
+        let layout = Layout::from_size_align(::std::cmp::max(len, 1), CHUNK_ALIGN).expect("Invalid chunk size");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        Buffer { ptr, len, layout }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Buffer {
+        let buffer = Buffer::new(bytes.len());
+        unsafe { ::std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.ptr, buffer.len) };
+        buffer
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+/// A `ChunkStorage` that persists chunks as "files" in an in-memory virtual file system,
+/// for platforms without a file system or mmap (such as WASM).
+///
+/// Unlike `HeapStorage`, chunks outlive their `Chunk` and can be loaded again
+/// (a forgotten file's contents live on until all chunks loaded from it are dropped),
+/// and the whole file system can be exported to bytes (to be stored by the host)
+/// and imported again later.
+pub struct VirtualFsStorage {
+    files: RefCell<HashMap<String, Rc<Buffer>>>,
+}
+
+/// Returned when importing bytes that weren't produced by `VirtualFsStorage::export`
+#[derive(Debug)]
+pub struct InvalidExportError;
+
+impl ::std::fmt::Display for InvalidExportError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "Invalid virtual file system export")
+    }
+}
+
+impl ::std::error::Error for InvalidExportError {}
+
+/// Keeps the buffer alive for as long as the chunk, even if it is forgotten through another chunk
+struct VirtualFsHandle(Rc<Buffer>);
+
+impl VirtualFsStorage {
+    /// Create a new, empty `VirtualFsStorage`
+    pub fn new() -> VirtualFsStorage {
+        VirtualFsStorage {
+            files: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Create a `VirtualFsStorage` with the files of a previous `export`
+    pub fn import(mut bytes: &[u8]) -> Result<VirtualFsStorage, InvalidExportError> {
+        fn read_len(bytes: &mut &[u8]) -> Result<usize, InvalidExportError> {
+            let len_bytes = read_bytes(bytes, 8)?;
+            let mut buffer = [0u8; 8];
+            buffer.copy_from_slice(len_bytes);
+            usize::try_from(u64::from_le_bytes(buffer)).map_err(|_| InvalidExportError)
+        }
+
+        fn read_bytes<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], InvalidExportError> {
+            if bytes.len() < len {
+                return Err(InvalidExportError);
+            }
+            let (read, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Ok(read)
+        }
+
+        let n_files = read_len(&mut bytes)?;
+        let mut files = HashMap::new();
+
+        for _ in 0..n_files {
+            let name_len = read_len(&mut bytes)?;
+            let name = ::std::str::from_utf8(read_bytes(&mut bytes, name_len)?).map_err(|_| InvalidExportError)?;
+            let contents_len = read_len(&mut bytes)?;
+            let contents = read_bytes(&mut bytes, contents_len)?;
+            files.insert(name.to_owned(), Rc::new(Buffer::from_bytes(contents)));
+        }
+
+        if !bytes.is_empty() {
+            return Err(InvalidExportError);
+        }
+
+        Ok(VirtualFsStorage {
+            files: RefCell::new(files),
+        })
+    }
+
+    /// Serialize all files, including the current contents of live chunks, to bytes
+    pub fn export(&self) -> Vec<u8> {
+        let files = self.files.borrow();
+        let mut names = files.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(names.len() as u64).to_le_bytes());
+        for name in names {
+            let contents = files[name].bytes();
+            bytes.extend_from_slice(&(name.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(contents);
+        }
+        bytes
+    }
+
+    fn chunk(buffer: Rc<Buffer>) -> Chunk {
+        let (ptr, len, capacity) = (buffer.ptr, buffer.len, buffer.layout.size());
+        // the handle keeps the buffer alive, even if the storage is dropped first
+        unsafe { Chunk::from_raw_parts_with_capacity(ptr, len, capacity, ChunkKind::Persistent, Box::new(VirtualFsHandle(buffer))) }
+    }
+}
+
+impl Default for VirtualFsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkStorage for VirtualFsStorage {
+    fn create_chunk(&self, ident: Ident, size: usize) -> Chunk {
+        assert!(!self.chunk_exists(&ident), "Virtual file {} already exists", ident.0);
+        let buffer = Rc::new(Buffer::new(size));
+        self.files.borrow_mut().insert(ident.0, Rc::clone(&buffer));
+        Self::chunk(buffer)
+    }
+
+    fn load_or_create_chunk(&self, ident: Ident, size: usize) -> (Chunk, bool) {
+        let created_new = !self.chunk_exists(&ident);
+        if created_new {
+            (self.create_chunk(ident, size), true)
+        } else {
+            (self.load_chunk(ident), false)
+        }
+    }
+
+    fn load_chunk(&self, ident: Ident) -> Chunk {
+        let buffer = self.files.borrow().get(&ident.0).cloned()
+            .unwrap_or_else(|| panic!("No virtual file {}", ident.0));
+        Self::chunk(buffer)
+    }
+
+    fn forget_chunk(&self, chunk: Chunk) {
+        match chunk._handle_to_drop.downcast::<VirtualFsHandle>() {
+            Ok(handle) => self.files.borrow_mut().retain(|_, buffer| !Rc::ptr_eq(buffer, &handle.0)),
+            Err(_) => crate::warn_foreign_chunk("VirtualFsStorage"),
+        }
+    }
+
+    fn try_forget_chunk(&self, chunk: Chunk) -> ::std::io::Result<()> {
+        if !chunk._handle_to_drop.is::<VirtualFsHandle>() {
+            return Err(crate::foreign_chunk_error("VirtualFsStorage"));
+        }
+        self.forget_chunk(chunk);
+        Ok(())
+    }
+
+    fn chunk_exists(&self, ident: &Ident) -> bool {
+        self.files.borrow().contains_key(&ident.0)
+    }
+
+    fn chunk_len(&self, ident: &Ident) -> Option<usize> {
+        self.files.borrow().get(&ident.0).map(|buffer| buffer.len)
+    }
+
+    fn list_chunks(&self, group: &Ident) -> Vec<Ident> {
+        self.files.borrow().keys()
+            .map(|name| Ident(name.clone()))
+            .filter(|ident| ident.belongs_to(group))
+            .collect()
+    }
+
+    fn chunk_checksum(&self, ident: &Ident) -> u64 {
+        let files = self.files.borrow();
+        let buffer = files.get(&ident.0)
+            .unwrap_or_else(|| panic!("No virtual file {}", ident.0));
+        crate::checksum(buffer.bytes())
+    }
+}
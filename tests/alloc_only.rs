@@ -0,0 +1,50 @@
+//! Only uses what the crate provides without its `std` feature, to check that the heap
+//! collections work in `no_std` builds: `cargo test --no-default-features --test alloc_only`
+
+use chunky::*;
+use std::rc::Rc;
+
+fn heap() -> Rc<dyn ChunkStorage> {
+    Rc::new(HeapStorage::new())
+}
+
+#[test]
+fn values_and_arenas_work_without_std() {
+    let storage = heap();
+    let mut value = Value::load_or_default(Ident::from("v"), 5u32, Rc::clone(&storage));
+    value.modify(|value| *value += 1);
+    assert_eq!(*value, 6);
+
+    let mut arena = Arena::new(Ident::from("a"), 64, 8, storage);
+    for item in 0..20u64 {
+        unsafe { *(arena.push().0 as *mut u64) = item };
+    }
+    assert_eq!(unsafe { *(arena.at(ArenaIndex(13)) as *const u64) }, 13);
+}
+
+#[test]
+fn vectors_and_multi_arenas_work_without_std() {
+    let storage = heap();
+    let mut vector = Vector::new(Ident::from("v"), 64, Rc::clone(&storage));
+    for item in 0..20u64 {
+        vector.push(item);
+    }
+    vector.truncate(10);
+    assert_eq!(vector.iter().sum::<u64>(), 45);
+
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, storage);
+    let handle = arena.push_sized(20);
+    arena.write(handle, &[7; 20]);
+    assert_eq!(arena.read(handle), &[7; 20][..]);
+}
+
+#[test]
+fn queues_work_without_std() {
+    let mut queue = Queue::new(&Ident::from("q"), 64, heap());
+    for item in 0..20u64 {
+        unsafe { *(queue.enqueue(8) as *mut u64) = item };
+    }
+    for item in 0..20u64 {
+        assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u64) }, item);
+    }
+}
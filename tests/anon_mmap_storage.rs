@@ -0,0 +1,36 @@
+#![cfg(feature = "mmap")]
+
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn anonymous_chunks_are_page_aligned_and_released() {
+    let anon = Rc::new(AnonMmapStorage::new());
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&anon) as Rc<dyn ChunkStorage>;
+    let mut chunk = storage.create_chunk(Ident::from("x"), 100_000);
+    assert!((chunk.as_ptr() as usize).is_multiple_of(storage.page_size()));
+    assert!(!chunk.is_persistent());
+    assert_eq!(storage.chunk_len(&Ident::from("x")), Some(100_000));
+
+    chunk[0] = 3;
+    chunk[99_999] = 5;
+    anon.discard(&mut chunk);
+    if cfg!(target_os = "linux") {
+        assert_eq!((chunk[0], chunk[99_999]), (0, 0));
+    }
+
+    storage.forget_chunk(chunk);
+    assert!(!storage.chunk_exists(&Ident::from("x")));
+}
+
+#[test]
+fn collections_work_on_anonymous_chunks() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(AnonMmapStorage::new());
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 4096, Rc::clone(&storage));
+    for item in 0..10_000 {
+        vector.push(item);
+    }
+    assert!(vector.iter().copied().eq(0..10_000));
+    // 20 chunks of 512 items, plus the length
+    assert_eq!(storage.list_chunks(&Ident::from("v")).len(), 21);
+}
@@ -0,0 +1,293 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[cfg(feature = "mmap")]
+#[test]
+fn try_new_reports_missing_chunks() {
+    let dir = common::temp_dir("try_new_reports_missing_chunks");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+    {
+        let mut arena = Arena::new(Ident::from("a"), 16, 8, Rc::clone(&storage));
+        for _ in 0..6 {
+            arena.push();
+        }
+    }
+    std::fs::remove_file(dir.join("a_2")).unwrap();
+
+    let error = Arena::try_new(Ident::from("a"), 16, 8, storage).err().expect("should be missing a chunk");
+    assert_eq!(error.missing, vec![Ident::from("a_2")]);
+}
+
+#[test]
+fn retain_swap_keeps_exactly_the_matching_items() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 32, 8, storage);
+    for item in 0..21u64 {
+        unsafe { *(arena.push().0 as *mut u64) = item };
+    }
+    unsafe { arena.retain_swap(|item| (*(item as *const u64)).is_multiple_of(2)) };
+
+    let mut survivors = (0..arena.len())
+        .map(|index| unsafe { *(arena.at(ArenaIndex(index)) as *const u64) })
+        .collect::<Vec<_>>();
+    survivors.sort();
+    assert_eq!(survivors, (0..21).filter(|item: &u64| item.is_multiple_of(2)).collect::<Vec<_>>());
+}
+
+fn persisted_arena(storage: &Rc<dyn ChunkStorage>) {
+    let mut arena = Arena::new(Ident::from("a"), 64, 8, Rc::clone(storage));
+    for _ in 0..20 {
+        arena.push();
+    }
+    arena.reserve(30);
+}
+
+#[test]
+fn verify_reports_reserved_chunks_as_extra() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    persisted_arena(&storage);
+
+    let report = Arena::verify(&Ident::from("a"), 64, 8, &*storage);
+    assert!(report.is_consistent());
+    assert_eq!(report.len, 20);
+    assert_eq!(report.extra, ["a_24", "a_32", "a_40", "a_48"].iter().map(|&ident| Ident::from(ident)).collect::<Vec<_>>());
+}
+
+#[test]
+fn verify_reports_missing_and_mis_sized_chunks() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    persisted_arena(&storage);
+    storage.forget_chunk(storage.load_chunk(Ident::from("a_8")));
+    storage.forget_chunk(storage.load_chunk(Ident::from("a_16")));
+    drop(storage.create_chunk(Ident::from("a_16"), 128));
+
+    let report = Arena::verify(&Ident::from("a"), 64, 8, &*storage);
+    assert!(!report.is_consistent());
+    assert_eq!(report.missing, vec![Ident::from("a_8")]);
+    assert_eq!(report.mis_sized, vec![(Ident::from("a_16"), 128)]);
+}
+
+#[test]
+fn verify_doesnt_create_anything() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let report = Arena::verify(&Ident::from("none"), 64, 8, &*storage);
+
+    assert!(report.is_consistent());
+    assert_eq!(report.len, 0);
+    assert!(!storage.chunk_exists(&Ident::from("none_len")));
+}
+
+#[test]
+fn verify_finds_nothing_persisted_on_transient_storages() {
+    let heap: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let bump: Rc<dyn ChunkStorage> = Rc::new(BumpHeapStorage::new(1 << 12));
+    for storage in &[heap, bump] {
+        let mut arena = Arena::new(Ident::from("a"), 64, 8, Rc::clone(storage));
+        for _ in 0..10 {
+            arena.push();
+        }
+
+        let report = Arena::verify(&Ident::from("a"), 64, 8, &**storage);
+        assert_eq!(report.len, 0);
+        assert!(report.is_consistent());
+        assert_eq!(report.extra, vec![Ident::from("a_0"), Ident::from("a_8")]);
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn verify_finds_nothing_persisted_on_anonymous_mmaps() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(AnonMmapStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 64, 8, Rc::clone(&storage));
+    arena.push();
+
+    let report = Arena::verify(&Ident::from("a"), 64, 8, &*storage);
+    assert_eq!((report.len, report.extra), (0, vec![Ident::from("a_0")]));
+}
+
+#[test]
+fn iter_mut_ptrs_writes_through_to_every_item() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 40, 8, storage);
+    for item in 0..23u64 {
+        unsafe { *(arena.push().0 as *mut u64) = item };
+    }
+    assert_eq!(arena.iter_mut_ptrs().count(), 23);
+
+    for ptr in arena.iter_mut_ptrs() {
+        unsafe { *(ptr as *mut u64) *= 10 };
+    }
+    for index in 0..23 {
+        assert_eq!(unsafe { *(arena.at(ArenaIndex(index)) as *const u64) }, index as u64 * 10);
+    }
+}
+
+#[test]
+fn drain_filter_extracts_exactly_the_matching_items() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 64, 8, storage);
+    for item in 0..100u64 {
+        unsafe { *(arena.push().0 as *mut u64) = item };
+    }
+
+    let removed = unsafe { arena.drain_filter(|item| (*(item as *const u64)).is_multiple_of(3)) }
+        .iter()
+        .map(|bytes| u64::from_ne_bytes(std::convert::TryInto::try_into(&bytes[..]).unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(removed, (0..100).filter(|item: &u64| item.is_multiple_of(3)).collect::<Vec<_>>());
+
+    let mut rest = arena.iter_ptrs().map(|item| unsafe { *(item as *const u64) }).collect::<Vec<_>>();
+    rest.sort();
+    assert_eq!(rest, (0..100).filter(|item: &u64| !item.is_multiple_of(3)).collect::<Vec<_>>());
+}
+
+#[test]
+fn set_len_adopts_chunks_written_by_other_means() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 32, 8, Rc::clone(&storage));
+    for chunk_index in 0..3usize {
+        let mut chunk = storage.create_chunk(Ident::from("a").sub(chunk_index * 4), 32);
+        for offset in 0..4 {
+            let item = (chunk_index * 4 + offset) as u64;
+            chunk[offset * 8..(offset + 1) * 8].copy_from_slice(&item.to_ne_bytes());
+        }
+    }
+
+    unsafe { arena.set_len(11) };
+    for index in 0..11 {
+        assert_eq!(unsafe { *(arena.at(ArenaIndex(index)) as *const u64) }, index as u64);
+    }
+    let past_the_chunks = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { arena.set_len(13) }));
+    assert!(past_the_chunks.is_err());
+
+    drop(arena);
+    assert_eq!(Arena::new(Ident::from("a"), 32, 8, storage).len(), 11);
+}
+
+#[test]
+fn lazy_arenas_only_load_chunks_when_accessed() {
+    let storage = Rc::new(Logging::new(VirtualFsStorage::new()));
+    {
+        let mut arena = Arena::new(Ident::from("a"), 64, 8, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+        for item in 0..100u64 {
+            unsafe { *(arena.push().0 as *mut u64) = item };
+        }
+    }
+    storage.take_log();
+    let loads = || storage.take_log().into_iter().filter(|event| matches!(event, StorageEvent::Load { .. })).count();
+
+    let mut arena = Arena::new_lazy(Ident::from("a"), 64, 8, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+    assert_eq!(loads(), 0);
+    assert_eq!(unsafe { *(arena.at(ArenaIndex(17)) as *const u64) }, 17);
+    assert_eq!(unsafe { *(arena.at(ArenaIndex(16)) as *const u64) }, 16);
+    assert_eq!(loads(), 1);
+
+    let sum = arena.iter_ptrs().map(|item| unsafe { *(item as *const u64) }).sum::<u64>();
+    assert_eq!(sum, 4950);
+    assert_eq!(loads(), 12);
+    unsafe { arena.swap_remove(ArenaIndex(0)) };
+    assert_eq!(arena.len(), 99);
+}
+
+#[test]
+fn truncate_forgets_chunks_past_the_new_len() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let chunks = || {
+        let mut chunks = storage.list_chunks(&Ident::from("a"));
+        chunks.sort_by(|a, b| a.0.cmp(&b.0));
+        chunks
+    };
+    let mut arena = Arena::new(Ident::from("a"), 32, 8, Rc::clone(&storage));
+    for _ in 0..20 {
+        arena.push();
+    }
+    assert_eq!(chunks().len(), 5 + 1);
+
+    arena.truncate(30);
+    assert_eq!(arena.len(), 20);
+    arena.truncate(9);
+    assert_eq!(arena.len(), 9);
+    assert_eq!(chunks(), ["a_0", "a_4", "a_8", "a_len"].iter().map(|&ident| Ident::from(ident)).collect::<Vec<_>>());
+    arena.truncate(8);
+    assert_eq!(chunks().len(), 2 + 1);
+    arena.truncate(0);
+    assert_eq!(chunks(), vec![Ident::from("a_len")]);
+
+    arena.push();
+    assert_eq!(arena.len(), 1);
+}
+
+#[test]
+fn droppers_run_once_for_each_removed_item() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // each item holds how many drops it counts for
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    unsafe fn count_drops(item: *mut u8) {
+        DROPS.fetch_add(*(item as *const u64) as usize, Ordering::SeqCst);
+    }
+    let push_items = |arena: &mut Arena, n: usize, drops: u64| {
+        for _ in 0..n {
+            unsafe { *(arena.push().0 as *mut u64) = drops };
+        }
+    };
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = Arena::new_with_dropper(Ident::from("a"), 32, 8, storage, Some(count_drops));
+    push_items(&mut arena, 20, 1);
+    arena.pop_away();
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    unsafe { arena.swap_remove(ArenaIndex(3)) };
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+    unsafe { arena.retain_swap(|_| false) };
+    assert_eq!(DROPS.load(Ordering::SeqCst), 20);
+
+    // drained items are moved out rather than dropped
+    push_items(&mut arena, 10, 100);
+    assert_eq!(unsafe { arena.drain_filter(|_| true) }.len(), 10);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 20);
+
+    push_items(&mut arena, 10, 1);
+    arena.truncate(4);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 26);
+    arena.clear();
+    assert_eq!((arena.len(), DROPS.load(Ordering::SeqCst)), (0, 30));
+}
+
+#[test]
+fn indices_are_valid_up_to_but_excluding_len() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 64, 8, storage);
+    assert!(!arena.contains(ArenaIndex(0)));
+    assert!(arena.get_ptr(ArenaIndex(0)).is_none());
+    for _ in 0..8 {
+        arena.push();
+    }
+
+    assert!(arena.contains(ArenaIndex(7)));
+    assert_eq!(arena.get_ptr(ArenaIndex(7)), Some(unsafe { arena.at(ArenaIndex(7)) }));
+    assert!(!arena.contains(ArenaIndex(8)));
+    assert!(arena.get_ptr(ArenaIndex(8)).is_none());
+    assert!(arena.get_mut_ptr(ArenaIndex(8)).is_none());
+}
+
+#[test]
+fn extend_from_ptr_copies_runs_across_chunks() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = Arena::new(Ident::from("a"), 32, 8, Rc::clone(&storage));
+    unsafe { *(arena.push().0 as *mut u64) = 100 };
+
+    let imported = (0..11u64).collect::<Vec<_>>();
+    unsafe { arena.extend_from_ptr(imported.as_ptr() as *const u8, imported.len()) };
+    assert_eq!(arena.len(), 12);
+    assert_eq!(storage.list_chunks(&Ident::from("a")).len(), 3 + 1);
+    assert_eq!(unsafe { *(arena.at(ArenaIndex(0)) as *const u64) }, 100);
+    for index in 0..11 {
+        assert_eq!(unsafe { *(arena.at(ArenaIndex(index + 1)) as *const u64) }, index as u64);
+    }
+
+    unsafe { arena.extend_from_ptr(imported.as_ptr() as *const u8, 0) };
+    assert_eq!(arena.len(), 12);
+}
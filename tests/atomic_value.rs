@@ -0,0 +1,44 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+#[test]
+fn atomic_values_compare_and_add() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let value = storage.atomic_value(Ident::from("a"), -3i32);
+    assert_eq!(value.fetch_add(1, Ordering::Relaxed), -3);
+    assert_eq!(value.compare_exchange(0, 5, Ordering::SeqCst, Ordering::SeqCst), Err(-2));
+    assert_eq!(value.compare_exchange(-2, 5, Ordering::SeqCst, Ordering::SeqCst), Ok(-2));
+    value.store(7, Ordering::Relaxed);
+    assert_eq!(value.load(Ordering::Relaxed), 7);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn concurrent_fetch_adds_through_shared_mmaps() {
+    let dir = common::temp_dir("concurrent_fetch_adds_through_shared_mmaps");
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+        assert_eq!(storage.atomic_value(Ident::from("counter"), 0u64).load(Ordering::SeqCst), 0);
+    }
+
+    // every thread maps the same file with a storage of its own
+    let threads = (0..8).map(|_| {
+        let dir = dir.clone();
+        std::thread::spawn(move || {
+            let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+            let counter = AtomicValue::load_or_default(Ident::from("counter"), 0u64, storage);
+            for _ in 0..10_000 {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    assert_eq!(storage.atomic_value(Ident::from("counter"), 0u64).load(Ordering::SeqCst), 80_000);
+}
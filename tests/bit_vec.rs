@@ -0,0 +1,57 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn bits_are_set_across_chunk_boundaries() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    // 4 byte chunks hold 32 bits each
+    let mut bits = BitVec::new(Ident::from("b"), 4, storage);
+    bits.resize(100);
+    for index in (0..100).step_by(3) {
+        bits.set(index);
+    }
+    bits.clear(33);
+
+    assert_eq!(bits.len(), 100);
+    for index in 0..100 {
+        assert_eq!(bits.get(index), index % 3 == 0 && index != 33, "bit {}", index);
+    }
+}
+
+#[test]
+fn shrinking_clears_the_removed_bits() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut bits = BitVec::new(Ident::from("b"), 4, storage);
+    bits.resize(100);
+    for index in 0..100 {
+        bits.set(index);
+    }
+
+    bits.resize(50);
+    bits.resize(64);
+    assert!((0..50).all(|index| bits.get(index)));
+    assert!((50..64).all(|index| !bits.get(index)));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn bits_survive_reloading() {
+    let dir = common::temp_dir("bits_survive_reloading");
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+        let mut bits = BitVec::new(Ident::from("b"), 4, storage);
+        bits.resize(64);
+        for index in (0..64).step_by(3) {
+            bits.set(index);
+        }
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    let bits = BitVec::new(Ident::from("b"), 4, storage);
+    assert_eq!(bits.len(), 64);
+    for index in 0..64 {
+        assert_eq!(bits.get(index), index % 3 == 0, "bit {}", index);
+    }
+}
@@ -0,0 +1,54 @@
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn chunks_are_aligned_and_packed_into_slabs() {
+    let storage = BumpHeapStorage::new(1000);
+    let first = storage.create_chunk(Ident::from("a"), 100);
+    let second = storage.create_chunk(Ident::from("b"), 100);
+
+    assert_eq!(first.as_ptr() as usize % 16, 0);
+    assert_eq!(second.as_ptr() as usize % 16, 0);
+    // 100 bytes are rounded up to the next multiple of the alignment
+    assert_eq!(second.as_ptr() as usize - first.as_ptr() as usize, 112);
+}
+
+#[test]
+fn forgotten_ranges_are_reused() {
+    let storage = BumpHeapStorage::new(1000);
+    let first = storage.create_chunk(Ident::from("a"), 100);
+    let first_ptr = first.as_ptr();
+    storage.forget_chunk(first);
+    assert!(!storage.chunk_exists(&Ident::from("a")));
+
+    let reused = storage.create_chunk(Ident::from("c"), 100);
+    assert_eq!(reused.as_ptr(), first_ptr);
+    assert_eq!(storage.chunk_len(&Ident::from("c")), Some(100));
+}
+
+#[test]
+fn chunks_larger_than_a_slab_get_their_own() {
+    let storage = BumpHeapStorage::new(1000);
+    let mut big = storage.create_chunk(Ident::from("big"), 5000);
+    assert_eq!(big.len(), 5000);
+    big.fill(7);
+    assert!(big.iter().all(|&byte| byte == 7));
+}
+
+#[test]
+fn chunks_outlive_the_storage() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(BumpHeapStorage::new(4096));
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, Rc::clone(&storage));
+    for index in 0..1000 {
+        let (item, _) = arena.push(8 + index % 100);
+        unsafe { *item = index as u8 };
+    }
+    let mut vector = Vector::new(Ident::from("v"), 64, Rc::clone(&storage));
+    for item in 0..1000u64 {
+        vector.push(item);
+    }
+    drop(storage);
+    drop(arena);
+
+    assert_eq!((0..1000).map(|index| *vector.at(index).unwrap()).collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use chunky::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[test]
+fn random_inserts_and_removes_match_hash_map() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut map: ChunkyMap<u64, String> = storage.chunky_map(Ident::from("m"), 256);
+    let mut expected = HashMap::new();
+    let mut rng = 7u64;
+    for _ in 0..20_000 {
+        rng = rng.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let key = (rng >> 40) % 500;
+        if (rng >> 20).is_multiple_of(3) {
+            assert_eq!(map.remove(&key), expected.remove(&key));
+        } else {
+            assert_eq!(map.insert(key, key.to_string()), expected.insert(key, key.to_string()));
+        }
+        assert_eq!(map.len(), expected.len());
+    }
+
+    for key in 0..600u64 {
+        assert_eq!(map.get(&key), expected.get(&key));
+    }
+    assert_eq!(map.iter().count(), expected.len());
+    let some_key = *expected.keys().next().unwrap();
+    *map.get_mut(&some_key).unwrap() = "changed".to_owned();
+    assert_eq!(map.get(&some_key).map(String::as_str), Some("changed"));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn maps_are_reloaded_with_removed_keys_gone() {
+    let dir = common::temp_dir("maps_are_reloaded_with_removed_keys_gone");
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+        let mut map: ChunkyMap<u32, u64> = storage.chunky_map(Ident::from("m"), 4096);
+        for key in 0..5000u32 {
+            map.insert(key, u64::from(key) * 7);
+        }
+        for key in (0..5000u32).step_by(2) {
+            assert_eq!(map.remove(&key), Some(u64::from(key) * 7));
+        }
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    let mut map: ChunkyMap<u32, u64> = storage.chunky_map(Ident::from("m"), 4096);
+    assert_eq!(map.len(), 2500);
+    for key in 0..5000u32 {
+        let expected = if !key.is_multiple_of(2) { Some(u64::from(key) * 7) } else { None };
+        assert_eq!(map.get(&key).copied(), expected);
+    }
+    map.insert(2, 3);
+    assert_eq!(map.get(&2), Some(&3));
+}
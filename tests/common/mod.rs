@@ -0,0 +1,10 @@
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// A fresh, empty directory for the chunk files of a single test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chunky_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
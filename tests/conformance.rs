@@ -0,0 +1,38 @@
+mod common;
+
+use chunky::conformance::{run_storage_tests, CheckResult};
+use chunky::*;
+
+fn assert_all_passed(results: Vec<CheckResult>) {
+    assert!(!results.is_empty());
+    for result in &results {
+        assert!(result.passed, "{:?}", result);
+    }
+}
+
+#[test]
+fn heap_storages_conform() {
+    assert_all_passed(run_storage_tests(HeapStorage::default));
+    assert_all_passed(run_storage_tests(|| BumpHeapStorage::new(1 << 16)));
+    assert_all_passed(run_storage_tests(|| Logging::new(HeapStorage::default())));
+    assert_all_passed(run_storage_tests(|| Dedup::new(HeapStorage::default())));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_storages_conform() {
+    let dir = common::temp_dir("mmap_storages_conform");
+    assert_all_passed(run_storage_tests(|| MmapStorage::new(dir.clone())));
+    // all chunks of the checks are forgotten again
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    assert_all_passed(run_storage_tests(AnonMmapStorage::new));
+    let dir = common::temp_dir("deduplicated_mmap_storages_conform");
+    assert_all_passed(run_storage_tests(|| Dedup::new(MmapStorage::new(dir.clone()))));
+}
+
+#[cfg(feature = "direct_io")]
+#[test]
+fn direct_io_storages_conform() {
+    let dir = common::temp_dir("direct_io_storages_conform");
+    assert_all_passed(run_storage_tests(|| DirectIoStorage::new(dir.clone())));
+}
@@ -0,0 +1,32 @@
+use chunky::*;
+use std::rc::Rc;
+
+fn vector(ident: &str, storage: &Rc<Dedup<VirtualFsStorage>>) -> Vector<u64> {
+    Vector::new(Ident::from(ident), 64, Rc::clone(storage) as Rc<dyn ChunkStorage>)
+}
+
+#[test]
+fn identical_chunks_share_one_blob_until_mutated() {
+    let storage = Rc::new(Dedup::new(VirtualFsStorage::new()));
+    {
+        let mut a = vector("a", &storage);
+        let mut b = vector("b", &storage);
+        for item in 0..8 {
+            a.push(item);
+            b.push(item);
+        }
+    }
+    // one blob for both lens, one for both chunks of items
+    assert_eq!(storage.blob_count(), 2);
+
+    {
+        let a = vector("a", &storage);
+        assert_eq!((a.len(), a.at(5)), (8, Some(&5)));
+        *vector("b", &storage).at_mut(5).unwrap() = 99;
+    }
+    assert_eq!(storage.blob_count(), 3);
+    assert_eq!(vector("a", &storage).at(5), Some(&5));
+
+    storage.forget_chunk(storage.load_chunk(Ident::from("b").sub(0)));
+    assert_eq!(storage.blob_count(), 2);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use chunky::*;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+unsafe fn read_u32(ptr: *const u8) -> u32 {
+    (ptr as *const u32).read()
+}
+
+#[test]
+fn interleaved_operations_at_both_ends_match_vec_deque() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut deque = storage.deque(Ident::from("d"), 16, 4);
+    let mut expected = VecDeque::new();
+    let mut next = 0u32;
+    let mut rng = 12345u64;
+    for step in 0..5000 {
+        rng = rng.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        unsafe {
+            match (rng >> 33) % 5 {
+                0 | 4 => {
+                    (deque.push_back() as *mut u32).write(next);
+                    expected.push_back(next);
+                    next += 1;
+                }
+                1 => {
+                    (deque.push_front() as *mut u32).write(next);
+                    expected.push_front(next);
+                    next += 1;
+                }
+                2 => assert_eq!(deque.pop_front().map(|ptr| read_u32(ptr)), expected.pop_front()),
+                _ => assert_eq!(deque.pop_back().map(|ptr| read_u32(ptr)), expected.pop_back()),
+            }
+            if step % 7 == 0 {
+                deque.drop_old_chunks();
+            }
+        }
+        assert_eq!(deque.len(), expected.len());
+    }
+
+    assert!(deque.iter().map(|ptr| unsafe { read_u32(ptr) }).eq(expected.iter().copied()));
+    assert!(deque.iter().rev().map(|ptr| unsafe { read_u32(ptr) }).eq(expected.iter().rev().copied()));
+}
+
+#[cfg(feature = "mmap")]
+fn push_both_ends_and_pop_some(deque: &mut Deque) {
+    unsafe {
+        for item in 0..20u32 {
+            (deque.push_front() as *mut u32).write(item);
+            (deque.push_back() as *mut u32).write(100 + item);
+        }
+        for _ in 0..10 {
+            deque.pop_front();
+            deque.pop_back();
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn deques_are_persisted() {
+    let dir = common::temp_dir("deques_are_persisted");
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+        let mut deque = storage.deque(Ident::from("d"), 16, 4);
+        push_both_ends_and_pop_some(&mut deque);
+        unsafe { deque.drop_old_chunks() };
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    let deque = storage.deque(Ident::from("d"), 16, 4);
+    let mut expected = (0..10).rev().collect::<Vec<u32>>();
+    expected.extend(100..110);
+    assert_eq!(deque.iter().map(|ptr| unsafe { read_u32(ptr) }).collect::<Vec<_>>(), expected);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn reloaded_deques_can_move_back_over_chunks_not_dropped_yet() {
+    let dir = common::temp_dir("reloaded_deques_can_move_back_over_chunks_not_dropped_yet");
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+        push_both_ends_and_pop_some(&mut storage.deque(Ident::from("d"), 16, 4));
+    }
+    {
+        // as if the process died before anything could be forgotten
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+        let mut deque = storage.deque(Ident::from("d"), 16, 4);
+        push_both_ends_and_pop_some(&mut deque);
+        std::mem::forget(deque);
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    let mut deque = storage.deque(Ident::from("d"), 16, 4);
+    assert_eq!(deque.len(), 40);
+    unsafe {
+        for item in 0..20u32 {
+            (deque.push_front() as *mut u32).write(item);
+            (deque.push_back() as *mut u32).write(item);
+        }
+    }
+    assert_eq!(deque.len(), 80);
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "direct_io")]
+
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+fn chunks_round_trip(block_size: usize, name: &str) {
+    let dir = common::temp_dir(name);
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(DirectIoStorage::with_block_size(dir.clone(), block_size));
+        let mut vector = Vector::<u32>::new(Ident::from("v"), 1000, Rc::clone(&storage));
+        for item in 0..5000u32 {
+            vector.push(item * 3);
+        }
+        let mut odd = storage.create_chunk(Ident::from("odd"), 13);
+        odd.copy_from_slice(b"hello, world!");
+        storage.flush_chunk(&odd);
+        assert_eq!(storage.chunk_len(&Ident::from("odd")), Some(13));
+
+        storage.forget_chunk(storage.create_chunk(Ident::from("gone"), 10));
+        assert!(!storage.chunk_exists(&Ident::from("gone")));
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(DirectIoStorage::with_block_size(dir.clone(), block_size));
+    let vector = Vector::<u32>::new(Ident::from("v"), 1000, Rc::clone(&storage));
+    assert!(vector.iter().copied().eq((0..5000u32).map(|item| item * 3)));
+    assert_eq!(&storage.load_chunk(Ident::from("odd"))[..], b"hello, world!");
+    // the padding up to the block size is truncated away again
+    assert_eq!(std::fs::metadata(dir.join("v_0")).unwrap().len(), 1000);
+}
+
+#[test]
+fn chunks_round_trip_through_direct_io() {
+    chunks_round_trip(4096, "chunks_round_trip_through_direct_io");
+}
+
+#[test]
+fn chunks_round_trip_with_buffered_fallback() {
+    // too small a block size for O_DIRECT, so this falls back to buffered writes
+    chunks_round_trip(16, "chunks_round_trip_with_buffered_fallback");
+}
@@ -0,0 +1,37 @@
+use chunky::*;
+use std::rc::Rc;
+
+fn arena_of(items: &[&str]) -> (GenerationalArena<String>, Vec<GenerationalIndex>) {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = GenerationalArena::new(Ident::from("g"), 64, storage);
+    let indices = items.iter().map(|&item| arena.push(item.to_owned())).collect();
+    (arena, indices)
+}
+
+#[test]
+fn indices_of_removed_and_moved_items_are_rejected() {
+    let (mut arena, indices) = arena_of(&["a", "b", "c"]);
+
+    assert_eq!(arena.swap_remove(indices[0]).unwrap(), "a");
+    assert!(arena.get(indices[0]).is_none());
+    // "c" was moved into the removed slot, so its old index is stale as well
+    assert!(arena.get(indices[2]).is_none());
+    let moved = arena.index_at(indices[0].0).unwrap();
+    assert_eq!(arena.get(moved).unwrap(), "c");
+    assert_eq!(arena.get(indices[1]).unwrap(), "b");
+}
+
+#[test]
+fn indices_of_reused_slots_are_rejected() {
+    let (mut arena, indices) = arena_of(&["a", "b", "c"]);
+    arena.swap_remove(indices[0]);
+
+    let reused = arena.push("d".to_owned());
+    assert_eq!(reused.0, indices[2].0);
+    assert_ne!(reused, indices[2]);
+    assert!(arena.get(indices[2]).is_none());
+    assert_eq!(arena.get(reused).unwrap(), "d");
+
+    assert!(arena.swap_remove(indices[2]).is_none());
+    assert_eq!(arena.len(), 3);
+}
@@ -0,0 +1,93 @@
+use chunky::*;
+
+#[test]
+fn configured_alignment_takes_effect() {
+    let storage = HeapStorage::builder().min_align(4096).build();
+    for size in 100..110 {
+        let chunk = storage.create_chunk(Ident::from(size), size);
+        assert_eq!(chunk.as_ptr() as usize % 4096, 0);
+        assert_eq!(chunk.len(), size);
+    }
+    assert_eq!(storage.page_size(), 4096);
+}
+
+#[test]
+fn default_alignment_is_16() {
+    let storage = HeapStorage::default();
+    for size in 1..10 {
+        assert_eq!(storage.create_chunk(Ident::from(size), size).as_ptr() as usize % 16, 0);
+    }
+}
+
+#[test]
+fn configured_zeroing_also_zeroes_reused_buffers() {
+    let storage = HeapStorage::with_config(HeapStorageConfig { zero_on_create: true, pool_limit: 1, ..HeapStorageConfig::default() });
+    let mut chunk = storage.create_chunk(Ident::from("a"), 100);
+    assert!(chunk.iter().all(|&byte| byte == 0));
+    chunk.fill(0xff);
+    let ptr = chunk.as_ptr();
+    storage.forget_chunk(chunk);
+    assert_eq!(storage.pooled_buffers(), 1);
+
+    let reused = storage.create_chunk(Ident::from("b"), 100);
+    assert_eq!(reused.as_ptr(), ptr);
+    assert!(reused.iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn pooled_buffers_are_only_reused_for_the_same_size() {
+    let storage = HeapStorage::builder().pool_limit(2).build();
+    let chunk = storage.create_chunk(Ident::from("a"), 100);
+    let ptr = chunk.as_ptr();
+    storage.forget_chunk(chunk);
+    assert!(!storage.chunk_exists(&Ident::from("a")));
+    assert_eq!(storage.pooled_buffers(), 1);
+
+    assert_ne!(storage.create_chunk(Ident::from("larger"), 200).as_ptr(), ptr);
+    let reused = storage.create_chunk(Ident::from("b"), 100);
+    assert_eq!(reused.as_ptr(), ptr);
+    assert_eq!(storage.pooled_buffers(), 0);
+}
+
+#[test]
+fn pools_are_limited_per_size() {
+    let storage = HeapStorage::builder().pool_limit(2).build();
+    let chunks = (0..4).map(|index| storage.create_chunk(Ident::from(index), 64)).collect::<Vec<_>>();
+    storage.forget_chunks(chunks);
+    assert_eq!(storage.pooled_buffers(), 2);
+
+    // dropped chunks aren't forgotten, so they aren't pooled
+    drop(storage.create_chunk(Ident::from("dropped"), 32));
+    assert_eq!(storage.pooled_buffers(), 2);
+
+    let unpooled = HeapStorage::new();
+    unpooled.forget_chunk(unpooled.create_chunk(Ident::from("a"), 64));
+    assert_eq!(unpooled.pooled_buffers(), 0);
+}
+
+#[test]
+fn fill_and_zero_set_every_byte_of_a_chunk() {
+    let storage = HeapStorage::new();
+    let mut chunk = storage.create_chunk(Ident::from("c"), 1000);
+    chunk.fill(0xab);
+    assert!(chunk.iter().all(|&byte| byte == 0xab));
+    chunk.zero();
+    assert!(chunk.iter().all(|&byte| byte == 0));
+
+    let mut empty = storage.create_chunk(Ident::from("e"), 0);
+    empty.fill(1);
+    assert!(empty.is_empty());
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn forgotten_chunks_are_poisoned_in_debug_builds() {
+    // pooling hands out the forgotten buffer again, without zeroing it
+    let storage = HeapStorage::builder().pool_limit(1).build();
+    let mut chunk = storage.create_chunk(Ident::from("c"), 64);
+    chunk.fill(1);
+    storage.forget_chunk(chunk);
+
+    let reused = storage.create_chunk(Ident::from("d"), 64);
+    assert!(reused.iter().all(|&byte| byte == FORGOTTEN_CHUNK_POISON));
+}
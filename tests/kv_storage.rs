@@ -0,0 +1,44 @@
+#![cfg(feature = "kv")]
+
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn collections_round_trip_through_the_key_value_store() {
+    let dir = common::temp_dir("collections_round_trip_through_the_key_value_store");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("chunks.redb");
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(KvStorage::new(path.clone()));
+        let mut vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+        for item in 0..100 {
+            vector.push(item);
+        }
+        let mut queue = Queue::new(&Ident::from("q"), 64, storage);
+        for item in 0..20u64 {
+            unsafe { *(queue.enqueue(8) as *mut u64) = item };
+        }
+    }
+
+    let storage = Rc::new(KvStorage::new(path.clone()));
+    let vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+    assert!(vector.iter().copied().eq(0..100));
+    let mut queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+    assert_eq!(queue.len(), 20);
+    assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u64) }, 0);
+
+    vector.forget_all();
+    assert!(storage.list_chunks(&Ident::from("v")).is_empty());
+    storage.flush().unwrap();
+}
+
+#[test]
+fn key_value_storages_conform() {
+    let dir = common::temp_dir("key_value_storages_conform");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("chunks.redb");
+    let results = conformance::run_storage_tests(|| KvStorage::new(path.clone()));
+    assert!(results.iter().all(|result| result.passed), "{:?}", results);
+}
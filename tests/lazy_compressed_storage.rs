@@ -0,0 +1,145 @@
+#![cfg(all(feature = "mmap", target_os = "linux"))]
+
+mod common;
+
+use chunky::*;
+
+/// A deterministic, poorly compressible byte for each offset
+fn pattern(offset: usize) -> u8 {
+    (offset.wrapping_mul(2_654_435_761) >> 13) as u8
+}
+
+fn page_size() -> usize {
+    AnonMmapStorage::new().page_size()
+}
+
+#[test]
+fn random_reads_across_block_boundaries() {
+    let block_size = page_size();
+    let storage = LazyCompressed::new(HeapStorage::new(), block_size, 2);
+    let len = 16 * block_size;
+    let mut chunk = storage.create_chunk(Ident::from("c"), len);
+    for offset in 0..len {
+        chunk[offset] = pattern(offset);
+    }
+    assert_eq!(storage.decompressed_blocks(&chunk), 2);
+
+    // reads jump around and straddle block boundaries, which each decompress at most two blocks
+    let mut offset = 7usize;
+    for _ in 0..200 {
+        offset = (offset * 31 + 17) % (len - 8);
+        let boundary = (offset / block_size + 1) * block_size - 4;
+        for start in [offset, ::std::cmp::min(boundary, len - 8)].iter() {
+            let expected = (*start..start + 8).map(pattern).collect::<Vec<_>>();
+            assert_eq!(&chunk[*start..start + 8], &expected[..]);
+            assert!(storage.decompressed_blocks(&chunk) <= 2);
+        }
+    }
+}
+
+#[test]
+fn threads_fault_in_blocks_of_the_same_chunk_concurrently() {
+    let block_size = page_size();
+    let storage = LazyCompressed::new(HeapStorage::new(), block_size, 2);
+    let len = 32 * block_size;
+    let chunk = storage.create_chunk(Ident::from("c"), len);
+    let address = chunk.as_ptr() as usize;
+
+    // every thread writes and reads back its own interleaved blocks, evicting each other's all the time
+    let threads = (0..4).map(|thread| std::thread::spawn(move || {
+        for round in 0..3 {
+            for block in (thread..32).step_by(4) {
+                let bytes = unsafe { std::slice::from_raw_parts_mut((address + block * block_size) as *mut u8, block_size) };
+                for (offset, byte) in bytes.iter_mut().enumerate().step_by(61) {
+                    *byte = pattern(offset + block + round);
+                }
+            }
+        }
+    })).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert!(storage.decompressed_blocks(&chunk) <= 2);
+    for offset in 0..len {
+        let expected = if (offset % block_size).is_multiple_of(61) { pattern(offset % block_size + offset / block_size + 2) } else { 0 };
+        assert_eq!(chunk[offset], expected);
+    }
+}
+
+#[test]
+fn dropped_chunks_make_room_for_new_ones() {
+    // more chunks than can be loaded at once, but never more than one at a time
+    let storage = LazyCompressed::new(HeapStorage::new(), page_size(), 2);
+    for index in 0..5000 {
+        let mut chunk = storage.create_chunk(Ident::from("c"), 16);
+        chunk[index % 16] = 1;
+        assert_eq!(chunk.iter().sum::<u8>(), 1);
+        storage.forget_chunk(chunk);
+    }
+}
+
+#[test]
+fn contents_survive_flushing_and_reloading() {
+    let block_size = page_size();
+    let storage = LazyCompressed::new(HeapStorage::new(), block_size, 3);
+    let len = 5 * block_size + 100;
+    let mut chunk = storage.create_chunk(Ident::from("c"), len);
+    for offset in (0..len).step_by(3) {
+        chunk[offset] = pattern(offset);
+    }
+    storage.flush_chunk(&chunk);
+
+    let reloaded = storage.load_chunk(Ident::from("c"));
+    assert_eq!(reloaded.len(), len);
+    assert_eq!(storage.decompressed_blocks(&reloaded), 0);
+    for offset in (0..len).rev() {
+        let expected = if offset % 3 == 0 { pattern(offset) } else { 0 };
+        assert_eq!(reloaded[offset], expected);
+    }
+    assert_eq!(storage.chunk_checksum(&Ident::from("c")), heap_checksum(&reloaded));
+}
+
+fn heap_checksum(bytes: &[u8]) -> u64 {
+    let storage = HeapStorage::new();
+    let mut copy = storage.create_chunk(Ident::from("copy"), bytes.len());
+    copy.copy_from_slice(bytes);
+    storage.chunk_checksum(&Ident::from("copy"))
+}
+
+#[test]
+fn compressed_chunks_are_persisted() {
+    let dir = common::temp_dir("compressed_chunks_are_persisted");
+    let block_size = page_size();
+    {
+        let storage = LazyCompressed::new(MmapStorage::new(dir.clone()), block_size, 2);
+        let mut chunk = storage.create_chunk(Ident::from("c"), 8 * block_size);
+        chunk[3 * block_size + 1] = 42;
+    }
+
+    // the mostly zeroed chunk takes up much less space at rest
+    assert!(std::fs::metadata(dir.join("c")).unwrap().len() < block_size as u64);
+
+    let storage = LazyCompressed::new(MmapStorage::new(dir), block_size, 2);
+    let chunk = storage.load_chunk(Ident::from("c"));
+    assert_eq!(chunk[3 * block_size + 1], 42);
+    assert_eq!(chunk.iter().filter(|&&byte| byte != 0).count(), 1);
+}
+
+#[test]
+fn forgotten_chunks_are_gone() {
+    let storage = LazyCompressed::new(HeapStorage::new(), page_size(), 2);
+    let chunk = storage.create_chunk(Ident::from("c"), 10);
+    assert!(storage.chunk_exists(&Ident::from("c")));
+    assert_eq!(storage.chunk_len(&Ident::from("c")), Some(10));
+
+    storage.forget_chunk(chunk);
+    assert!(!storage.chunk_exists(&Ident::from("c")));
+}
+
+#[test]
+fn conforms_to_the_storage_contract() {
+    let dir = common::temp_dir("lazy_compressed_conforms_to_the_storage_contract");
+    let results = conformance::run_storage_tests(|| LazyCompressed::new(MmapStorage::new(dir.clone()), page_size(), 2));
+    assert!(results.iter().all(|result| result.passed), "{:?}", results);
+}
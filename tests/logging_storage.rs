@@ -0,0 +1,42 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[cfg(feature = "mmap")]
+#[test]
+fn records_arena_build_and_reload() {
+    let storage = Rc::new(Logging::new(MmapStorage::new(common::temp_dir("records_arena_build_and_reload"))));
+    {
+        let mut arena = Arena::new(Ident::from("a"), 16, 8, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+        arena.push();
+        arena.push();
+        arena.push();
+        arena.pop_away();
+    }
+    assert_eq!(storage.take_log(), vec![
+        StorageEvent::LoadOrCreate { ident: Ident::from("a_len"), size: 8, created_new: true },
+        StorageEvent::Create { ident: Ident::from("a_0"), size: 16 },
+        StorageEvent::Create { ident: Ident::from("a_2"), size: 16 },
+        StorageEvent::Forget { ident: Ident::from("a_2") },
+    ]);
+
+    let _reloaded = Arena::new(Ident::from("a"), 16, 8, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+    assert_eq!(storage.take_log(), vec![
+        StorageEvent::LoadOrCreate { ident: Ident::from("a_len"), size: 8, created_new: false },
+        StorageEvent::Load { ident: Ident::from("a_0") },
+    ]);
+}
+
+#[test]
+fn take_log_clears_the_log() {
+    let storage = Logging::new(HeapStorage::new());
+    let chunk = storage.create_chunk(Ident::from("c"), 4);
+    storage.forget_chunk(chunk);
+
+    assert_eq!(storage.take_log(), vec![
+        StorageEvent::Create { ident: Ident::from("c"), size: 4 },
+        StorageEvent::Forget { ident: Ident::from("c") },
+    ]);
+    assert!(storage.take_log().is_empty());
+}
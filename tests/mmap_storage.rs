@@ -0,0 +1,237 @@
+#![cfg(feature = "mmap")]
+
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+fn vector_round_trips(name: &str, options: MmapOptions) {
+    let dir = common::temp_dir(name);
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::with_options(dir.clone(), options));
+        let mut vector = Vector::<u64>::new(Ident::from("v"), 64, storage);
+        for item in 0..100 {
+            vector.push(item);
+        }
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::with_options(dir.clone(), options));
+    let vector = Vector::<u64>::new(Ident::from("v"), 64, storage);
+    assert_eq!(vector.iter().cloned().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+    assert_eq!(std::fs::metadata(dir.join("v_0")).unwrap().len(), 64);
+}
+
+#[test]
+fn advice_and_sparseness_dont_change_contents() {
+    for &sparse in &[true, false] {
+        for &advice in &[MmapAdvice::Normal, MmapAdvice::Sequential, MmapAdvice::Random, MmapAdvice::WillNeed] {
+            let name = format!("advice_and_sparseness_{:?}_{}", advice, sparse);
+            vector_round_trips(&name, MmapOptions { advice, sparse, ..MmapOptions::default() });
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn preallocated_chunks_take_up_disk_space() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = common::temp_dir("preallocated_chunks_take_up_disk_space");
+    let storage = MmapStorage::with_options(dir.clone(), MmapOptions { preallocate: true, ..MmapOptions::default() });
+    let chunk = storage.try_create_chunk(Ident::from("a"), 1 << 16).unwrap();
+
+    assert_eq!(chunk.len(), 1 << 16);
+    assert!(std::fs::metadata(dir.join("a")).unwrap().blocks() * 512 >= 1 << 16);
+}
+
+#[test]
+fn preallocating_too_much_fails_at_creation() {
+    let storage = MmapStorage::with_options(
+        common::temp_dir("preallocating_too_much_fails_at_creation"),
+        MmapOptions { preallocate: true, ..MmapOptions::default() },
+    );
+
+    assert!(storage.try_create_chunk(Ident::from("huge"), 1 << 55).is_err());
+    assert!(!storage.chunk_exists(&Ident::from("huge")));
+}
+
+#[test]
+fn forgetting_a_multi_arena_removes_all_its_files() {
+    let dir = common::temp_dir("forgetting_a_multi_arena_removes_all_its_files");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+    let mut arena = MultiArena::new(Ident::from("m"), 64, 8, storage);
+    for index in 0..200 {
+        arena.push(8 << (index % 4));
+    }
+    assert!(std::fs::read_dir(&dir).unwrap().count() > 20);
+
+    arena.forget_all();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn group_directories_hold_the_chunks_of_each_collection() {
+    let dir = common::temp_dir("group_directories_hold_the_chunks_of_each_collection").join("nested");
+    let options = MmapOptions { group_directories: true, ..MmapOptions::default() };
+    {
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::with_options(dir.clone(), options));
+        let mut vector = Vector::<u64>::new(Ident::from("vec"), 64, Rc::clone(&storage));
+        for item in 0..20 {
+            vector.push(item);
+        }
+        let mut queue = Queue::new(&Ident::from("queue"), 64, storage);
+        unsafe { *(queue.enqueue(8) as *mut u64) = 5 };
+    }
+
+    assert!(dir.join("vec").join("vec_0").is_file());
+    assert!(dir.join("vec").join("vec_len").is_file());
+    assert!(dir.join("queue").join("queue_q_state").is_file());
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::with_options(dir, options));
+    let vector = Vector::<u64>::new(Ident::from("vec"), 64, Rc::clone(&storage));
+    assert_eq!((vector.len(), vector.at(19)), (20, Some(&19)));
+    assert_eq!(storage.chunk_len(&Ident::from("vec_8")), Some(64));
+}
+
+#[test]
+fn page_aligned_collections_round_chunk_sizes_up() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(common::temp_dir("page_aligned_collections_round_chunk_sizes_up")));
+    let page_size = storage.page_size();
+    assert!(page_size >= 4096 && page_size.is_power_of_two());
+
+    let mut arena = Arena::new_page_aligned(Ident::from("a"), 100, 8, Rc::clone(&storage));
+    for item in 0..1000u64 {
+        unsafe { *(arena.push().0 as *mut u64) = item };
+    }
+    assert_eq!(storage.chunk_len(&Ident::from("a_0")), Some(page_size));
+    for item in 0..1000 {
+        assert_eq!(unsafe { *(arena.at(ArenaIndex(item)) as *const u64) }, item as u64);
+    }
+
+    let mut queue = Queue::new_page_aligned(&Ident::from("q"), 100, Rc::clone(&storage));
+    unsafe {
+        for item in 0..2000u64 {
+            *(queue.enqueue(8) as *mut u64) = item;
+        }
+        queue.enqueue(page_size * 2);
+        for item in 0..2000u64 {
+            assert_eq!(*(queue.dequeue().unwrap() as *const u64), item);
+        }
+    }
+    for ident in storage.list_chunks(&Ident::from("q")) {
+        if ident != Ident::from("q_q_state") {
+            assert_eq!(storage.chunk_len(&ident).unwrap() % page_size, 0, "{:?}", ident);
+        }
+    }
+}
+
+#[test]
+fn hashed_names_allow_idents_beyond_the_file_name_limit() {
+    let dir = common::temp_dir("hashed_names_allow_idents_beyond_the_file_name_limit");
+    let options = MmapOptions { hash_long_names: true, ..MmapOptions::default() };
+    let open = || Rc::new(MmapStorage::with_options(dir.clone(), options)) as Rc<dyn ChunkStorage>;
+    let ident = (0..60).fold(Ident::from("deep"), |ident, level| ident.sub(format!("level{}", level)));
+    assert!(ident.0.len() > 255);
+    {
+        let mut vector = Vector::<u32>::new(ident.clone(), 64, open());
+        for item in 0..100 {
+            vector.push(item);
+        }
+    }
+
+    let storage = open();
+    let vector = Vector::<u32>::new(ident.clone(), 64, Rc::clone(&storage));
+    assert!(vector.iter().copied().eq(0..100));
+    let listed = storage.list_chunks(&Ident::from("deep"));
+    assert!(listed.contains(&ident.sub("len")), "{:?}", listed);
+    assert!(listed.iter().all(|chunk_ident| chunk_ident.belongs_to(&ident)));
+
+    vector.forget_all();
+    assert!(storage.list_chunks(&Ident::from("deep")).is_empty());
+    let results = conformance::run_storage_tests(|| MmapStorage::with_options(dir.clone(), options));
+    assert!(results.iter().all(|result| result.passed), "{:?}", results);
+}
+
+#[test]
+fn remap_grown_preserves_the_prefix_and_zeroes_the_tail() {
+    for &sparse in &[true, false] {
+        let storage = MmapStorage::with_options(common::temp_dir(&format!("remap_grown_{}", sparse)), MmapOptions { sparse, ..MmapOptions::default() });
+        let mut chunk = storage.create_chunk(Ident::from("c"), 100);
+        for (index, byte) in chunk.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        let mut grown = storage.remap_grown(chunk, 10_000).unwrap();
+        assert_eq!(grown.len(), 10_000);
+        assert!(grown[..100].iter().enumerate().all(|(index, &byte)| byte == index as u8));
+        assert!(grown[100..].iter().all(|&byte| byte == 0));
+        grown[9999] = 7;
+        drop(grown);
+
+        assert_eq!(storage.chunk_len(&Ident::from("c")), Some(10_000));
+        let reloaded = storage.load_chunk(Ident::from("c"));
+        assert_eq!((reloaded[50], reloaded[9999]), (50, 7));
+
+        let empty = storage.create_chunk(Ident::from("e"), 0);
+        assert_eq!(&storage.remap_grown(empty, 8).unwrap()[..], &[0; 8]);
+    }
+}
+
+#[test]
+fn exactly_one_concurrent_creator_creates_a_chunk() {
+    use std::sync::{Arc, Barrier};
+
+    for round in 0..20 {
+        let dir = common::temp_dir(&format!("exactly_one_concurrent_creator_creates_a_chunk_{}", round));
+        std::fs::create_dir_all(&dir).unwrap();
+        let barrier = Arc::new(Barrier::new(8));
+        let creators = (0..8)
+            .map(|_| {
+                let (dir, barrier) = (dir.clone(), Arc::clone(&barrier));
+                std::thread::spawn(move || {
+                    let storage = MmapStorage::with_options(dir, MmapOptions { sparse: false, ..MmapOptions::default() });
+                    barrier.wait();
+                    let (chunk, created_new) = storage.load_or_create_chunk(Ident::from("c"), 1 << 20);
+                    assert_eq!(chunk.len(), 1 << 20);
+                    created_new
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|creator| creator.join().unwrap())
+            .filter(|&created_new| created_new)
+            .count();
+        assert_eq!(creators, 1);
+
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
+
+#[test]
+fn forgotten_chunks_are_poisoned_if_configured() {
+    for &poison_on_forget in &[false, true] {
+        let dir = common::temp_dir(&format!("forgotten_chunks_are_poisoned_if_configured_{}", poison_on_forget));
+        let storage = MmapStorage::with_options(dir, MmapOptions { poison_on_forget, ..MmapOptions::default() });
+        let mut chunk = storage.create_chunk(Ident::from("c"), 64);
+        chunk.fill(1);
+
+        // another mapping of the same file sees what the forgotten chunk was overwritten with
+        let stale = storage.load_chunk(Ident::from("c"));
+        storage.forget_chunk(chunk);
+        let expected = if poison_on_forget { FORGOTTEN_CHUNK_POISON } else { 1 };
+        assert!(stale.iter().all(|&byte| byte == expected));
+    }
+}
+
+#[test]
+fn validate_chunk_detects_partially_written_files() {
+    let dir = common::temp_dir("validate_chunk_detects_partially_written_files");
+    let storage = MmapStorage::new(dir.clone());
+    drop(storage.create_chunk(Ident::from("a"), 64));
+    storage.validate_chunk(&Ident::from("a"), 64).unwrap();
+
+    std::fs::OpenOptions::new().write(true).open(dir.join("a")).unwrap().set_len(10).unwrap();
+    assert_eq!(storage.validate_chunk(&Ident::from("a"), 64).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(storage.validate_chunk(&Ident::from("b"), 64).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+}
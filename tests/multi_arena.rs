@@ -0,0 +1,310 @@
+use chunky::*;
+use std::rc::Rc;
+
+fn heap() -> Rc<dyn ChunkStorage> {
+    Rc::new(HeapStorage::new())
+}
+
+#[test]
+fn utilization_shows_rounding_overhead() {
+    let mut arena = MultiArena::new(Ident::from("m"), 1024, 8, heap());
+    // 33 bytes are just over 32, so each item takes up a 64 byte slot
+    for _ in 0..10 {
+        arena.push(33);
+    }
+
+    let (live_bytes, allocated_bytes) = arena.utilization();
+    assert_eq!(live_bytes, 10 * 64);
+    assert_eq!(allocated_bytes, 1024);
+}
+
+#[test]
+fn reserve_bin_allocates_ahead() {
+    let storage = Rc::new(Logging::new(HeapStorage::new()));
+    let mut arena = MultiArena::new(Ident::from("m"), 64, 8, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+    arena.push(16);
+    arena.reserve_bin(16, 20);
+    storage.take_log();
+
+    for _ in 0..20 {
+        arena.push(16);
+    }
+    assert!(storage.take_log().is_empty());
+}
+
+#[test]
+fn sizes_near_usize_max_are_rejected() {
+    let mut arena = MultiArena::new(Ident::from("m"), 64, 8, heap());
+
+    for &size in &[usize::MAX, usize::MAX - 3, usize::MAX / 2 + 2] {
+        let error = arena.try_size_to_index(size).expect_err("should be too large");
+        assert_eq!(error.size, size);
+    }
+    assert!(arena.try_push(usize::MAX - 3).is_err());
+}
+
+#[test]
+fn largest_representable_size_gets_the_last_bin() {
+    let arena = MultiArena::new(Ident::from("m"), 64, 8, heap());
+    // 2^63 bytes are 2^60 multiples of the base size of 8 bytes
+    assert_eq!(arena.try_size_to_index(1 << 63).unwrap(), 60);
+    assert_eq!(arena.size_to_index(0), 0);
+    assert_eq!(arena.size_to_index(8), 0);
+    assert_eq!(arena.size_to_index(9), 1);
+}
+
+#[test]
+fn migrate_preserves_all_items() {
+    let storage = heap();
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 4, Rc::clone(&storage));
+    let indices = (0..50u32)
+        .map(|item| {
+            let (item_ptr, index) = arena.push(4 + (item as usize % 5) * 4);
+            unsafe { *(item_ptr as *mut u32) = item };
+            index
+        })
+        .collect::<Vec<_>>();
+
+    let (migrated, new_indices) = arena.migrate(16, Ident::from("n"), storage);
+    assert_eq!(new_indices.len(), 50);
+    assert_eq!(migrated.iter().count(), 50);
+    for (item, index) in indices.iter().enumerate() {
+        assert_eq!(unsafe { *(migrated.at(new_indices[index]) as *const u32) }, item as u32);
+    }
+    // with the larger base size, all items of up to 16 bytes share the first bin
+    for (item, index) in indices.iter().enumerate() {
+        let expected_bin = if 4 + (item % 5) * 4 <= 16 { 0 } else { 1 };
+        assert_eq!(new_indices[index].0, expected_bin);
+    }
+}
+
+#[test]
+fn shrink_to_fit_releases_drained_chunks() {
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, heap());
+    for _ in 0..100 {
+        arena.push(8);
+        arena.push(64);
+    }
+    let (_, allocated_before) = arena.utilization();
+
+    for _ in 0..95 {
+        arena.swap_remove_within_bin(MultiArenaIndex(0, ArenaIndex(0)));
+        arena.swap_remove_within_bin(MultiArenaIndex(3, ArenaIndex(0)));
+    }
+    arena.shrink_to_fit();
+
+    let (live_bytes, allocated_bytes) = arena.utilization();
+    assert!(allocated_bytes < allocated_before);
+    assert_eq!(live_bytes, 5 * 8 + 5 * 64);
+    // one chunk holds the 8 byte items, two chunks the 64 byte items
+    assert_eq!(allocated_bytes, 3 * 256);
+}
+
+#[test]
+fn shrink_bin_releases_reserved_chunks() {
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, heap());
+    arena.push(8);
+    let (_, allocated_before) = arena.utilization();
+
+    arena.reserve_bin(8, 100);
+    assert!(arena.utilization().1 > allocated_before);
+    arena.shrink_bin(0);
+    assert_eq!(arena.utilization().1, allocated_before);
+}
+
+#[test]
+fn sized_handles_read_back_exactly_what_was_written() {
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, heap());
+    let written = [1usize, 7, 8, 9, 33, 100, 200]
+        .iter()
+        .map(|&size| {
+            let handle = arena.push_sized(size);
+            let bytes = (0..size as u8).collect::<Vec<_>>();
+            arena.write(handle, &bytes);
+            (handle, bytes)
+        })
+        .collect::<Vec<_>>();
+
+    for (handle, bytes) in &written {
+        assert_eq!(arena.read(*handle), &bytes[..]);
+    }
+}
+
+#[test]
+fn oversized_handles_panic_instead_of_reading_past_the_item() {
+    let mut arena = MultiArena::new(Ident::from("m"), 256, 8, heap());
+    let handle = arena.push_sized(5);
+    let forged = SizedHandle { size: 1 << 20, ..handle };
+
+    let read = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.read(forged).len()));
+    assert!(read.is_err());
+    let write = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arena.write(forged, &vec![0; 1 << 20])));
+    assert!(write.is_err());
+
+    // sizes up to the bin's rounded-up item size are still within the item
+    arena.write(SizedHandle { size: 8, ..handle }, &[1; 8]);
+    assert_eq!(arena.read(handle), &[1; 5]);
+}
+
+#[test]
+fn size_histogram_counts_live_items_per_bin() {
+    let mut arena = MultiArena::new(Ident::from("m"), 1024, 8, heap());
+    for _ in 0..3 {
+        arena.push(5);
+    }
+    for _ in 0..2 {
+        arena.push(30);
+    }
+    arena.push(8);
+    arena.push(100);
+    let (_, removed) = arena.push(64);
+    arena.swap_remove_within_bin(removed);
+
+    assert_eq!(arena.size_histogram(), vec![(8, 4), (32, 2), (128, 1)]);
+}
+
+#[test]
+fn ensured_bins_are_reconstructed_on_reload() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let index = {
+        let mut arena = MultiArena::new(Ident::from("m"), 1024, 8, Rc::clone(&storage));
+        let index = arena.ensure_bin(24);
+        assert_eq!(index, arena.size_to_index(24));
+        assert_eq!(arena.ensure_bin(20), index);
+        assert_eq!(arena.bin_len(index), 0);
+        index
+    };
+
+    for round in 0..3 {
+        let mut arena = MultiArena::new(Ident::from("m"), 1024, 8, Rc::clone(&storage));
+        assert_eq!(arena.bin_len(index), round);
+        assert_eq!(arena.populated_bin_indices_and_lens().count(), 1);
+        arena.push(24);
+    }
+}
+
+#[test]
+fn at_with_size_reports_the_rounded_up_item_size() {
+    let mut arena = MultiArena::new(Ident::from("m"), 1024, 8, heap());
+    for &(size, rounded) in &[(1, 8), (8, 8), (9, 16), (20, 32), (33, 64)] {
+        let (ptr, index) = arena.push(size);
+        assert_eq!(arena.at_with_size(index), (ptr as *const u8, rounded));
+    }
+}
+
+#[test]
+fn oversized_items_get_exactly_sized_chunks_of_their_own() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let mut arena = MultiArena::new_with_max_item_size(Ident::from("m"), 1024, 8, 256, Rc::clone(&storage));
+    assert_ne!(arena.push_sized(100).index.0, MultiArena::OVERFLOW_BIN);
+    let handle = arena.push_sized(5000);
+    assert_eq!(handle.index.0, MultiArena::OVERFLOW_BIN);
+    assert_eq!(arena.size_to_index(300), MultiArena::OVERFLOW_BIN);
+    assert_eq!(arena.at_with_size(handle.index).1, 5000);
+    assert_eq!(storage.chunk_len(&Ident::from("m").sub("overflow").sub(0)), Some(5000));
+    arena.push_sized(3000);
+    assert_eq!(arena.size_histogram(), vec![(128, 1), (3000, 1), (5000, 1)]);
+    assert_eq!(arena.utilization().1, 1024 + 5000 + 3000);
+
+    arena.forget_all();
+    assert!(storage.list_chunks(&Ident::from("m")).is_empty());
+}
+
+#[test]
+fn removed_overflow_items_leave_no_holes() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let overflow_chunks = || (0..4).filter(|&item_index| storage.chunk_exists(&Ident::from("m").sub("overflow").sub(item_index))).count();
+    let handles = {
+        let mut arena = MultiArena::new_with_max_item_size(Ident::from("m"), 1024, 8, 256, Rc::clone(&storage));
+        let handles = (0..4u8)
+            .map(|item| {
+                let handle = arena.push_sized(1000 + item as usize);
+                arena.write(handle, &vec![item; handle.size]);
+                handle
+            })
+            .collect::<Vec<_>>();
+
+        // removing a middle item moves the last one into its place, like in any other bin
+        let moved = arena.swap_remove_within_bin(handles[1].index).expect("should move the last item");
+        assert_eq!(moved, arena.at(handles[1].index));
+        assert_eq!(arena.at_with_size(handles[1].index).1, 1003);
+        assert_eq!(arena.bin_len(MultiArena::OVERFLOW_BIN), 3);
+        assert!(arena.swap_remove_within_bin(handles[2].index).is_none());
+        assert_eq!(arena.populated_bin_indices_and_lens().collect::<Vec<_>>(), vec![(MultiArena::OVERFLOW_BIN, 2)]);
+        assert_eq!(overflow_chunks(), 2);
+        handles
+    };
+
+    let arena = MultiArena::new_with_max_item_size(Ident::from("m"), 1024, 8, 256, storage);
+    assert_eq!(arena.bin_len(MultiArena::OVERFLOW_BIN), 2);
+    assert_eq!(arena.read(handles[0]), &vec![0; 1000][..]);
+    let moved = SizedHandle { index: handles[1].index, size: 1003 };
+    assert_eq!(arena.read(moved), &vec![3; 1003][..]);
+    assert_eq!(arena.iter().count(), 2);
+}
+
+#[test]
+fn iter_stable_orders_by_bin_then_item_index() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let dump = |arena: &MultiArena| arena.iter_stable().map(|(index, item)| (index, unsafe { *item })).collect::<Vec<_>>();
+    let first_dump = {
+        let mut arena = MultiArena::new_with_max_item_size(Ident::from("m"), 1024, 8, 200, Rc::clone(&storage));
+        for (item, &size) in [64usize, 8, 300, 16, 8, 500, 64].iter().enumerate() {
+            unsafe { *arena.push(size).0 = item as u8 };
+        }
+        let first_dump = dump(&arena);
+        assert_eq!(dump(&arena), first_dump);
+
+        let mut sorted = first_dump.clone();
+        sorted.sort_by_key(|&(index, _)| (index.0, (index.1).0));
+        assert_eq!(first_dump, sorted);
+        assert_eq!(first_dump.last().map(|&(index, _)| index.0), Some(MultiArena::OVERFLOW_BIN));
+        first_dump
+    };
+
+    let arena = MultiArena::new_with_max_item_size(Ident::from("m"), 1024, 8, 200, storage);
+    assert_eq!(dump(&arena), first_dump);
+}
+
+#[test]
+fn defragment_reports_the_bytes_of_forgotten_chunks() {
+    let mut arena = MultiArena::new(Ident::from("m"), 64, 8, heap());
+    arena.reserve_bin(8, 64);
+    let indices = (0..20).map(|_| arena.push(8).1).collect::<Vec<_>>();
+    arena.push(16);
+    for &index in indices.iter().rev().take(18) {
+        arena.swap_remove_within_bin(index);
+    }
+    let (live_bytes, allocated_before) = arena.utilization();
+
+    // removing items forgets the chunks they emptied, but 5 of the 8 reserved chunks were never used
+    let freed = arena.defragment();
+    assert_eq!(freed, 5 * 64);
+    assert_eq!(arena.utilization(), (live_bytes, allocated_before - freed));
+    assert_eq!(arena.defragment(), 0);
+}
+
+#[test]
+fn zero_base_sizes_are_rejected_clearly() {
+    let zero_base_size = std::panic::catch_unwind(|| {
+        MultiArena::new(Ident::from("m"), 1024, 0, heap());
+    });
+    let message = zero_base_size.err().and_then(|panic| panic.downcast_ref::<&str>().map(|message| message.to_string()));
+    assert_eq!(message.as_deref(), Some("MultiArena base size has to be positive"));
+}
+
+#[test]
+fn non_power_of_two_base_sizes_give_consistent_bins() {
+    let mut arena = MultiArena::new(Ident::from("m"), 1024, 12, heap());
+    let mut previous_index = 0;
+    for size in 1..2000 {
+        let bin_index = arena.size_to_index(size);
+        assert!(bin_index >= previous_index);
+        previous_index = bin_index;
+
+        let (_, index) = arena.push(size);
+        assert_eq!(index.0, bin_index);
+        let item_size = arena.at_with_size(index).1;
+        assert!(item_size >= size && (item_size / 12).is_power_of_two() && item_size.is_multiple_of(12));
+    }
+}
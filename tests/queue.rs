@@ -0,0 +1,285 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+fn enqueue_u64s(queue: &mut Queue, items: std::ops::Range<u64>) {
+    for item in items {
+        unsafe { *(queue.enqueue(8) as *mut u64) = item };
+    }
+}
+
+fn dequeue_u64s(queue: &mut Queue, items: std::ops::Range<u64>) {
+    for item in items {
+        assert_eq!(unsafe { *(queue.dequeue().expect("should have item") as *const u64) }, item);
+    }
+}
+
+#[test]
+fn reserve_bytes_allocates_ahead() {
+    let storage = Rc::new(Logging::new(HeapStorage::new()));
+    let mut queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage) as Rc<dyn ChunkStorage>);
+    queue.reserve_bytes(1000);
+    storage.take_log();
+
+    enqueue_u64s(&mut queue, 0..40);
+    assert!(storage.take_log().is_empty());
+    dequeue_u64s(&mut queue, 0..40);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn reserved_chunks_are_reloaded() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(common::temp_dir("reserved_chunks_are_reloaded")));
+    {
+        let mut queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage));
+        enqueue_u64s(&mut queue, 0..10);
+        queue.reserve_bytes(500);
+    }
+
+    let mut queue = Queue::new(&Ident::from("q"), 64, storage);
+    enqueue_u64s(&mut queue, 10..80);
+    dequeue_u64s(&mut queue, 0..80);
+    assert!(unsafe { queue.dequeue() }.is_none());
+}
+
+#[test]
+fn iter_yields_what_dequeue_would() {
+    let mut queue = Queue::new(&Ident::from("q"), 100, Rc::new(HeapStorage::new()));
+    enqueue_u64s(&mut queue, 0..50);
+    dequeue_u64s(&mut queue, 0..7);
+
+    // 100 byte chunks don't fit a whole number of items, so this crosses jump markers
+    let pending = unsafe { queue.iter() }.collect::<Vec<_>>();
+    assert_eq!(pending.len(), 43);
+    assert_eq!(queue.len(), 43);
+    for item in pending {
+        assert_eq!(unsafe { queue.dequeue() }, Some(item));
+    }
+    assert_eq!(unsafe { queue.iter() }.count(), 0);
+}
+
+#[cfg(feature = "mmap")]
+fn drained_queue_reloads(n: u64, reserve: usize, drop_old_chunks: bool) {
+    let dir = common::temp_dir(&format!("drained_queue_reloads_{}_{}_{}", n, reserve, drop_old_chunks));
+    {
+        let mut queue = Queue::new(&Ident::from("q"), 64, Rc::new(MmapStorage::new(dir.clone())));
+        if reserve > 0 {
+            queue.reserve_bytes(reserve);
+        }
+        enqueue_u64s(&mut queue, 0..n);
+        dequeue_u64s(&mut queue, 0..n);
+        assert!(unsafe { queue.dequeue() }.is_none());
+        if drop_old_chunks {
+            unsafe { queue.drop_old_chunks() };
+        }
+    }
+
+    for round in 0..3 {
+        let mut queue = Queue::new(&Ident::from("q"), 64, Rc::new(MmapStorage::new(dir.clone())));
+        assert!(queue.is_empty());
+        enqueue_u64s(&mut queue, 0..n + round);
+        dequeue_u64s(&mut queue, 0..n + round);
+        unsafe { queue.drop_old_chunks() };
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn drained_queues_reload_without_forgotten_chunks() {
+    for &n in &[1, 10, 37] {
+        drained_queue_reloads(n, 0, true);
+    }
+    drained_queue_reloads(37, 0, false);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn drained_queues_reload_with_reserved_chunks() {
+    for &n in &[0, 5, 37] {
+        drained_queue_reloads(n, 500, true);
+    }
+}
+
+#[test]
+fn stats_count_operations_and_chunks() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let item_chunks = || storage.list_chunks(&Ident::from("q")).len() as u64 - 1;
+    let mut queue = storage.queue(&Ident::from("q"), 256);
+    unsafe {
+        for _ in 0..100 {
+            queue.enqueue(16);
+        }
+        for _ in 0..60 {
+            queue.dequeue();
+        }
+    }
+    let before = queue.stats();
+    assert_eq!((before.enqueued, before.dequeued, before.chunks_forgotten), (100, 60, 0));
+    assert_eq!(before.chunks_created, item_chunks());
+
+    unsafe { queue.drop_old_chunks() };
+    let after = queue.stats();
+    assert!(after.chunks_forgotten > 0);
+    assert_eq!(after.chunks_forgotten, before.chunks_created - item_chunks());
+
+    queue.reserve_bytes(10_000);
+    assert!(queue.stats().chunks_created > after.chunks_created);
+    while unsafe { queue.dequeue() }.is_some() {}
+    assert_eq!(queue.stats().dequeued, 100);
+}
+
+#[cfg(feature = "direct_io")]
+#[test]
+fn write_ahead_log_rolls_back_uncommitted_enqueues() {
+    // direct I/O chunks are only written out when flushed or dropped,
+    // so forgetting a queue loses everything that wasn't flushed, as in a crash
+    let dir = common::temp_dir("write_ahead_log_rolls_back_uncommitted_enqueues");
+    let open = || Queue::with_wal(&Ident::from("q"), 64, Rc::new(DirectIoStorage::new(dir.clone())));
+    {
+        let mut queue = open();
+        enqueue_u64s(&mut queue, 0..10);
+        queue.commit();
+        enqueue_u64s(&mut queue, 10..15);
+        std::mem::forget(queue);
+    }
+    {
+        let mut queue = open();
+        // the items 10 to 13 were logged, but their contents never written out,
+        // and item 14 wasn't logged yet
+        assert_eq!(queue.len(), 10);
+        assert_eq!((queue.stats().replayed, queue.stats().rolled_back), (0, 4));
+        dequeue_u64s(&mut queue, 0..5);
+        unsafe { queue.drop_old_chunks() };
+        enqueue_u64s(&mut queue, 100..200);
+        queue.commit();
+        std::mem::forget(queue);
+    }
+
+    let mut queue = open();
+    assert_eq!((queue.len(), queue.stats().rolled_back), (105, 0));
+    dequeue_u64s(&mut queue, 5..10);
+    dequeue_u64s(&mut queue, 100..200);
+    assert!(unsafe { queue.dequeue() }.is_none());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn write_ahead_log_replays_uncommitted_enqueues_that_survived() {
+    // memory mapped chunks keep what was written to them when the process crashes,
+    // which forgetting the queue leaves behind just the same
+    let dir = common::temp_dir("write_ahead_log_replays_uncommitted_enqueues_that_survived");
+    let open = || Queue::with_wal(&Ident::from("q"), 64, Rc::new(MmapStorage::new(dir.clone())));
+    {
+        let mut queue = open();
+        enqueue_u64s(&mut queue, 0..10);
+        queue.commit();
+        dequeue_u64s(&mut queue, 0..3);
+        enqueue_u64s(&mut queue, 10..15);
+        std::mem::forget(queue);
+    }
+    {
+        // all but the latest item were logged, together with the dequeues before them
+        let mut queue = open();
+        assert_eq!(queue.len(), 11);
+        assert_eq!((queue.stats().replayed, queue.stats().rolled_back), (4, 0));
+        dequeue_u64s(&mut queue, 3..14);
+        assert!(unsafe { queue.dequeue() }.is_none());
+
+        // an item whose contents changed after it was logged ends the replay
+        enqueue_u64s(&mut queue, 20..22);
+        let item_22 = unsafe { queue.enqueue(8) as *mut u64 };
+        unsafe { *item_22 = 22 };
+        enqueue_u64s(&mut queue, 23..25);
+        unsafe { *item_22 = 99 };
+        std::mem::forget(queue);
+    }
+
+    let mut queue = open();
+    assert_eq!(queue.len(), 2);
+    assert_eq!((queue.stats().replayed, queue.stats().rolled_back), (2, 2));
+    dequeue_u64s(&mut queue, 20..22);
+    assert!(unsafe { queue.dequeue() }.is_none());
+}
+
+/// Pushes 1.5 GB of items through a queue that always keeps a few unread items,
+/// returning the largest chunk offset seen along the way
+fn churn_with_items_in_flight(queue: &mut Queue, storage: &dyn ChunkStorage) -> usize {
+    let mut max_chunk_at = 0;
+    let mut next_read = 0;
+    for item in 0..1500u64 {
+        unsafe {
+            *(queue.enqueue(1 << 20) as *mut u64) = item;
+            if queue.len() > 3 {
+                assert_eq!(*(queue.dequeue().unwrap() as *const u64), next_read);
+                next_read += 1;
+            }
+            queue.drop_old_chunks();
+        }
+        for chunk in storage.list_chunks(&Ident::from("q")) {
+            if let Some(chunk_at) = chunk.0.strip_prefix("q_").and_then(|chunk_at| chunk_at.parse::<usize>().ok()) {
+                max_chunk_at = max_chunk_at.max(chunk_at);
+            }
+        }
+    }
+    max_chunk_at
+}
+
+#[test]
+fn offsets_stay_bounded_while_items_are_in_flight() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    {
+        let mut queue = Queue::new(&Ident::from("q"), 4 << 20, Rc::clone(&storage));
+        let max_chunk_at = churn_with_items_in_flight(&mut queue, &*storage);
+        assert!(max_chunk_at < (1 << 30) + (8 << 20), "{}", max_chunk_at);
+        assert!(queue.stats().chunks_forgotten > 300);
+    }
+
+    let mut queue = Queue::new(&Ident::from("q"), 4 << 20, storage);
+    assert_eq!(queue.len(), 3);
+    for item in 1497..1500u64 {
+        assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u64) }, item);
+    }
+}
+
+#[cfg(feature = "direct_io")]
+#[test]
+fn rebased_offsets_survive_write_ahead_log_recovery() {
+    let dir = common::temp_dir("rebased_offsets_survive_write_ahead_log_recovery");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(DirectIoStorage::new(dir));
+    {
+        let mut queue = Queue::with_wal(&Ident::from("q"), 4 << 20, Rc::clone(&storage));
+        churn_with_items_in_flight(&mut queue, &*storage);
+        queue.commit();
+        enqueue_u64s(&mut queue, 0..10);
+        std::mem::forget(queue);
+    }
+
+    let mut queue = Queue::with_wal(&Ident::from("q"), 4 << 20, storage);
+    assert_eq!((queue.len(), queue.stats().rolled_back), (3, 9));
+    for item in 1497..1500u64 {
+        assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u64) }, item);
+    }
+}
+
+#[test]
+fn bytes_until_chunk_end_shrinks_until_the_next_chunk() {
+    let mut queue = Queue::new(&Ident::from("q"), 256, Rc::new(HeapStorage::new()));
+    let empty = queue.bytes_until_chunk_end();
+    assert!(empty > 200 && empty < 256, "{}", empty);
+
+    let header_size = 2 * std::mem::size_of::<usize>();
+    unsafe { queue.enqueue(16) };
+    assert_eq!(queue.bytes_until_chunk_end(), empty - 16 - header_size);
+    unsafe { queue.enqueue(40) };
+    let rest = queue.bytes_until_chunk_end();
+    assert_eq!(rest, empty - 56 - 2 * header_size);
+
+    // an item of exactly the remaining size still fits
+    unsafe { queue.enqueue(rest) };
+    assert_eq!(queue.bytes_until_chunk_end(), 0);
+    assert_eq!(queue.stats().chunks_created, 1);
+    unsafe { queue.enqueue(8) };
+    assert_eq!(queue.stats().chunks_created, 2);
+    assert_eq!(queue.bytes_until_chunk_end(), empty - 8 - header_size);
+}
@@ -0,0 +1,44 @@
+use chunky::*;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+#[test]
+fn bytes_written_to_a_queue_read_back_identically() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let data = (0..200_003u32).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+    {
+        let mut queue = Queue::new(&Ident::from("q"), 4096, Rc::clone(&storage));
+        let mut writer = queue.writer(1000);
+        writer.write_all(&data[..100_001]).unwrap();
+        writer.flush().unwrap();
+        writer.write_all(&data[100_001..]).unwrap();
+    }
+
+    // reading in pieces that don't line up with the messages
+    let mut queue = Queue::new(&Ident::from("q"), 4096, storage);
+    let mut read = Vec::new();
+    let mut buffer = [0u8; 333];
+    {
+        let mut reader = queue.reader();
+        loop {
+            match reader.read(&mut buffer).unwrap() {
+                0 => break,
+                n_bytes => read.extend_from_slice(&buffer[..n_bytes]),
+            }
+        }
+    }
+    assert_eq!(read, data);
+    assert!(queue.is_empty());
+    assert!(queue.stats().chunks_forgotten > 10);
+}
+
+#[test]
+fn readers_stop_at_the_end_of_the_queue() {
+    let mut queue = Queue::new(&Ident::from("q"), 256, Rc::new(HeapStorage::new()));
+    queue.writer(10).write_all(b"abc").unwrap();
+    let mut read = String::new();
+    queue.reader().read_to_string(&mut read).unwrap();
+    assert_eq!(read, "abc");
+
+    assert_eq!(queue.reader().read(&mut [0; 8]).unwrap(), 0);
+}
@@ -0,0 +1,77 @@
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn quota_refuses_chunks_past_the_limit_until_some_are_forgotten() {
+    let quota = Rc::new(Quota::new(HeapStorage::new(), 300));
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&quota) as Rc<dyn ChunkStorage>;
+    let mut arena = Arena::new(Ident::from("a"), 100, 10, Rc::clone(&storage));
+    // the persisted len counts towards the quota too
+    assert_eq!(quota.used(), 8);
+    for _ in 0..20 {
+        arena.push();
+    }
+    assert_eq!(quota.used(), 208);
+    assert!(storage.try_create_chunk(Ident::from("x"), 100).is_err());
+
+    let overflowing = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        for _ in 0..10 {
+            arena.push();
+        }
+    }));
+    assert!(overflowing.is_err());
+    let exactly_full = storage.try_create_chunk(Ident::from("x"), 92).unwrap();
+    assert_eq!(quota.used(), 300);
+    storage.forget_chunk(exactly_full);
+
+    for _ in 0..10 {
+        arena.pop_away();
+    }
+    arena.shrink_to_fit();
+    assert_eq!(quota.used(), 108);
+    storage.forget_chunk(storage.try_create_chunk(Ident::from("x"), 150).unwrap());
+    assert_eq!(quota.used(), 108);
+}
+
+#[test]
+fn reloading_persisted_chunks_counts_them_once_until_they_are_forgotten() {
+    let quota = Rc::new(Quota::new(VirtualFsStorage::new(), 1000));
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&quota) as Rc<dyn ChunkStorage>;
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+    for i in 0..8 {
+        vector.push(i);
+    }
+    // one chunk of 64 bytes and the persisted len
+    assert_eq!(quota.used(), 72);
+    drop(vector);
+    // dropping doesn't free persisted chunks
+    assert_eq!(quota.used(), 72);
+
+    for _ in 0..5 {
+        let vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+        assert_eq!(vector.len(), 8);
+        assert_eq!(quota.used(), 72);
+    }
+
+    // zero-length chunks share a dangling pointer, but are still told apart
+    let empty_a = storage.create_chunk(Ident::from("a"), 0);
+    let empty_b = storage.create_chunk(Ident::from("b"), 0);
+    let big = storage.create_chunk(Ident::from("c"), 100);
+    storage.forget_chunk(empty_a);
+    storage.forget_chunk(empty_b);
+    assert_eq!(quota.used(), 172);
+    storage.forget_chunk(big);
+
+    Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage)).forget_all();
+    assert_eq!(quota.used(), 0);
+}
+
+#[test]
+fn dropping_the_last_handle_of_a_transient_chunk_frees_its_quota() {
+    let quota = Rc::new(Quota::new(HeapStorage::new(), 100));
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&quota) as Rc<dyn ChunkStorage>;
+    for _ in 0..5 {
+        drop(storage.create_chunk(Ident::from("x"), 80));
+        assert_eq!(quota.used(), 0);
+    }
+}
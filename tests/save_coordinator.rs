@@ -0,0 +1,81 @@
+#![cfg(feature = "mmap")]
+
+mod common;
+
+use chunky::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// Simulates a crash while a collection is being flushed
+struct Crashing;
+
+impl Flushable for Crashing {
+    fn flush(&self) {
+        panic!("Crashed while saving");
+    }
+}
+
+fn save_vector(storage: &Rc<dyn ChunkStorage>) -> SaveCoordinator {
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(storage));
+    for item in 0..20 {
+        vector.push(item);
+    }
+    let mut queue = Queue::new(&Ident::from("q"), 256, Rc::clone(storage));
+    unsafe { *(queue.enqueue(8) as *mut u64) = 3 };
+    let multi_arena = MultiArena::new(Ident::from("m"), 256, 8, Rc::clone(storage));
+
+    let mut coordinator = SaveCoordinator::new(Ident::from("save"), Rc::clone(storage));
+    assert!(coordinator.last_save_is_complete());
+    coordinator.save(&[&vector, &queue, &multi_arena]);
+    assert_eq!(coordinator.generation(), 1);
+    assert!(coordinator.last_save_is_complete());
+    coordinator
+}
+
+#[test]
+fn completed_saves_are_complete_when_loaded() {
+    let dir = common::temp_dir("completed_saves_are_complete_when_loaded");
+    drop(save_vector(&(Rc::new(MmapStorage::new(dir.clone())) as Rc<dyn ChunkStorage>)));
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    let coordinator = SaveCoordinator::new(Ident::from("save"), Rc::clone(&storage));
+    assert!(coordinator.last_save_is_complete());
+    assert_eq!(coordinator.generation(), 1);
+    assert_eq!(Vector::<u64>::new(Ident::from("v"), 64, storage).len(), 20);
+}
+
+#[test]
+fn interrupted_saves_are_incomplete_when_loaded() {
+    let dir = common::temp_dir("interrupted_saves_are_incomplete_when_loaded");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+    let mut coordinator = save_vector(&storage);
+    let crashed = catch_unwind(AssertUnwindSafe(|| coordinator.save(&[&Crashing])));
+    assert!(crashed.is_err());
+    drop(coordinator);
+
+    let coordinator = SaveCoordinator::new(Ident::from("save"), Rc::new(MmapStorage::new(dir)));
+    assert!(!coordinator.last_save_is_complete());
+    assert_eq!(coordinator.generation(), 2);
+}
+
+#[test]
+fn changes_after_a_save_make_it_incomplete() {
+    let dir = common::temp_dir("changes_after_a_save_make_it_incomplete");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+    let mut coordinator = save_vector(&storage);
+    coordinator.mark_dirty();
+    assert!(!coordinator.last_save_is_complete());
+    Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage)).push(20);
+    drop(coordinator);
+
+    // loading the manifest also marks it incomplete, until the next save
+    let reload = || SaveCoordinator::new(Ident::from("save"), Rc::new(MmapStorage::new(dir.clone())));
+    assert!(!reload().last_save_is_complete());
+    let mut coordinator = reload();
+    assert!(!coordinator.last_save_is_complete());
+    coordinator.save(&[]);
+    drop(coordinator);
+    assert!(reload().last_save_is_complete());
+    // the collections could have been changed after loading them, without saving again
+    assert!(!reload().last_save_is_complete());
+}
@@ -0,0 +1,37 @@
+use chunky::*;
+use std::sync::Arc;
+
+#[test]
+fn shared_vector_is_read_on_another_thread() {
+    let storage: Arc<dyn SendableStorage> = Arc::new(HeapStorage::new());
+    let mut vector = Vector::<u64>::new_shared(Ident::from("v"), 64, storage);
+    for item in 0..100 {
+        vector.push(item);
+    }
+
+    let sum = std::thread::spawn(move || {
+        assert_eq!(vector.len(), 100);
+        vector.iter().sum::<u64>()
+    });
+    assert_eq!(sum.join().unwrap(), 4950);
+}
+
+#[test]
+fn shared_collections_are_mutated_on_another_thread() {
+    let storage: Arc<dyn SendableStorage> = Arc::new(HeapStorage::new());
+    let mut arena = MultiArena::new_shared(Ident::from("m"), 64, 8, Arc::clone(&storage));
+    let mut map = ChunkyMap::<u32, u64>::new_shared(Ident::from("map"), 256, storage);
+    let (item, index) = arena.push(4);
+    unsafe { *item = 3 };
+
+    let (arena, map) = std::thread::spawn(move || {
+        arena.push(20);
+        map.insert(1, 10);
+        (arena, map)
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(unsafe { *arena.at(index) }, 3);
+    assert_eq!(map.into_inner().get(&1), Some(&10));
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "mmap")]
+
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn collections_are_only_written_out_on_commit() {
+    let dir = common::temp_dir("collections_are_only_written_out_on_commit");
+    let snapshot = Rc::new(SnapshotStorage::new());
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&snapshot) as Rc<dyn ChunkStorage>;
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+    for item in 0..100 {
+        vector.push(item);
+    }
+    assert!(!dir.exists());
+
+    snapshot.commit(dir.clone()).unwrap();
+    assert!(MmapStorage::new(dir.clone()).chunk_exists(&Ident::from("v").sub(96)));
+
+    // committing again removes the chunks forgotten since
+    vector.truncate(10);
+    snapshot.commit(dir.clone()).unwrap();
+    drop(vector);
+    assert!(!MmapStorage::new(dir.clone()).chunk_exists(&Ident::from("v").sub(16)));
+
+    let reopened = Vector::<u64>::new(Ident::from("v"), 64, Rc::new(SnapshotStorage::open(dir)));
+    assert_eq!(reopened.len(), 10);
+    assert!((0..10).all(|index| *reopened.at(index).unwrap() == index as u64));
+}
+
+#[test]
+fn reopened_snapshots_stay_in_memory() {
+    let dir = common::temp_dir("reopened_snapshots_stay_in_memory");
+    let snapshot = SnapshotStorage::new();
+    snapshot.create_chunk(Ident::from("c"), 8).copy_from_slice(&[1; 8]);
+    snapshot.commit(dir.clone()).unwrap();
+
+    let reopened = SnapshotStorage::open(dir.clone());
+    reopened.load_chunk(Ident::from("c")).copy_from_slice(&[2; 8]);
+    assert_eq!(&reopened.load_chunk(Ident::from("c"))[..], &[2; 8]);
+    assert_eq!(&MmapStorage::new(dir).load_chunk(Ident::from("c"))[..], &[1; 8]);
+}
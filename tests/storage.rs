@@ -0,0 +1,261 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn checksum_changes_with_contents() {
+    let storage = HeapStorage::new();
+    let mut chunk = storage.create_chunk(Ident::from("a"), 64);
+    chunk.copy_from_slice(&[7; 64]);
+    let checksum = storage.chunk_checksum(&Ident::from("a"));
+
+    chunk[3] = 99;
+    assert_ne!(storage.chunk_checksum(&Ident::from("a")), checksum);
+    chunk[3] = 7;
+    assert_eq!(storage.chunk_checksum(&Ident::from("a")), checksum);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn checksums_match_across_backends() {
+    let mmap = MmapStorage::new(common::temp_dir("checksums_match_across_backends"));
+    let heap = HeapStorage::new();
+    let mut mmap_chunk = mmap.create_chunk(Ident::from("a"), 64);
+    let mut heap_chunk = heap.create_chunk(Ident::from("a"), 64);
+    for index in 0..64 {
+        mmap_chunk[index] = index as u8;
+        heap_chunk[index] = index as u8;
+    }
+    drop(mmap_chunk);
+
+    assert_eq!(mmap.chunk_checksum(&Ident::from("a")), heap.chunk_checksum(&Ident::from("a")));
+}
+
+#[test]
+fn heap_chunks_are_transient() {
+    let chunk = HeapStorage::new().create_chunk(Ident::from("a"), 8);
+    assert_eq!(chunk.kind(), ChunkKind::Transient);
+    assert!(!chunk.is_persistent());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_chunks_are_persistent() {
+    let storage = MmapStorage::new(common::temp_dir("mmap_chunks_are_persistent"));
+    let chunk = storage.create_chunk(Ident::from("a"), 8);
+    assert_eq!(chunk.kind(), ChunkKind::Persistent);
+    assert!(chunk.is_persistent());
+}
+
+#[test]
+fn chunks_stay_valid_after_their_storage_is_dropped() {
+    let heap = HeapStorage::new();
+    let bump = BumpHeapStorage::new(1000);
+    let mut heap_chunk = heap.create_chunk(Ident::from("a"), 100);
+    let mut bump_chunk = bump.create_chunk(Ident::from("a"), 100);
+    drop(heap);
+    drop(bump);
+
+    heap_chunk[5] = 7;
+    bump_chunk[5] = 8;
+    assert_eq!((heap_chunk[5], bump_chunk[5]), (7, 8));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_chunks_stay_valid_after_their_storage_is_dropped() {
+    let storage = MmapStorage::new(common::temp_dir("mmap_chunks_stay_valid_after_their_storage_is_dropped"));
+    let mut chunk = storage.create_chunk(Ident::from("a"), 64);
+    drop(storage);
+
+    chunk[5] = 7;
+    assert_eq!(chunk[5], 7);
+}
+
+#[test]
+fn retained_owners_live_as_long_as_the_chunk() {
+    let storage = HeapStorage::new();
+    let mut chunk = storage.create_chunk(Ident::from("a"), 100);
+    let owner = Rc::new(5);
+    chunk.retain(Rc::clone(&owner));
+    assert_eq!(Rc::strong_count(&owner), 2);
+
+    drop(chunk);
+    assert_eq!(Rc::strong_count(&owner), 1);
+}
+
+#[test]
+fn group_is_the_top_level_prefix() {
+    assert_eq!(Ident::from("a").group(), "a");
+    assert_eq!(Ident::from("a").sub("b").sub(3).group(), "a");
+}
+
+#[test]
+fn fingerprint_changes_with_any_chunk_of_the_group() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let group = Ident::from("v");
+    let mut vector = Vector::<u64>::new(group.clone(), 64, Rc::clone(&storage));
+    for item in 0..20 {
+        vector.push(item);
+    }
+    let fingerprint = storage.group_fingerprint(&group);
+
+    *vector.at_mut(13).unwrap() = 99;
+    assert_ne!(storage.group_fingerprint(&group), fingerprint);
+    *vector.at_mut(13).unwrap() = 13;
+    assert_eq!(storage.group_fingerprint(&group), fingerprint);
+    vector.push(20);
+    assert_ne!(storage.group_fingerprint(&group), fingerprint);
+}
+
+#[test]
+fn groups_only_contain_their_own_chunks() {
+    assert!(Ident::from("a").sub(1).belongs_to(&Ident::from("a")));
+    assert!(!Ident::from("ab").belongs_to(&Ident::from("a")));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn fingerprint_is_stable_across_reloads() {
+    for &group_directories in &[false, true] {
+        let dir = common::temp_dir(&format!("fingerprint_is_stable_across_reloads_{}", group_directories));
+        let options = MmapOptions { group_directories, ..MmapOptions::default() };
+        let group = Ident::from("vec");
+        let fingerprint = {
+            let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::with_options(dir.clone(), options));
+            let mut vector = Vector::<u64>::new(group.clone(), 64, Rc::clone(&storage));
+            // a group whose name starts with the other's must not be part of it
+            let _other = Vector::<u64>::new(Ident::from("vecx"), 64, Rc::clone(&storage));
+            for item in 0..20 {
+                vector.push(item);
+            }
+            assert_eq!(storage.list_chunks(&group).len(), 4);
+            storage.group_fingerprint(&group)
+        };
+
+        let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::with_options(dir, options));
+        let _vector = Vector::<u64>::new(group.clone(), 64, Rc::clone(&storage));
+        assert_eq!(storage.group_fingerprint(&group), fingerprint);
+    }
+}
+
+fn chunk_lens_are_exactly_the_requested_sizes(storage: &dyn ChunkStorage, tag: &str) {
+    for (index, &size) in [0usize, 1, 7, 13, 100, 4095, 4097, 10_000].iter().enumerate() {
+        let ident = Ident(format!("{}_{}", tag, index));
+        let chunk = storage.create_chunk(ident.clone(), size);
+        assert_eq!(chunk.len(), size, "{} chunk of size {}", tag, size);
+        assert!(chunk.capacity() >= chunk.len());
+        if chunk.is_persistent() {
+            drop(chunk);
+            assert_eq!(storage.load_chunk(ident).len(), size, "reloaded {} chunk of size {}", tag, size);
+        }
+    }
+}
+
+#[test]
+fn heap_chunk_lens_are_exactly_the_requested_sizes() {
+    chunk_lens_are_exactly_the_requested_sizes(&HeapStorage::new(), "heap");
+    chunk_lens_are_exactly_the_requested_sizes(&BumpHeapStorage::new(1 << 16), "bump");
+    chunk_lens_are_exactly_the_requested_sizes(&VirtualFsStorage::new(), "vfs");
+    // empty chunks still get a byte allocated
+    assert_eq!(HeapStorage::new().create_chunk(Ident::from("x"), 0).capacity(), 1);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_chunk_lens_are_exactly_the_requested_sizes() {
+    let dir = common::temp_dir("mmap_chunk_lens_are_exactly_the_requested_sizes");
+    chunk_lens_are_exactly_the_requested_sizes(&MmapStorage::new(dir), "mmap");
+    chunk_lens_are_exactly_the_requested_sizes(&AnonMmapStorage::new(), "anon");
+}
+
+#[cfg(feature = "direct_io")]
+#[test]
+fn direct_io_chunk_lens_are_exactly_the_requested_sizes() {
+    let dir = common::temp_dir("direct_io_chunk_lens_are_exactly_the_requested_sizes");
+    chunk_lens_are_exactly_the_requested_sizes(&DirectIoStorage::new(dir), "direct");
+}
+
+/// Forgetting or flushing a chunk of another storage only drops it, and `try_forget_chunk` reports it
+fn foreign_chunks_are_only_dropped(storage: &dyn ChunkStorage) {
+    let other = HeapStorage::new();
+    storage.forget_chunk(other.create_chunk(Ident::from("foreign"), 10));
+    storage.forget_chunks(vec![other.create_chunk(Ident::from("foreign"), 10), storage.create_chunk(Ident::from("own"), 10)]);
+    assert!(!storage.chunk_exists(&Ident::from("own")));
+    storage.flush_chunk(&other.create_chunk(Ident::from("foreign"), 10));
+
+    let error = storage.try_forget_chunk(other.create_chunk(Ident::from("foreign"), 10)).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(!other.chunk_exists(&Ident::from("foreign")));
+    storage.try_forget_chunk(storage.create_chunk(Ident::from("own"), 10)).unwrap();
+    assert!(!storage.chunk_exists(&Ident::from("own")));
+}
+
+#[test]
+fn heap_storages_only_drop_foreign_chunks() {
+    let heap = HeapStorage::new();
+    heap.forget_chunk(BumpHeapStorage::new(1 << 12).create_chunk(Ident::from("foreign"), 10));
+    BumpHeapStorage::new(1 << 12).forget_chunk(heap.create_chunk(Ident::from("foreign"), 10));
+    assert!(!heap.chunk_exists(&Ident::from("foreign")));
+    let error = heap.try_forget_chunk(VirtualFsStorage::new().create_chunk(Ident::from("foreign"), 10)).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    foreign_chunks_are_only_dropped(&BumpHeapStorage::new(1 << 12));
+    foreign_chunks_are_only_dropped(&VirtualFsStorage::new());
+    foreign_chunks_are_only_dropped(&Logging::new(HeapStorage::new()));
+    foreign_chunks_are_only_dropped(&Quota::new(HeapStorage::new(), 1000));
+    foreign_chunks_are_only_dropped(&Dedup::new(VirtualFsStorage::new()));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_storages_only_drop_foreign_chunks() {
+    let mmap = MmapStorage::new(common::temp_dir("mmap_storages_only_drop_foreign_chunks"));
+    foreign_chunks_are_only_dropped(&mmap);
+    let grown = mmap.remap_grown(HeapStorage::new().create_chunk(Ident::from("foreign"), 10), 20);
+    assert_eq!(grown.err().map(|error| error.kind()), Some(std::io::ErrorKind::InvalidInput));
+
+    foreign_chunks_are_only_dropped(&AnonMmapStorage::new());
+    foreign_chunks_are_only_dropped(&SnapshotStorage::new());
+}
+
+#[cfg(feature = "direct_io")]
+#[test]
+fn direct_io_storages_only_drop_foreign_chunks() {
+    foreign_chunks_are_only_dropped(&DirectIoStorage::new(common::temp_dir("direct_io_storages_only_drop_foreign_chunks")));
+}
+
+#[cfg(feature = "kv")]
+#[test]
+fn key_value_storages_only_drop_foreign_chunks() {
+    let dir = common::temp_dir("key_value_storages_only_drop_foreign_chunks");
+    std::fs::create_dir_all(&dir).unwrap();
+    foreign_chunks_are_only_dropped(&KvStorage::new(dir.join("chunks.redb")));
+}
+
+#[test]
+fn collections_with_the_same_ident_share_transient_storages() {
+    let mut storages: Vec<Rc<dyn ChunkStorage>> = vec![Rc::new(HeapStorage::new()), Rc::new(BumpHeapStorage::new(1 << 12))];
+    #[cfg(feature = "mmap")]
+    storages.push(Rc::new(AnonMmapStorage::new()));
+
+    for storage in storages {
+        let mut first = Arena::new(Ident::from("x"), 64, 8, Rc::clone(&storage));
+        first.push();
+        first.reserve(20);
+        let mut second = Arena::new(Ident::from("x"), 64, 8, Rc::clone(&storage));
+        assert_eq!(second.len(), 0);
+        second.push();
+        let mut first_queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage));
+        first_queue.reserve_bytes(500);
+        let _second_queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage));
+
+        // dropping one of two same-ident chunks keeps the other one live
+        drop(first);
+        assert!(storage.chunk_exists(&Ident::from("x_0")));
+        assert_eq!(storage.chunk_len(&Ident::from("x_0")), Some(64));
+        drop(second);
+        assert!(!storage.chunk_exists(&Ident::from("x_0")));
+    }
+}
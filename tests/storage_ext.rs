@@ -0,0 +1,78 @@
+use chunky::prelude::*;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+fn heap() -> Rc<dyn ChunkStorage> {
+    Rc::new(HeapStorage::new())
+}
+
+#[test]
+fn builds_values_on_the_storage() {
+    let storage = heap();
+    let value = storage.value(Ident::from("value"), 5u32);
+    let portable_value = storage.portable_value(Ident::from("portable_value"), 6u64);
+    let atomic_value = storage.atomic_value(Ident::from("atomic_value"), 7u32);
+
+    assert_eq!(*value, 5);
+    assert_eq!(portable_value.get(), 6);
+    assert_eq!(atomic_value.load(Ordering::SeqCst), 7);
+    for ident in &["value", "portable_value", "atomic_value"] {
+        assert!(storage.chunk_exists(&Ident::from(*ident)));
+    }
+}
+
+#[test]
+fn builds_arenas_on_the_storage() {
+    let storage = heap();
+    let mut arena = storage.arena(Ident::from("arena"), 64, 8);
+    let mut typed_arena = storage.typed_arena::<u64>(Ident::from("typed_arena"), 64);
+    let mut multi_arena = storage.multi_arena(Ident::from("multi_arena"), 64, 8);
+
+    arena.push();
+    let index = typed_arena.push(3);
+    let (item, multi_index) = multi_arena.push(3);
+    unsafe { *item = 4 };
+
+    assert_eq!(arena.len(), 1);
+    assert_eq!(typed_arena.get(index), Some(&3));
+    assert_eq!(unsafe { *multi_arena.at(multi_index) }, 4);
+    for group in &["arena", "typed_arena", "multi_arena"] {
+        assert!(!storage.list_chunks(&Ident::from(*group)).is_empty());
+    }
+}
+
+#[test]
+fn builds_sequences_on_the_storage() {
+    let storage = heap();
+    let mut vector = storage.vector::<u32>(Ident::from("vector"), 64);
+    let mut queue = storage.queue(&Ident::from("queue"), 64);
+    let mut tagged_queue = storage.tagged_queue(&Ident::from("tagged_queue"), 64);
+    let mut deque = storage.deque(Ident::from("deque"), 64, 8);
+
+    vector.push(1);
+    unsafe {
+        *(queue.enqueue(4) as *mut u32) = 2;
+        *(tagged_queue.enqueue(9, 4) as *mut u32) = 3;
+    }
+    deque.push_back();
+
+    assert_eq!(vector.at(0), Some(&1));
+    assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u32) }, 2);
+    let (tag, item, _) = unsafe { tagged_queue.dequeue().unwrap() };
+    assert_eq!((tag, unsafe { *(item as *const u32) }), (9, 3));
+    assert_eq!(deque.len(), 1);
+}
+
+#[test]
+fn builds_bit_vecs_and_maps_on_the_storage() {
+    let storage = heap();
+    let mut bit_vec = storage.bit_vec(Ident::from("bit_vec"), 64);
+    let mut map = storage.chunky_map::<u32, u64>(Ident::from("map"), 256);
+
+    bit_vec.resize(10);
+    bit_vec.set(4);
+    map.insert(1, 10);
+
+    assert!(bit_vec.get(4) && !bit_vec.get(5));
+    assert_eq!(map.get(&1), Some(&10));
+}
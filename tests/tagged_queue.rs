@@ -0,0 +1,46 @@
+use chunky::*;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Moved {
+    distance: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Renamed {
+    id: u16,
+    flags: u8,
+}
+
+const MOVED: u32 = 1;
+const RENAMED: u32 = 2;
+
+#[test]
+fn messages_are_dispatched_on_their_tag() {
+    let mut queue = TaggedQueue::new(&Ident::from("q"), 128, Rc::new(HeapStorage::new()));
+    for item in 0..20u64 {
+        unsafe {
+            if item % 2 == 0 {
+                (queue.enqueue(MOVED, std::mem::size_of::<Moved>()) as *mut Moved).write_unaligned(Moved { distance: item });
+            } else {
+                (queue.enqueue(RENAMED, std::mem::size_of::<Renamed>()) as *mut Renamed)
+                    .write_unaligned(Renamed { id: item as u16, flags: 7 });
+            }
+        }
+    }
+
+    for item in 0..20u64 {
+        match unsafe { queue.dequeue() }.expect("should have message") {
+            (MOVED, message, len) => {
+                assert_eq!(len, std::mem::size_of::<Moved>());
+                assert_eq!(unsafe { (message as *const Moved).read_unaligned() }, Moved { distance: item });
+            }
+            (RENAMED, message, len) => {
+                assert_eq!(len, std::mem::size_of::<Renamed>());
+                assert_eq!(unsafe { (message as *const Renamed).read_unaligned() }, Renamed { id: item as u16, flags: 7 });
+            }
+            (tag, _, _) => panic!("unexpected tag {}", tag),
+        }
+    }
+    assert!(unsafe { queue.dequeue() }.is_none());
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+fn items(vector: &Vector<u64>) -> Vec<u64> {
+    (0..vector.len()).map(|index| *vector.at(index).unwrap()).collect()
+}
+
+#[test]
+fn dropped_transaction_rolls_back() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut vector = Vector::new(Ident::from("v"), 64, storage);
+    for item in 0..10 {
+        vector.push(item);
+    }
+    {
+        let mut transaction = vector.transaction();
+        for _ in 0..5 {
+            transaction.pop();
+        }
+        for item in 100..120 {
+            transaction.push(item);
+        }
+        assert_eq!(transaction.len(), 25);
+    }
+
+    assert_eq!(items(&vector), (0..10).collect::<Vec<_>>());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn uncommitted_transaction_leaves_persisted_vector_unchanged() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(common::temp_dir("uncommitted_transaction")));
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+    for item in 0..10 {
+        vector.push(item);
+    }
+    {
+        let mut transaction = vector.transaction();
+        transaction.pop();
+        transaction.push(100);
+    }
+    drop(vector);
+
+    let mut vector = Vector::new(Ident::from("v"), 64, Rc::clone(&storage));
+    assert_eq!(items(&vector), (0..10).collect::<Vec<_>>());
+
+    let mut transaction = vector.transaction();
+    transaction.push(10);
+    transaction.commit();
+    drop(vector);
+    assert_eq!(items(&Vector::new(Ident::from("v"), 64, storage)), (0..11).collect::<Vec<_>>());
+}
@@ -0,0 +1,47 @@
+use chunky::*;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+struct Entity {
+    id: u32,
+    name: String,
+}
+
+fn arena_of(n: u32) -> (TypedArena<Entity>, Vec<ArenaIndex>) {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut arena = TypedArena::new(Ident::from("a"), 64, storage);
+    let indices = (0..n).map(|id| arena.push(Entity { id, name: format!("e{}", id) })).collect();
+    (arena, indices)
+}
+
+#[test]
+fn stores_structs_by_index() {
+    let (mut arena, indices) = arena_of(10);
+    assert_eq!(arena.get(indices[3]), Some(&Entity { id: 3, name: "e3".to_owned() }));
+
+    arena.get_mut(indices[3]).unwrap().id = 33;
+    assert_eq!(arena.get(indices[3]).unwrap().id, 33);
+    assert_eq!(arena.len(), 10);
+}
+
+#[test]
+fn swap_remove_moves_the_last_item_into_place() {
+    let (mut arena, indices) = arena_of(10);
+
+    assert_eq!(arena.swap_remove(indices[3]).unwrap().id, 3);
+    assert_eq!(arena.get(indices[3]).unwrap().id, 9);
+    assert_eq!(arena.len(), 9);
+
+    // the last index is gone now
+    assert!(arena.get(indices[9]).is_none());
+    assert!(arena.swap_remove(indices[9]).is_none());
+    assert_eq!(arena.swap_remove(indices[8]).unwrap().name, "e8");
+}
+
+#[test]
+fn contains_checks_the_index_against_len() {
+    let (arena, indices) = arena_of(3);
+    assert!(arena.contains(indices[2]));
+    assert!(!arena.contains(ArenaIndex(3)));
+    assert!(arena.get(ArenaIndex(3)).is_none());
+}
@@ -0,0 +1,232 @@
+mod common;
+
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn portable_values_are_little_endian_u64s() {
+    assert_eq!(usize::decode(&[5, 0, 0, 0, 0, 0, 0, 0]), 5);
+    assert_eq!(u64::decode(&[1, 2, 0, 0, 0, 0, 0, 0]), 0x0201);
+
+    let mut bytes = [0; 8];
+    0x0201usize.encode(&mut bytes);
+    assert_eq!(bytes, [1, 2, 0, 0, 0, 0, 0, 0]);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn arena_loads_hand_written_little_endian_len() {
+    let dir = common::temp_dir("arena_loads_hand_written_little_endian_len");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a_len"), [5, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    for chunk_file in &["a_0", "a_2", "a_4"] {
+        std::fs::write(dir.join(chunk_file), [0; 16]).unwrap();
+    }
+
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir));
+    assert_eq!(Arena::new(Ident::from("a"), 16, 8, storage).len(), 5);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn queue_state_is_persisted_as_little_endian_u64s() {
+    let dir = common::temp_dir("queue_state_is_persisted_as_little_endian_u64s");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+    {
+        let mut queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage));
+        for item in 0..20u64 {
+            unsafe { *(queue.enqueue(8) as *mut u64) = item };
+        }
+        for _ in 0..5 {
+            unsafe { queue.dequeue() };
+        }
+    }
+
+    let state = std::fs::read(dir.join("q_q_state")).unwrap();
+    assert_eq!(state.len(), 5 * 8);
+    // the last field is the number of items in the queue
+    assert_eq!(state[32..], [15, 0, 0, 0, 0, 0, 0, 0]);
+
+    let mut queue = Queue::new(&Ident::from("q"), 64, storage);
+    assert_eq!(queue.len(), 15);
+    for item in 5..20u64 {
+        assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u64) }, item);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    read_at: u64,
+    len: u32,
+}
+
+#[test]
+fn modify_updates_in_place() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut value = Value::load_or_default(Ident::from("v"), Position { read_at: 1, len: 2 }, storage);
+    value.modify(|position| position.read_at += 10);
+    assert_eq!((value.read_at, value.len), (11, 2));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn modify_is_persisted() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(common::temp_dir("modify_is_persisted")));
+    {
+        let mut value = Value::load_or_default(Ident::from("v"), Position { read_at: 1, len: 2 }, Rc::clone(&storage));
+        value.modify(|position| {
+            position.read_at += 10;
+            position.len = 7;
+        });
+    }
+
+    let value = Value::load_or_default(Ident::from("v"), Position { read_at: 0, len: 0 }, storage);
+    assert_eq!((value.read_at, value.len), (11, 7));
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Marker;
+
+#[test]
+fn zero_sized_values_need_no_chunk() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(HeapStorage::new());
+    let mut unit = Value::load_or_default(Ident::from("unit"), (), Rc::clone(&storage));
+    unit.modify(|_| {});
+    let marker = Value::load_or_default(Ident::from("marker"), Marker, Rc::clone(&storage));
+
+    assert_eq!((*unit, *marker), ((), Marker));
+    assert!(!storage.chunk_exists(&Ident::from("unit")));
+    assert!(!storage.chunk_exists(&Ident::from("marker")));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn zero_sized_values_work_on_mmap_storage() {
+    let dir = common::temp_dir("zero_sized_values_work_on_mmap_storage");
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(dir.clone()));
+    let marker = Value::load_or_default(Ident::from("marker"), Marker, storage);
+
+    assert_eq!(*marker, Marker);
+    assert!(!dir.join("marker").exists());
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn protected_values_fault_on_writes() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(MmapStorage::new(common::temp_dir("protected_values_fault_on_writes")));
+    let mut value = storage.value(Ident::from("v"), 5u64);
+    value.protect().unwrap();
+    assert_eq!(*value, 5);
+
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        *value = 6;
+        unsafe { libc::_exit(0) };
+    }
+    let mut status = 0;
+    unsafe { libc::waitpid(pid, &mut status, 0) };
+    assert!(libc::WIFSIGNALED(status), "writing to a protected value should fault");
+    assert!([libc::SIGSEGV, libc::SIGBUS].contains(&libc::WTERMSIG(status)));
+
+    value.unprotect().unwrap();
+    *value = 7;
+    assert_eq!(*value, 7);
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn only_page_aligned_values_can_be_protected() {
+    let anon: Rc<dyn ChunkStorage> = Rc::new(AnonMmapStorage::new());
+    let value = anon.value(Ident::from("w"), 1u32);
+    value.protect().unwrap();
+    value.unprotect().unwrap();
+
+    // the first chunk of a slab takes its start, so the second one isn't page-aligned
+    let bump: Rc<dyn ChunkStorage> = Rc::new(BumpHeapStorage::new(1 << 16));
+    let _first = bump.create_chunk(Ident::from("first"), 16);
+    let unaligned = bump.value(Ident::from("v"), 1u64);
+    assert_eq!(unaligned.protect().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(*unaligned, 1);
+}
+
+#[derive(Debug, PartialEq)]
+#[repr(C)]
+struct Versioned {
+    version: u32,
+    score: u32,
+}
+
+fn migrate_score(old: &[u8]) -> Versioned {
+    assert_eq!(old.len(), 4);
+    Versioned { version: 2, score: u32::from_ne_bytes([old[0], old[1], old[2], old[3]]) }
+}
+
+#[test]
+fn load_migrating_rewrites_values_of_an_old_layout() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    drop(Value::<u32>::load_or_default(Ident::from("v"), 42, Rc::clone(&storage)));
+    {
+        let value = Value::<Versioned>::load_migrating(Ident::from("v"), Rc::clone(&storage), migrate_score);
+        assert_eq!(*value, Versioned { version: 2, score: 42 });
+    }
+    assert_eq!(storage.chunk_len(&Ident::from("v")), Some(8));
+    assert!(!storage.chunk_exists(&Ident::from("v").sub("migrated")));
+
+    let value = Value::<Versioned>::load_migrating(Ident::from("v"), storage, |_| panic!("Already migrated"));
+    assert_eq!(*value, Versioned { version: 2, score: 42 });
+}
+
+#[test]
+fn interrupted_migrations_are_redone_from_the_old_value() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    drop(Value::<u32>::load_or_default(Ident::from("v"), 42, Rc::clone(&storage)));
+    // a staged value that might not have been written completely
+    drop(storage.create_chunk(Ident::from("v").sub("migrated"), 8));
+
+    let value = Value::<Versioned>::load_migrating(Ident::from("v"), Rc::clone(&storage), migrate_score);
+    assert_eq!(*value, Versioned { version: 2, score: 42 });
+    assert!(!storage.chunk_exists(&Ident::from("v").sub("migrated")));
+}
+
+#[test]
+fn interrupted_migrations_are_finished_from_the_staged_value() {
+    let migrated = Versioned { version: 2, score: 42 };
+    let migrated_bytes = unsafe { std::slice::from_raw_parts(&migrated as *const Versioned as *const u8, 8) };
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    storage.create_chunk(Ident::from("v").sub("migrated"), 8).copy_from_slice(migrated_bytes);
+
+    // the old chunk was already forgotten, and the new one may only be partially written
+    for partial in &[false, true] {
+        if *partial {
+            storage.forget_chunk(storage.load_chunk(Ident::from("v")));
+            storage.create_chunk(Ident::from("v").sub("migrated"), 8).copy_from_slice(migrated_bytes);
+            drop(storage.create_chunk(Ident::from("v"), 8));
+        }
+        let value = Value::<Versioned>::load_migrating(Ident::from("v"), Rc::clone(&storage), |_| panic!("Old value is gone"));
+        assert_eq!(*value, migrated);
+        assert!(!storage.chunk_exists(&Ident::from("v").sub("migrated")));
+    }
+}
+
+#[test]
+fn little_endian_values_read_what_big_endian_targets_wrote() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    {
+        let mut value = Value::<u64>::load_or_default(Ident::from("v"), 0, Rc::clone(&storage));
+        value.write_le(0x0102_0304_0506_0708);
+        assert_eq!(value.read_le(), 0x0102_0304_0506_0708);
+    }
+    assert_eq!(&storage.load_chunk(Ident::from("v"))[..], &[8, 7, 6, 5, 4, 3, 2, 1]);
+
+    // a big-endian process stores the byte-swapped value in its native (big-endian) layout
+    let written = 0x1122_3344_5566_7788u64;
+    let big_endian_encoded = written.swap_bytes().to_be_bytes();
+    storage.load_chunk(Ident::from("v")).copy_from_slice(&big_endian_encoded);
+    let value = Value::<u64>::load_or_default(Ident::from("v"), 0, Rc::clone(&storage));
+    assert_eq!(value.read_le(), written);
+    drop(value);
+
+    // while its plain native layout decodes to something else
+    storage.load_chunk(Ident::from("v")).copy_from_slice(&written.to_be_bytes());
+    assert_eq!(Value::<u64>::load_or_default(Ident::from("v"), 0, storage).read_le(), written.swap_bytes());
+}
@@ -0,0 +1,569 @@
+use chunky::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn heap() -> Rc<dyn ChunkStorage> {
+    Rc::new(HeapStorage::new())
+}
+
+fn vector_of(ident: &str, items: std::ops::Range<u64>, storage: &Rc<dyn ChunkStorage>) -> Vector<u64> {
+    let mut vector = Vector::new(Ident::from(ident), 64, Rc::clone(storage));
+    for item in items {
+        vector.push(item);
+    }
+    vector
+}
+
+fn items(vector: &Vector<u64>) -> Vec<u64> {
+    (0..vector.len()).map(|index| *vector.at(index).unwrap()).collect()
+}
+
+#[test]
+fn split_off_across_chunk_boundary() {
+    let storage = heap();
+    let mut vector = vector_of("v", 0..30, &storage);
+    let tail = vector.split_off(5, Ident::from("t"));
+
+    assert_eq!(items(&vector), (0..5).collect::<Vec<_>>());
+    assert_eq!(items(&tail), (5..30).collect::<Vec<_>>());
+}
+
+#[test]
+fn split_off_at_the_ends() {
+    let storage = heap();
+    let mut vector = vector_of("v", 0..3, &storage);
+
+    assert!(vector.split_off(3, Ident::from("t1")).is_empty());
+    assert_eq!(vector.len(), 3);
+
+    let tail = vector.split_off(0, Ident::from("t2"));
+    assert_eq!(items(&tail), vec![0, 1, 2]);
+    assert!(vector.is_empty());
+}
+
+#[test]
+fn append_moves_items_without_double_drops() {
+    let storage = heap();
+    let counted = Rc::new(());
+    let mut vector = Vector::new(Ident::from("v"), 64, Rc::clone(&storage));
+    let mut other = Vector::new(Ident::from("o"), 64, Rc::clone(&storage));
+    for _ in 0..3 {
+        vector.push(Rc::clone(&counted));
+    }
+    for _ in 0..37 {
+        other.push(Rc::clone(&counted));
+    }
+
+    vector.append(&mut other);
+    assert!(other.is_empty());
+    assert_eq!(vector.len(), 40);
+    assert_eq!(Rc::strong_count(&counted), 41);
+
+    drop(other);
+    drop(vector);
+    assert_eq!(Rc::strong_count(&counted), 1);
+}
+
+#[test]
+fn append_copies_runs_between_different_chunk_sizes() {
+    let storage = heap();
+    for &(len, other_len) in &[(0, 0), (0, 5), (3, 37), (8, 16), (13, 1)] {
+        // 8 items per chunk here, 3 in `other`, so the runs straddle chunks on both sides
+        let mut vector = vector_of("v", 0..len, &storage);
+        let mut other = Vector::new(Ident::from("o"), 24, Rc::clone(&storage));
+        for item in len..len + other_len {
+            other.push(item);
+        }
+
+        vector.append(&mut other);
+        assert_eq!(items(&vector), (0..len + other_len).collect::<Vec<_>>());
+        assert!(other.is_empty());
+        vector.forget_all();
+    }
+}
+
+#[test]
+fn map_in_place_across_chunks() {
+    let storage = heap();
+    let mut vector = vector_of("v", 0..30, &storage);
+    vector.map_in_place(|item| item * 2);
+
+    assert_eq!(items(&vector), (0..30).map(|item| item * 2).collect::<Vec<_>>());
+}
+
+#[test]
+fn map_in_place_is_panic_safe() {
+    let storage = heap();
+    let counted = Rc::new(());
+    let mut vector = Vector::new(Ident::from("v"), 64, Rc::clone(&storage));
+    for _ in 0..30 {
+        vector.push(Rc::clone(&counted));
+    }
+
+    let mut mapped = 0;
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        vector.map_in_place(|item| {
+            if mapped == 10 {
+                panic!("failed to map");
+            }
+            mapped += 1;
+            item
+        })
+    }));
+    assert!(result.is_err());
+
+    // the items from the one being mapped onwards are dropped, none of them twice
+    assert_eq!(vector.len(), 10);
+    assert_eq!(Rc::strong_count(&counted), 11);
+}
+
+#[test]
+fn try_as_slice_only_for_a_single_chunk() {
+    let storage = heap();
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 64, storage);
+    assert_eq!(vector.try_as_slice(), Some(&[][..]));
+
+    for item in 0..8 {
+        vector.push(item);
+    }
+    assert_eq!(vector.try_as_slice(), Some(&[0, 1, 2, 3, 4, 5, 6, 7][..]));
+    vector.try_as_mut_slice().unwrap()[0] = 9;
+    assert_eq!(*vector.at(0).unwrap(), 9);
+
+    vector.push(8);
+    assert!(vector.try_as_slice().is_none());
+    assert!(vector.try_as_mut_slice().is_none());
+}
+
+#[test]
+fn clone_to_is_equal_until_mutated() {
+    let storage = heap();
+    let vector = vector_of("v", 0..100, &storage);
+    let mut clone = vector.clone_to(Ident::from("c"), Rc::clone(&storage));
+
+    assert!(vector == clone);
+    assert_eq!(items(&clone), (0..100).collect::<Vec<_>>());
+
+    *clone.at_mut(50).unwrap() = 7;
+    assert!(vector != clone);
+    assert_eq!(*vector.at(50).unwrap(), 50);
+}
+
+#[test]
+fn vectors_of_different_lengths_are_unequal() {
+    let storage = heap();
+    assert!(vector_of("a", 0..10, &storage) != vector_of("b", 0..11, &storage));
+}
+
+#[test]
+fn cursor_traverses_like_at() {
+    let storage = heap();
+    // 40 byte chunks hold 5 items each
+    let mut vector = Vector::new(Ident::from("v"), 40, Rc::clone(&storage));
+    for item in 0..100u64 {
+        vector.push(item);
+    }
+
+    let mut cursor = vector.cursor();
+    for index in 0..100 {
+        assert_eq!(cursor.next(), vector.at(index));
+    }
+    assert!(cursor.next().is_none());
+}
+
+#[test]
+fn cursor_reads_after_seeking() {
+    let storage = heap();
+    let vector = vector_of("v", 0..100, &storage);
+    let mut cursor = vector.cursor();
+
+    cursor.seek(37);
+    assert_eq!(cursor.remaining(), 63);
+    assert_eq!(cursor.next(), Some(&37));
+    assert_eq!(cursor.next(), Some(&38));
+
+    cursor.seek(200);
+    assert_eq!(cursor.remaining(), 0);
+    assert!(cursor.next().is_none());
+}
+
+#[derive(Clone)]
+struct CountsDrops {
+    item: u64,
+    drops: Rc<Cell<usize>>,
+}
+
+impl Drop for CountsDrops {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
+
+/// A vector of `n` items (3 per chunk) which count their drops in the returned counter
+fn counted_vector(ident: &str, n: u64, storage: &Rc<dyn ChunkStorage>) -> (Vector<CountsDrops>, Rc<Cell<usize>>) {
+    let drops = Rc::new(Cell::new(0));
+    let mut vector = Vector::new(Ident::from(ident), 3 * std::mem::size_of::<CountsDrops>(), Rc::clone(storage));
+    for item in 0..n {
+        vector.push(CountsDrops { item, drops: Rc::clone(&drops) });
+    }
+    (vector, drops)
+}
+
+#[test]
+fn forget_all_forgets_chunks_without_dropping_items() {
+    let logging = Rc::new(Logging::new(HeapStorage::new()));
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&logging) as Rc<dyn ChunkStorage>;
+    let (vector, drops) = counted_vector("v", 10, &storage);
+    logging.take_log();
+
+    vector.forget_all();
+    assert_eq!(drops.get(), 0);
+    let forgotten = logging.take_log().into_iter().filter(|event| matches!(event, StorageEvent::Forget { .. })).count();
+    assert_eq!(forgotten, 4 + 1);
+    assert!(!storage.chunk_exists(&Ident::from("v_len")));
+}
+
+#[test]
+fn removing_items_drops_each_once() {
+    let (mut vector, drops) = counted_vector("v", 10, &heap());
+
+    drop(vector.pop());
+    assert_eq!(drops.replace(0), 1);
+    vector.truncate(4);
+    assert_eq!(drops.replace(0), 5);
+    vector.truncate(7);
+    assert_eq!(drops.replace(0), 0);
+    vector.clear();
+    assert_eq!(drops.replace(0), 4);
+    assert!(vector.is_empty());
+    drop(vector);
+    assert_eq!(drops.get(), 0);
+}
+
+#[test]
+fn swap_remove_moves_the_item_out() {
+    let (mut vector, drops) = counted_vector("v", 4, &heap());
+
+    let removed = vector.swap_remove(1);
+    assert_eq!(drops.get(), 0);
+    assert_eq!((removed.item, vector.at(1).unwrap().item), (1, 3));
+    drop(removed);
+    assert_eq!(drops.replace(0), 1);
+    drop(vector);
+    assert_eq!(drops.get(), 3);
+}
+
+#[test]
+fn dropping_a_vector_drops_its_items() {
+    let (vector, drops) = counted_vector("v", 10, &heap());
+    drop(vector);
+    assert_eq!(drops.get(), 10);
+}
+
+#[test]
+fn split_off_and_append_move_items_without_dropping() {
+    let storage = heap();
+    let (mut vector, drops) = counted_vector("v", 7, &storage);
+    let tail = vector.split_off(3, Ident::from("t"));
+    let (mut other, other_drops) = counted_vector("o", 1, &storage);
+    other.append(&mut vector.clone_to(Ident::from("c"), Rc::clone(&storage)));
+    assert_eq!((drops.get(), other_drops.get()), (0, 0));
+
+    drop(tail);
+    assert_eq!(drops.replace(0), 4);
+    drop(other);
+    // the clones count their drops in the same counter as the originals
+    assert_eq!((drops.replace(0), other_drops.get()), (3, 1));
+    drop(vector);
+    assert_eq!(drops.get(), 3);
+}
+
+#[test]
+fn panicking_map_in_place_drops_each_item_once() {
+    let (mut vector, drops) = counted_vector("v", 10, &heap());
+    let counter = Rc::clone(&drops);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        vector.map_in_place(|old| {
+            if old.item == 4 {
+                panic!("mapping failed");
+            }
+            CountsDrops { item: old.item + 100, drops: Rc::clone(&counter) }
+        })
+    }));
+
+    assert!(result.is_err());
+    // the mapped items are kept, the one being mapped and the ones after it are dropped
+    assert_eq!(vector.len(), 4);
+    assert_eq!(drops.replace(0), 4 + 1 + 5);
+    drop(vector);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn rolled_back_transaction_drops_only_its_own_items() {
+    let (mut vector, drops) = counted_vector("v", 5, &heap());
+    {
+        let mut transaction = vector.transaction();
+        transaction.push(CountsDrops { item: 7, drops: Rc::clone(&drops) });
+        transaction.pop();
+        transaction.pop();
+    }
+    drops.set(0);
+    assert_eq!(vector.len(), 5);
+    drop(vector);
+    assert_eq!(drops.get(), 5);
+}
+
+#[test]
+fn fill_overwrites_each_item_across_chunks() {
+    let storage = heap();
+    let mut vector = vector_of("v", 0..30, &storage);
+
+    vector.fill(7);
+    assert_eq!(items(&vector), vec![7; 30]);
+
+    let mut next = 0;
+    vector.fill_with(|| {
+        next += 1;
+        next
+    });
+    assert_eq!(items(&vector), (1..=30).collect::<Vec<_>>());
+}
+
+#[test]
+fn fill_drops_old_items_once() {
+    let (mut vector, drops) = counted_vector("v", 10, &heap());
+    let counter = Rc::clone(&drops);
+
+    vector.fill(CountsDrops { item: 7, drops: Rc::clone(&counter) });
+    // the value itself is moved into the last slot
+    assert_eq!(drops.replace(0), 10);
+    assert!(vector.iter().all(|item| item.item == 7));
+
+    vector.fill_with(|| CountsDrops { item: 8, drops: Rc::clone(&counter) });
+    assert_eq!(drops.replace(0), 10);
+    assert_eq!(vector.len(), 10);
+    drop(vector);
+    assert_eq!(drops.get(), 10);
+}
+
+#[test]
+fn windows_and_chunks_exact_match_slices_across_chunk_edges() {
+    // 12 byte chunks hold 3 items each
+    let mut vector = Vector::new(Ident::from("v"), 12, heap());
+    let flat = (0..23u32).collect::<Vec<_>>();
+    for item in &flat {
+        vector.push(*item);
+    }
+
+    for size in 1..26 {
+        let windows = vector.windows(size).map(|window| window.into_iter().cloned().collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(windows, flat.windows(size).map(<[u32]>::to_vec).collect::<Vec<_>>());
+
+        let chunks = vector.chunks_exact(size).map(|chunk| chunk.into_iter().cloned().collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(chunks, flat.chunks_exact(size).map(<[u32]>::to_vec).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn from_vec_moves_all_items_across_chunks() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Named {
+        id: u64,
+        name: String,
+    }
+
+    let data = (0..10_000).map(|id| Named { id, name: id.to_string() }).collect::<Vec<_>>();
+    let vector = Vector::from_vec(Ident::from("v"), 1024, heap(), data.clone());
+    assert_eq!(vector.len(), 10_000);
+    assert!(vector.iter().eq(data.iter()));
+}
+
+#[test]
+fn from_vec_moves_items_without_double_drops() {
+    let counted = Rc::new(());
+    let data = (0..37).map(|_| Rc::clone(&counted)).collect::<Vec<_>>();
+    let vector = Vector::from_vec(Ident::from("v"), 64, heap(), data);
+    assert_eq!(vector.len(), 37);
+    assert_eq!(Rc::strong_count(&counted), 38);
+
+    drop(vector);
+    assert_eq!(Rc::strong_count(&counted), 1);
+}
+
+#[test]
+fn iter_enumerate_indices_skips_ahead_like_at() {
+    let vector = Vector::from_vec(Ident::from("v"), 64, heap(), (0..1000u64).collect());
+    let mut iter = vector.iter_enumerate_indices();
+    assert_eq!(iter.next(), Some((0, &0)));
+    assert_eq!(iter.nth(500), Some((501, vector.at(501).unwrap())));
+    assert_eq!(iter.next(), Some((502, &502)));
+    assert_eq!(iter.size_hint(), (497, Some(497)));
+    assert_eq!(iter.nth(496), Some((999, &999)));
+    assert_eq!(iter.next(), None);
+
+    assert_eq!(vector.iter_enumerate_indices().nth(2000), None);
+    assert!(vector.iter_enumerate_indices().eq(vector.iter().enumerate()));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_iter_matches_sequential_iteration() {
+    use rayon::prelude::*;
+
+    let vector = Vector::from_vec(Ident::from("v"), 4096, heap(), (0..1_000_000u64).collect());
+    assert_eq!(vector.par_iter().sum::<u64>(), vector.iter().sum::<u64>());
+    assert_eq!((&vector).into_par_iter().filter(|item| item.is_multiple_of(2)).count(), 500_000);
+}
+
+#[test]
+fn introspection_reports_the_adjusted_configuration() {
+    let vector = Vector::<u64>::new(Ident::from("v"), 100, heap());
+    assert_eq!((vector.chunk_size(), vector.item_size(), vector.items_per_chunk()), (100, 8, 12));
+    // chunks are at least as large as one item
+    let vector = Vector::<[u8; 32]>::new(Ident::from("w"), 10, heap());
+    assert_eq!((vector.chunk_size(), vector.item_size(), vector.items_per_chunk()), (32, 32, 1));
+}
+
+#[test]
+fn rotations_match_vec_across_chunk_edges() {
+    let storage = heap();
+    for &len in &[0usize, 1, 5, 7, 23] {
+        for mid in 0..=len {
+            let data = (0..len).map(|item| item.to_string()).collect::<Vec<_>>();
+            let chunk_size = 3 * std::mem::size_of::<String>();
+            let mut vector = Vector::from_vec(Ident::from("v"), chunk_size, Rc::clone(&storage), data.clone());
+            let mut expected = data;
+
+            vector.rotate_left(mid);
+            expected.rotate_left(mid);
+            assert!(vector.iter().eq(expected.iter()));
+            for _ in 0..2 {
+                vector.rotate_right(mid);
+                expected.rotate_right(mid);
+                assert!(vector.iter().eq(expected.iter()));
+            }
+            vector.clear();
+        }
+    }
+}
+
+#[test]
+fn rollback_restores_the_checkpointed_items() {
+    let storage = heap();
+    let expected = (0..6).map(|item| item.to_string()).collect::<Vec<_>>();
+    let mut vector = Vector::from_vec(Ident::from("v"), 4 * std::mem::size_of::<String>(), Rc::clone(&storage), expected.clone());
+    let chunks = storage.list_chunks(&Ident::from("v")).len();
+
+    let checkpoint = vector.checkpoint();
+    for item in 6..20 {
+        vector.push(item.to_string());
+    }
+    vector.rollback(checkpoint);
+    assert!(vector.iter().eq(expected.iter()));
+    assert_eq!(storage.list_chunks(&Ident::from("v")).len(), chunks);
+
+    let checkpoint = vector.checkpoint_from(3);
+    vector.pop();
+    vector.pop();
+    *vector.at_mut(3).unwrap() = "changed".to_owned();
+    vector.push("pushed".to_owned());
+    vector.rollback(checkpoint);
+    assert!(vector.iter().eq(expected.iter()));
+}
+
+#[test]
+fn try_reserve_past_the_quota_leaves_the_vector_unchanged() {
+    // room for the len and four chunks of items
+    let storage: Rc<dyn ChunkStorage> = Rc::new(Quota::new(HeapStorage::new(), 64 * 4 + 8));
+    let mut vector = Vector::<u64>::new(Ident::from("v"), 64, storage);
+    vector.push(1);
+    assert!(vector.try_reserve(8 * 3).is_ok());
+
+    let error = vector.try_reserve(8 * 10).unwrap_err();
+    assert_eq!(error.source.kind(), std::io::ErrorKind::QuotaExceeded);
+    assert_eq!(vector.len(), 1);
+    // chunks created by the failed call were forgotten again, so these still fit
+    for item in 0..24 {
+        vector.push(item);
+    }
+    assert!(vector.try_reserve(usize::MAX).is_err());
+    assert_eq!(vector.len(), 25);
+}
+
+#[test]
+fn into_iter_yields_each_item_in_order_and_forgets_exhausted_chunks() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let (vector, drops) = counted_vector("v", 10, &storage);
+    let mut into_iter = vector.into_iter();
+    for index in 0..10u64 {
+        let item = into_iter.next().unwrap();
+        assert_eq!((item.item, drops.get()), (index, index as usize));
+        if index == 3 {
+            // the first chunk is exhausted
+            assert!(!storage.chunk_exists(&Ident::from("v_0")));
+            assert!(storage.chunk_exists(&Ident::from("v_3")));
+        }
+    }
+    assert!(into_iter.next().is_none());
+    drop(into_iter);
+    assert_eq!(drops.get(), 10);
+    assert!(storage.list_chunks(&Ident::from("v")).is_empty());
+
+    assert_eq!(Vector::<u64>::new(Ident::from("e"), 64, storage).into_iter().count(), 0);
+}
+
+#[test]
+fn dropping_a_partially_consumed_into_iter_drops_the_rest_once() {
+    let storage: Rc<dyn ChunkStorage> = Rc::new(VirtualFsStorage::new());
+    let (vector, drops) = counted_vector("v", 10, &storage);
+    let mut into_iter = vector.into_iter();
+    let taken = (&mut into_iter).take(4).collect::<Vec<_>>();
+    assert_eq!(into_iter.size_hint(), (6, Some(6)));
+    assert_eq!(drops.get(), 0);
+
+    drop(into_iter);
+    assert_eq!(drops.get(), 6);
+    drop(taken);
+    assert_eq!(drops.get(), 10);
+    assert!(storage.list_chunks(&Ident::from("v")).is_empty());
+}
+
+#[test]
+fn slices_are_bounded_views_across_chunk_edges() {
+    // 8 items per chunk
+    let storage = heap();
+    let vector = vector_of("v", 0..40, &storage);
+    let slice = vector.slice(5..13);
+    assert_eq!(slice.len(), 8);
+    assert_eq!(slice.iter().cloned().collect::<Vec<_>>(), (5..13).collect::<Vec<_>>());
+    assert_eq!((slice.at(0), slice.at(7), slice.at(8)), (Some(&5), Some(&12), None));
+
+    let (left, right) = (slice.slice(0..4), slice.slice(4..8));
+    assert_eq!(left.iter().chain(right.iter()).cloned().collect::<Vec<_>>(), (5..13).collect::<Vec<_>>());
+    assert_eq!(right.at(3), Some(&12));
+    assert!(vector.slice(40..40).is_empty());
+    let out_of_bounds = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        vector.slice(30..41);
+    }));
+    assert!(out_of_bounds.is_err());
+}
+
+#[test]
+fn shrink_to_fit_forgets_chunks_past_the_last_item() {
+    let logging = Rc::new(Logging::new(HeapStorage::new()));
+    let storage: Rc<dyn ChunkStorage> = Rc::clone(&logging) as Rc<dyn ChunkStorage>;
+    let mut vector = vector_of("v", 0..80, &storage);
+    vector.truncate(3);
+    // 103 items take 13 chunks of 8 items
+    vector.try_reserve(100).unwrap();
+    logging.take_log();
+
+    vector.shrink_to_fit();
+    let forgotten = logging.take_log().into_iter().filter(|event| matches!(event, StorageEvent::Forget { .. })).count();
+    assert_eq!(forgotten, 12);
+    assert_eq!(items(&vector), vec![0, 1, 2]);
+
+    vector.shrink_to_fit();
+    assert!(logging.take_log().is_empty());
+}
@@ -0,0 +1,58 @@
+use chunky::*;
+use std::rc::Rc;
+
+#[test]
+fn collections_round_trip_through_export_and_import() {
+    let bytes = {
+        let files = Rc::new(VirtualFsStorage::new());
+        let storage: Rc<dyn ChunkStorage> = Rc::clone(&files) as Rc<dyn ChunkStorage>;
+        let mut vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+        for item in 0..100 {
+            vector.push(item);
+        }
+        let mut queue = Queue::new(&Ident::from("q"), 64, storage);
+        unsafe { *(queue.enqueue(8) as *mut u64) = 42 };
+        files.export()
+    };
+
+    let files = VirtualFsStorage::import(&bytes).unwrap();
+    assert_eq!(files.export(), bytes);
+    let storage: Rc<dyn ChunkStorage> = Rc::new(files);
+    let vector = Vector::<u64>::new(Ident::from("v"), 64, Rc::clone(&storage));
+    assert_eq!(vector.iter().cloned().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+    let mut queue = Queue::new(&Ident::from("q"), 64, Rc::clone(&storage));
+    assert_eq!(unsafe { *(queue.dequeue().unwrap() as *const u64) }, 42);
+
+    vector.forget_all();
+    assert!(!storage.chunk_exists(&Ident::from("v_0")));
+}
+
+#[test]
+fn truncated_exports_are_rejected() {
+    let files = VirtualFsStorage::new();
+    files.create_chunk(Ident::from("a"), 10);
+    let bytes = files.export();
+
+    assert!(VirtualFsStorage::import(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn loaded_chunks_stay_valid_when_their_file_is_forgotten() {
+    let files = VirtualFsStorage::new();
+    let mut created = files.create_chunk(Ident::from("a"), 64);
+    created[3] = 7;
+    let loaded = files.load_chunk(Ident::from("a"));
+
+    files.forget_chunk(created);
+    assert!(!files.chunk_exists(&Ident::from("a")));
+    assert_eq!(loaded[3], 7);
+}
+
+#[test]
+fn chunks_are_aligned() {
+    let files = VirtualFsStorage::new();
+    for (index, size) in [1, 3, 17, 100].iter().enumerate() {
+        let chunk = files.create_chunk(Ident::from(format!("a_{}", index).as_str()), *size);
+        assert_eq!(chunk.as_ptr() as usize % 16, 0);
+    }
+}